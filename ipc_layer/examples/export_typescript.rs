@@ -0,0 +1,13 @@
+//! Writes the TypeScript definitions of the IPC contract to the given path (defaults to `ipc.d.ts`).
+//!
+//! ```console
+//! cargo run -p ipc_layer --example export_typescript --features typescript -- admin/src/ipc.d.ts
+//! ```
+fn main() -> std::io::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "ipc.d.ts".to_string());
+    std::fs::write(&path, ipc_layer::typescript::definitions())?;
+    println!("wrote TypeScript definitions to {}", path);
+    Ok(())
+}
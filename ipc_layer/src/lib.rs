@@ -8,34 +8,239 @@
 //! # Modules
 //! - `tauri`: Available when the `tauri` feature is enabled, providing Tauri-specific functionality.
 //! - `barcode_scanner`: Available when both the `ui` and `mobile` features are enabled, enabling barcode scanning capabilities for mobile devices.
+//! - `typescript`: Available when the `typescript` feature is enabled, assembling `.d.ts` definitions of the IPC contract for JS/TS frontends.
 //!
 //! # Structures
 //!
 //! ## `Message`
-//! Represents a message with a timestamp and text content.
+//! Represents a message with a unique id, a timestamp, text content and attachments.
 //!
 //! ### Fields
+//! - `id`: A random (UUID v4) `String` identifying the message, used to deduplicate it wherever it is seen twice.
 //! - `timestamp`: A `DateTime<Utc>` representing when the message was created.
 //! - `text`: A `String` containing the text of the message.
+//! - `attachments`: The `Attachment`s sent along with the message, possibly none.
+//! - `revision`: For edits and deletions, the `Revision` naming the message they change.  `None` for messages
+//!   shown in the conversation.
+//! - `profile`: For profile updates, the `Profile` the author publishes to the room.  `None` for messages shown in
+//!   the conversation.
 //!
 //! ### Methods
 //! - `new(msg: String) -> Self`:
 //!   Constructs a new `Message` with the current timestamp and the given text.
+//! - `with_attachments(msg: String, attachments: Vec<Attachment>) -> Self`:
+//!   Constructs a new `Message` with the current timestamp, the given text and attachments.
+//! - `edit(target: &str, msg: String) -> Self` / `delete(target: &str) -> Self`:
+//!   Constructs a revision replacing the text of the message with id `target`, or deleting it.
+//! - `profile_update(profile: Profile) -> Self`:
+//!   Constructs an update publishing the author's profile to the room.
 //! - `unpack_for_html_integration(self) -> (String, String)`:
 //!   Returns a tuple containing the message text and its timestamp as strings, suitable for integration with HTML or other UIs.
+//! - `id(&self) -> &str`:
+//!   Returns the `Message`'s id.
 //! - `timestamp(&self) -> &DateTime<Utc>`:
 //!   Returns a reference to the `Message`'s timestamp.
+//! - `attachments(&self) -> &[Attachment]`:
+//!   Returns the `Message`'s attachments.
+//! - `revision(&self) -> Option<&Revision>`:
+//!   Returns the message the `Message` revises, if it is an edit or deletion.
+//! - `profile(&self) -> Option<&Profile>`:
+//!   Returns the profile the `Message` publishes, if it is a profile update.
+//!
+//! ## `Revision` / `RevisionKind` / `RevisedMessage`
+//! Edits and deletions are committed to the room's document like any other message, referencing the id of the
+//! message they change.  Only revisions signed by the message's author, or carrying the proof of an admin of the
+//! room, are applied.  The backend folds them into the current state of the message: a deletion leaves a tombstone
+//! without text or attachments and is final, otherwise the edit with the highest clock (ties broken by id) wins,
+//! so every peer settles on the same text however concurrent edits arrived.
+//!
+//! ## `Attachment` / `AttachmentProgress`
+//! A file sent along with a message, referenced by the BLAKE3 hash of its bytes together with its name, mime type
+//! and size.  The bytes are not part of the room's document, they are transferred directly between peers as a
+//! content-addressed blob and verified against the hash.  Peers only serve a blob to peers of a room whose messages
+//! or member profiles reference it.  Transfers report the bytes received so far per room and hash.  Images of up
+//! to `MAX_INLINE_IMAGE_SIZE` are shown inline.
+//!
+//! ## `HybridTimestamp`
+//! The position of a message in its room, assigned by the sender's hybrid logical clock.  Messages are ordered
+//! by it (ties broken by id) instead of wall clock time, so skewed clocks or messages sent within the same
+//! second never reorder or drop messages.
+//!
+//! ## `HistoryCursor`
+//! The clock and id of the oldest message of a page of history, `ChatMessage::cursor` returns it.  Passed to
+//! `load_history` to load the page before it, excluding the message itself.
+//!
+//! ## `ChatMessage`
+//! A `Message` loaded from the local chat history, with `outgoing` set when this node authored it, the node id of
+//! its `author` and its `clock`.  Its revisions are applied already, `edited_at` is set once it was edited and
+//! `deleted` once it was deleted.
+//! `delivery` is the `DeliveryState` of messages this node is still delivering: waiting in the outbox for their room
+//! to become reachable, committed but not acknowledged by a peer yet, or failed.  It is `None` once a peer received
+//! the message, and for messages of other nodes.
+//! `verified` is set when the message carries a valid signature of its author over its id, room, timestamp,
+//! text and attachments.  Unverified messages are shown flagged, they may have been authored by anyone claiming the author's node id.
+//!
+//! ## `DeliveryState` / `MessageDelivery`
+//! The lifecycle of a message sent by this node (pending → committed → synced, or failed), tracked by the backend
+//! per message id and pushed to the frontend so each outgoing message can show its status.  A message is synced
+//! once a peer of the room acknowledges receiving it.  Messages still in the outbox when the app closes are failed
+//! on the next launch, so they can be retried, and flushed again once their room is reachable.
+//!
+//! ## `HistoryBackfill`
+//! Messages of a room that were only received as part of a bundle (a compacted range of the document), sent to the
+//! frontend in one batch so late joiners see the full conversation.
+//!
+//! ## `RoomInfo`
+//! A room this node takes part in: the id of its document and, for rooms created on this node, its title.
+//!
+//! ## `RoomMessage`
+//! Event payload tagged with the room (document id) it belongs to, so the frontend can follow several rooms at once.
+//! Carries the node id of its `author`, which the frontend resolves to a `Profile`, and whether the author's
+//! signature was `verified`.
+//!
+//! ## `Profile` / `PeerProfile`
+//! The display name, avatar image and status text a node publishes to the peers of its rooms, and the profile of a
+//! peer (node id) as received by this node.  Profiles are committed to the documents of the rooms as signed profile
+//! updates, so peers that were offline receive them with the rest of the document.  The most recent profile per
+//! node id is kept, a profile dated in the future counts as updated when it was received.  The avatar is an image
+//! `Attachment`, fetched from the peers of a room like any other.
+//!
+//! ## `ConnectionStatus` / `ConnectionPath`
+//! The state of the connection to a peer (connecting, connected, reconnecting, disconnected) and the network path
+//! it takes (direct, through a relay, or both).
+//!
+//! ## `PeerStatus` / `PeerPath`
+//! Event payloads reporting a `ConnectionStatus` or `ConnectionPath` per peer (node id) of a room.
+//!
+//! ## `Presence` / `Member` / `Roster`
+//! The peers taking part in a room, each with its presence (online, away or offline) and the last time it was seen.
+//! A peer is online while connected and active, and away while connected but idle.
+//!
+//! ## `SignalKind` / `EphemeralSignal`
+//! Short-lived signals (typing indicators, presence pings) exchanged between the peers of a room over a dedicated
+//! channel instead of the room's document, so they are never persisted.  Each one expires at `expires_at`.
+//!
+//! ## `ReadReceipt`
+//! The last message a peer (node id) read in a room.  Markers are exchanged over the same channel as signals,
+//! persisted, and only move forward in clock order, so a late receipt never moves a marker back.
+//!
+//! ## `Role` / `AccessGrant` / `RoomAccess`
+//! Who may access a room, as delegated through keyhive: every keyhive identity (member id) granted access to the
+//! room's document with its role.  Admins manage the room's members, writers add messages and readers only read.
+//!
+//! ## `TicketPreview`
+//! What a ticket invites into (room title, inviting node, how to reach it and until when), so users can check a
+//! ticket before joining through it.
+//!
+//! ## `InviteCodes`
+//! The forms a ticket can be shared in besides its serialized string: a compact, checksummed invite code that
+//! also fits in a QR code, a word code for copying it by hand and a `beelay-chat://join` link.  Every command taking
+//! a ticket accepts any of them.
+//!
+//! ## `Invite` / `InviteRejection`
+//! An invite into a room issued by this node, which may expire, allow a limited number of uses or be bound to a
+//! single invitee (node id), and be revoked.  Invite tickets only point to the issuing node, which grants access
+//! to peers redeeming an invite within those constraints and only then tells them the room, and tells peers it
+//! refused why.
 //!
 //! ## `API`
 //! A trait that defines asynchronous methods for working with tickets and broadcasting messages.
+//! Breaking changes to a method are introduced with `#[ipc_macros::ipc(since = "...")]`, which versions the
+//! invoked command name, while the backend keeps serving the previous version through an `#[ipc(compat)]` shim
+//! in `impl_trait!` until older frontends are gone.  `#[ipc(deprecated = "...")]` flags bindings that are going away.
 //!
 //! ### Methods
-//! - `async fn get_serialized_ticket() -> Result<String, String>`:
-//!   Retrieves a serialized ticket as a `String`. Returns an error message in case of failure.
+//! - `async fn get_serialized_ticket(room: Option<String>) -> Result<String, String>`:
+//!   Retrieves a serialized ticket as a `String`, inviting into the given room or into a new one when `None`.
+//!   Returns an error message in case of failure.  Deprecated in favour of `create_room` and `create_invite`.
+//! - `async fn create_room(title: String) -> Result<String, String>`:
+//!   Creates a new room (document) and returns the serialized ticket of a single use invite into it, valid for a
+//!   day.  The title is kept locally.
+//! - `async fn create_invite(room: String, expires_at: Option<DateTime<Utc>>, max_uses: Option<u32>, invitee: Option<String>) -> Result<String, String>`:
+//!   Issues an invite into an existing room and returns its serialized ticket.  `None` lifts the respective limit.
+//! - `async fn list_invites(room: String) -> Result<Vec<Invite>, String>`:
+//!   Lists the invites issued for the room, oldest first.
+//! - `async fn revoke_invite(id: String) -> Result<(), String>`:
+//!   Revokes an invite, peers that already joined through it keep their access.
+//! - `async fn list_rooms() -> Result<Vec<RoomInfo>, String>`:
+//!   Lists the rooms this node takes part in.
+//! - `async fn leave_room(room: String) -> Result<(), String>`:
+//!   Leaves a room: tells its peers, stops syncing it and deletes its document, local history, outbox and invites.
+//!   Every step is attempted, a failure lists all the steps that failed.  The room can only be joined again
+//!   through a new invite.
+//! - `async fn invite_codes(ticket: String) -> Result<InviteCodes, String>`:
+//!   Encodes a serialized ticket into its invite code, word code and link.
+//! - `async fn inspect_ticket(ticket: String) -> Result<TicketPreview, String>`:
+//!   Validates the format and version of a serialized ticket without dialing, and returns what it invites into.
+//!   Fails with a readable reason for malformed, unsupported or expired tickets.
 //! - `async fn connect_via_serialized_ticket(ticket: String) -> Result<String, String>`:
-//!   Connects using the provided `ticket`. Returns a success message if the connection succeeds, or an error message otherwise.
-//! - `async fn broadcast_message(message: Message) -> Result<(), String>`:
-//!   Broadcasts the provided `Message`. Returns `Ok(())` on success or an error message on failure.
+//!   Redeems the invite of the provided `ticket` with the node that issued it, which lets this node join the room
+//!   once it accepted the invite, or emits `"invite_rejected"`.  Returns a message once the invite is sent, or an
+//!   error message otherwise.  Tickets predating version 3 of invite tickets are refused.
+//! - `async fn broadcast_message(room: String, message: Message) -> Result<(), String>`:
+//!   Broadcasts the provided `Message` to the room, signed with this node's key. Returns `Ok(())` on success or an
+//!   error message on failure.
+//!   Progress is reported through `"delivery"` events.  Messages sent before the room's document is discovered or
+//!   while no peer is connected are kept pending in a persistent outbox and flushed in order once the room is reachable.
+//! - `async fn retry_message(id: String) -> Result<(), String>`:
+//!   Sends a message whose delivery failed again.
+//! - `async fn edit_message(room: String, id: String, text: String) -> Result<(), String>`:
+//!   Replaces the text of a message this node sent, or of any message when this node is an admin of the room.
+//!   The edit is sent like a message and applied once it is committed, through a `"message_revised"` event.
+//! - `async fn delete_message(room: String, id: String) -> Result<(), String>`:
+//!   Deletes a message like `edit_message` edits it, leaving a tombstone in the conversation.
+//! - `async fn add_attachment(path: String) -> Result<Attachment, String>`:
+//!   Stores the file at `path` as a blob and returns the attachment to send it with, files are limited to 64 MiB.
+//! - `async fn fetch_attachment(room: String, attachment: Attachment) -> Result<(), String>`:
+//!   Downloads an attachment from the peers of the room, reporting progress through `"attachment_progress"`
+//!   events.  Does nothing when the attachment is stored already.
+//! - `async fn attachment_data_url(attachment: Attachment) -> Result<String, String>`:
+//!   Returns a downloaded image attachment of up to 4 MiB as a `data:` URL, for showing it inline.
+//! - `async fn reveal_attachment(attachment: Attachment) -> Result<(), String>`:
+//!   Reveals a downloaded attachment in its folder, leaving it to the user to open a file a peer named.
+//! - `async fn save_attachment(attachment: Attachment, path: String) -> Result<(), String>`:
+//!   Copies a downloaded attachment to `path`.
+//! - `async fn send_signal(room: String, kind: SignalKind) -> Result<(), String>`:
+//!   Sends an ephemeral signal to the peers of the room, best effort.  The backend sets its expiry based on its kind.
+//! - `async fn mark_read(room: String, message_id: String) -> Result<(), String>`:
+//!   Marks everything up to the message as read by this node and lets the peers of the room know.
+//! - `async fn list_receipts(room: String) -> Result<Vec<ReadReceipt>, String>`:
+//!   Lists the read markers of the peers of the room, excluding this node.
+//! - `async fn get_profile() -> Result<Profile, String>`:
+//!   Returns the profile of this node, an empty one until it is set.
+//! - `async fn set_profile(display_name: String, avatar: Option<Attachment>, status: String) -> Result<(), String>`:
+//!   Updates the profile of this node and publishes it to the peers of all its rooms.  The display name is limited to
+//!   64 characters and the status to 140, the avatar must be an image added through `add_attachment`.
+//! - `async fn list_profiles() -> Result<Vec<PeerProfile>, String>`:
+//!   Lists the profiles received from peers.
+//! - `async fn list_members(room: String) -> Result<Vec<Member>, String>`:
+//!   Lists the peers taking part in the room, excluding this node, with their presence.
+//! - `async fn get_contact_card() -> Result<String, String>`:
+//!   Returns the serialized keyhive contact card of this node, which others add it to their rooms with.
+//! - `async fn list_access(room: String) -> Result<Vec<AccessGrant>, String>`:
+//!   Lists the keyhive identities with access to the room and their roles.
+//! - `async fn add_member(room: String, contact_card: String, role: Role) -> Result<(), String>`:
+//!   Grants the identity of a serialized contact card access to the room with the given role.
+//! - `async fn set_member_role(room: String, member: String, role: Role) -> Result<(), String>`:
+//!   Changes the role of a member added through `add_member` on this node, keyhive needs the contact card of a
+//!   member to change its access and only the cards added here are known.  Should delegating the new role fail,
+//!   the previous one is restored.
+//! - `async fn remove_member(room: String, member: String) -> Result<(), String>`:
+//!   Revokes the access of a member added through `add_member` on this node, for the same reason.
+//! - `async fn load_history(room: String, before: Option<HistoryCursor>, limit: u32) -> Result<Vec<ChatMessage>, String>`:
+//!   Loads up to `limit` persisted messages of the room (document id) ordered before the `before` cursor, oldest
+//!   first.  The newest page (`before` is `None`) also includes the messages still queued in the outbox.
+//!   Used to populate the chat when it opens, and to page further back in time.
+//! - `async fn get_node_fingerprint() -> Result<String, String>`:
+//!   Returns a short fingerprint of this node's id so users can compare identities out of band.
+//! - `async fn set_identity_passphrase(passphrase: Option<String>) -> Result<(), String>`:
+//!   Encrypts the stored identity with the passphrase, or stores it unencrypted when `None`.
+//! - `async fn rotate_identity(passphrase: Option<String>) -> Result<(), String>`:
+//!   Replaces the node identity with a newly generated one and restarts the app.  Previously shared tickets stop working.
+//! - `async fn is_identity_locked() -> Result<bool, String>`:
+//!   Whether the stored identity is passphrase protected and still waiting for `unlock_identity` before the node can start.
+//! - `async fn unlock_identity(passphrase: String) -> Result<(), String>`:
+//!   Decrypts the stored identity and starts the node with it.  Returns an error for a wrong passphrase.
 //!
 //! ## `barcode_scanner`
 //! Provides functionality for scanning barcodes on mobile devices. Available when the `ui` and `mobile` features are enabled.
@@ -61,35 +266,101 @@
 //! Defines IPC events with conditional compilation for the `ui` and `tauri` features.
 //!
 //! ### Events
-//! - `"conversation"`: Associated with the `Message` type.
-//! - `"connection"`: Associated with the `String` type.
-//! - `"connection_type"`: Associated with the `String` type.
+//! - `"conversation"`: Associated with the `RoomMessage` type.
+//! - `"connection"`: Associated with the `PeerStatus` type, emitted whenever the connection to a peer of a room changes.
+//! - `"connection_type"`: Associated with the `PeerPath` type, emitted whenever the path to a peer of a room changes.
+//! - `"history_backfill"`: Associated with the `HistoryBackfill` type, messages recovered from a compacted bundle.
+//! - `"delivery"`: Associated with the `MessageDelivery` type, emitted whenever a sent message changes `DeliveryState`.
+//! - `"roster_changed"`: Associated with the `Roster` type, emitted whenever a peer joins a room or its presence changes.
+//! - `"signal"`: Associated with the `EphemeralSignal` type, emitted for every unexpired signal a peer of a room sends.
+//! - `"receipt"`: Associated with the `ReadReceipt` type, emitted whenever the read marker of a peer moves.
+//! - `"profile_changed"`: Associated with the `PeerProfile` type, emitted whenever a peer publishes a newer profile.
+//! - `"membership_changed"`: Associated with the `RoomAccess` type, emitted whenever access to a room is granted,
+//!   changed or revoked.
+//! - `"invite_rejected"`: Associated with the `InviteRejection` type, emitted when the node that issued the invite
+//!   this node joined a room through refused it.
+//! - `"attachment_progress"`: Associated with the `AttachmentProgress` type, emitted while an attachment is downloaded.
+//! - `"message_revised"`: Associated with the `RevisedMessage` type, emitted whenever an edit or deletion of a
+//!   message is applied.
 //!
 //! # Feature Flags
 //! - `tauri`: Enables the `tauri` module.
 //! - `ui`: Enables user interface-related functionality, including event bindings and barcode scanning.
 //! - `mobile`: Enables the `barcode_scanner` module for mobile devices.
+//! - `typescript`: Enables the `typescript` module (implies `ui`), see the `export_typescript` example to write the definitions to disk.
 #[cfg(feature = "tauri")]
 pub mod tauri;
+#[cfg(feature = "typescript")]
+pub mod typescript;
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+#[ipc_macros::ts_interface]
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Message {
+    id: String,
     timestamp: DateTime<Utc>,
     text: String,
+    // defaulted for frontends predating attachments
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    // defaulted for frontends predating revisions
+    #[serde(default)]
+    revision: Option<Revision>,
+    // defaulted for frontends predating profile updates
+    #[serde(default)]
+    profile: Option<Profile>,
 }
 
 impl Message {
     pub fn new(msg: String) -> Self {
+        Self::with_attachments(msg, Vec::new())
+    }
+
+    pub fn with_attachments(msg: String, attachments: Vec<Attachment>) -> Self {
         let timestamp = Utc::now();
         Self {
+            id: uuid::Uuid::new_v4().to_string(),
             timestamp,
             text: msg,
+            attachments,
+            revision: None,
+            profile: None,
+        }
+    }
+
+    pub fn edit(target: &str, msg: String) -> Self {
+        Self {
+            revision: Some(Revision {
+                target: target.to_string(),
+                kind: RevisionKind::Edit,
+            }),
+            ..Self::new(msg)
+        }
+    }
+
+    pub fn delete(target: &str) -> Self {
+        Self {
+            revision: Some(Revision {
+                target: target.to_string(),
+                kind: RevisionKind::Delete,
+            }),
+            ..Self::new(String::new())
+        }
+    }
+
+    pub fn profile_update(profile: Profile) -> Self {
+        Self {
+            profile: Some(profile),
+            ..Self::new(String::new())
         }
     }
 
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     pub fn unpack_for_html_integration(self) -> (String, String) {
         (self.text, self.timestamp.to_string())
     }
@@ -97,14 +368,460 @@ impl Message {
     pub fn timestamp(&self) -> &DateTime<Utc> {
         &self.timestamp
     }
+
+    pub fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+
+    pub fn revision(&self) -> Option<&Revision> {
+        self.revision.as_ref()
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+}
+
+/// What a revision does to the message it references.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionKind {
+    /// Replaces the text of the message with the text of the revision.
+    Edit,
+    /// Deletes the message, leaving a tombstone.
+    Delete,
+}
+
+/// The message an edit or deletion applies to.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Revision {
+    /// Id of the revised message.
+    pub target: String,
+    pub kind: RevisionKind,
+}
+
+/// The current state of a message after one of its revisions was applied.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevisedMessage {
+    pub room: String,
+    pub id: String,
+    /// The text of the winning edit, empty once the message was deleted.
+    pub text: String,
+    /// When the winning edit was made, `None` while the message was not edited.
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted: bool,
+}
+
+/// Largest image attachment shown inline and accepted as an avatar, larger ones are only revealed or saved.
+pub const MAX_INLINE_IMAGE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A file sent along with a message, its bytes are transferred between peers as a blob named by its hash.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Hex encoded BLAKE3 hash of the file's bytes.
+    pub hash: String,
+    pub name: String,
+    pub mime: String,
+    /// Size in bytes.
+    pub size: u64,
+}
+
+/// Bytes of an attachment received so far.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentProgress {
+    pub room: String,
+    pub hash: String,
+    pub received: u64,
+    pub size: u64,
+}
+
+/// Position of a message in a room: the sender's wall clock in milliseconds, plus a counter ordering
+/// messages within the same millisecond.  Assigned by the sending node's hybrid logical clock, which never
+/// runs behind anything it has observed, so replies always order after the messages they answer.
+#[ipc_macros::ts_interface]
+#[derive(
+    Debug, Clone, Copy, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct HybridTimestamp {
+    pub millis: i64,
+    pub counter: u32,
+}
+
+impl HybridTimestamp {
+    /// The next timestamp after this one at the given wall clock time.
+    pub fn successor(&self, millis: i64) -> Self {
+        if millis > self.millis {
+            return Self { millis, counter: 0 };
+        }
+        match self.counter.checked_add(1) {
+            Some(counter) => Self {
+                millis: self.millis,
+                counter,
+            },
+            // the counter ran out within this millisecond, continue in the next one
+            None => Self {
+                millis: self.millis.saturating_add(1),
+                counter: 0,
+            },
+        }
+    }
+}
+
+/// Where a page of history ends: the clock and id of the oldest message of the previous page.  Pages stop just
+/// before it, so messages sharing a clock millisecond with it are never skipped.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryCursor {
+    pub clock: HybridTimestamp,
+    pub id: String,
+}
+
+/// A message loaded from the local chat history, flagged with whether this node authored it.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub message: Message,
+    pub outgoing: bool,
+    pub author: String,
+    pub clock: HybridTimestamp,
+    pub delivery: Option<DeliveryState>,
+    pub verified: bool,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted: bool,
+}
+
+impl ChatMessage {
+    /// The cursor to load the page of history before this message with.
+    pub fn cursor(&self) -> HistoryCursor {
+        HistoryCursor {
+            clock: self.clock,
+            id: self.message.id().to_string(),
+        }
+    }
+}
+
+/// Lifecycle of a message sent by this node.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    /// Handed to the backend, not committed to the document yet.
+    Pending,
+    /// Committed to the document, not acknowledged by a peer yet.
+    Committed,
+    /// Acknowledged by a peer of the room that received it.
+    Synced,
+    /// Could not be committed or pushed, can be retried with `retry_message`.
+    Failed { reason: String },
+}
+
+/// A change in the delivery state of a message sent by this node, with the clock the backend assigned to it.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageDelivery {
+    pub room: String,
+    pub id: String,
+    pub state: DeliveryState,
+    pub clock: HybridTimestamp,
+}
+
+/// Messages of a room recovered from a bundle a peer compacted, ordered by clock.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryBackfill {
+    pub room: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// A message received in a room, identified by the id of its document.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomMessage {
+    pub room: String,
+    pub message: Message,
+    pub author: String,
+    pub clock: HybridTimestamp,
+    pub verified: bool,
+}
+
+/// What a node shows about itself to its peers.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Profile {
+    pub display_name: String,
+    /// The avatar image, stored as a blob like attachments, if one is set.
+    pub avatar: Option<Attachment>,
+    pub status: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The profile of a peer (node id).
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerProfile {
+    pub peer: String,
+    pub profile: Profile,
+}
+
+/// A room this node takes part in, identified by the id of its document.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub id: String,
+    pub title: Option<String>,
+}
+
+/// State of the connection to a peer.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    /// Dialing the peer from a ticket, the room's document is not discovered yet.
+    Connecting,
+    /// Connected to the peer and syncing the room's document.
+    Connected,
+    /// The connection dropped and is being established again.
+    Reconnecting,
+    /// No connection to the peer.
+    Disconnected,
+}
+
+/// Network path of the connection to a peer.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionPath {
+    /// Straight to the peer's address.
+    Direct,
+    /// Through a relay server.
+    Relay,
+    /// Through a relay while a direct path is being established.
+    Mixed,
+}
+
+/// The connection status of a peer (node id) of a room.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerStatus {
+    pub room: String,
+    pub peer: String,
+    pub status: ConnectionStatus,
+}
+
+/// The connection path to a peer (node id) of a room.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerPath {
+    pub room: String,
+    pub peer: String,
+    pub path: ConnectionPath,
+}
+
+/// Presence of a peer.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Presence {
+    /// Connected and active recently.
+    Online,
+    /// Connected but idle.
+    Away,
+    /// Not connected.
+    Offline,
+}
+
+/// A peer (node id) taking part in a room.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    pub id: String,
+    pub presence: Presence,
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// The members of a room, ordered by id.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Roster {
+    pub room: String,
+    pub members: Vec<Member>,
+}
+
+/// Kind of an ephemeral signal.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalKind {
+    /// The peer is typing in the room.
+    Typing,
+    /// The peer cleared or sent what it was typing.
+    StoppedTyping,
+    /// The peer has the room open.
+    Ping,
+}
+
+/// An ephemeral signal a peer (node id) sent in a room, relevant until `expires_at`.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EphemeralSignal {
+    pub room: String,
+    pub peer: String,
+    pub kind: SignalKind,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The last message a peer (node id) read in a room.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadReceipt {
+    pub room: String,
+    pub peer: String,
+    pub message_id: String,
+}
+
+/// Access to a room granted to a keyhive identity.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Reads and writes, and manages the members of the room.
+    Admin,
+    /// Reads and adds messages.
+    Writer,
+    /// Only reads.
+    Reader,
+}
+
+/// A keyhive identity (member id) with access to a room, `this_node` is set for the identity of this node.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessGrant {
+    pub member: String,
+    pub role: Role,
+    pub this_node: bool,
+}
+
+/// The identities with access to a room, ordered by member id.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomAccess {
+    pub room: String,
+    pub grants: Vec<AccessGrant>,
+}
+
+/// An invite into a room issued by this node.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invite {
+    pub id: String,
+    pub room: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_uses: Option<u32>,
+    /// Node id of the only peer that may redeem the invite.
+    pub invitee: Option<String>,
+    /// Node ids of the peers that joined through the invite.
+    pub redeemed_by: Vec<String>,
+    pub revoked: bool,
+}
+
+/// What a ticket invites into, shown before redeeming it with the inviting node.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TicketPreview {
+    pub title: Option<String>,
+    /// Node id of the inviting node.
+    pub inviter: String,
+    pub inviter_fingerprint: String,
+    /// Relay server the inviting node is reachable through, if any.
+    pub relay: Option<String>,
+    /// Addresses the inviting node is directly reachable at.
+    pub direct_addresses: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// The compact forms of a ticket for sharing it outside the app.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InviteCodes {
+    /// Compressed and checksummed base32 code, also used for QR codes.
+    pub code: String,
+    /// Dash separated words spelling the same bytes as the code, one word per byte, for copying it by hand.
+    pub words: String,
+    /// `beelay-chat://join?code=...` link.
+    pub link: String,
+}
+
+/// Why the node that issued an invite refused to let this node join a room through it.
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InviteRejection {
+    /// Id of the refused invite, the room it invites into is only revealed once an invite is accepted.
+    pub invite_id: String,
+    pub reason: String,
 }
 
 #[cfg_attr(feature = "ui", ipc_macros::invoke_bindings)]
 #[allow(async_fn_in_trait)]
 pub trait API {
-    async fn get_serialized_ticket() -> Result<String, String>;
+    #[ipc_macros::ipc(deprecated = "use `create_room` or `create_invite` instead")]
+    async fn get_serialized_ticket(room: Option<String>) -> Result<String, String>;
+    async fn create_room(title: String) -> Result<String, String>;
+    #[ipc_macros::ipc(since = "2")]
+    async fn create_invite(
+        room: String,
+        expires_at: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+        invitee: Option<String>,
+    ) -> Result<String, String>;
+    async fn list_invites(room: String) -> Result<Vec<Invite>, String>;
+    async fn revoke_invite(id: String) -> Result<(), String>;
+    async fn list_rooms() -> Result<Vec<RoomInfo>, String>;
+    async fn leave_room(room: String) -> Result<(), String>;
+    async fn invite_codes(ticket: String) -> Result<InviteCodes, String>;
+    async fn inspect_ticket(ticket: String) -> Result<TicketPreview, String>;
     async fn connect_via_serialized_ticket(ticket: String) -> Result<String, String>;
-    async fn broadcast_message(message: Message) -> Result<(), String>;
+    #[ipc_macros::ipc(since = "2")]
+    async fn broadcast_message(room: String, message: Message) -> Result<(), String>;
+    async fn retry_message(id: String) -> Result<(), String>;
+    async fn edit_message(room: String, id: String, text: String) -> Result<(), String>;
+    async fn delete_message(room: String, id: String) -> Result<(), String>;
+    async fn add_attachment(path: String) -> Result<Attachment, String>;
+    async fn fetch_attachment(room: String, attachment: Attachment) -> Result<(), String>;
+    async fn attachment_data_url(attachment: Attachment) -> Result<String, String>;
+    async fn reveal_attachment(attachment: Attachment) -> Result<(), String>;
+    async fn save_attachment(attachment: Attachment, path: String) -> Result<(), String>;
+    async fn send_signal(room: String, kind: SignalKind) -> Result<(), String>;
+    async fn mark_read(room: String, message_id: String) -> Result<(), String>;
+    async fn list_receipts(room: String) -> Result<Vec<ReadReceipt>, String>;
+    async fn get_profile() -> Result<Profile, String>;
+    async fn set_profile(
+        display_name: String,
+        avatar: Option<Attachment>,
+        status: String,
+    ) -> Result<(), String>;
+    async fn list_profiles() -> Result<Vec<PeerProfile>, String>;
+    async fn list_members(room: String) -> Result<Vec<Member>, String>;
+    async fn get_contact_card() -> Result<String, String>;
+    async fn list_access(room: String) -> Result<Vec<AccessGrant>, String>;
+    async fn add_member(room: String, contact_card: String, role: Role) -> Result<(), String>;
+    async fn set_member_role(room: String, member: String, role: Role) -> Result<(), String>;
+    async fn remove_member(room: String, member: String) -> Result<(), String>;
+    async fn load_history(
+        room: String,
+        before: Option<HistoryCursor>,
+        limit: u32,
+    ) -> Result<Vec<ChatMessage>, String>;
+    async fn get_node_fingerprint() -> Result<String, String>;
+    async fn set_identity_passphrase(passphrase: Option<String>) -> Result<(), String>;
+    async fn rotate_identity(passphrase: Option<String>) -> Result<(), String>;
+    async fn is_identity_locked() -> Result<bool, String>;
+    async fn unlock_identity(passphrase: String) -> Result<(), String>;
 }
 
 // todo: use command generation tools to create this API eventually.  We can't apply type safety through and through because the tauri functionality is internal to the bardecode scanner plugin.
@@ -175,8 +892,21 @@ ipc_macros::derive_events! (
     ui=#[cfg(feature = "ui")],
     tauri=#[cfg(feature = "tauri")],
     {
-        ("conversation", Message),
-        ("connection", String),
-        ("connection_type", String),
+        #[ipc(since = "2")]
+        ("conversation", RoomMessage),
+        #[ipc(since = "2")]
+        ("connection", PeerStatus),
+        #[ipc(since = "3")]
+        ("connection_type", PeerPath),
+        ("history_backfill", HistoryBackfill),
+        ("delivery", MessageDelivery),
+        ("roster_changed", Roster),
+        ("signal", EphemeralSignal),
+        ("receipt", ReadReceipt),
+        ("profile_changed", PeerProfile),
+        ("membership_changed", RoomAccess),
+        ("invite_rejected", InviteRejection),
+        ("attachment_progress", AttachmentProgress),
+        ("message_revised", RevisedMessage),
     }
 );
@@ -1,76 +1,1280 @@
-use crate::{API, Message};
-use beelay_protocol::{DocumentId, IrohBeelayProtocol, NodeId, NodeTicket, Router, Ticket};
-use chrono::{DateTime, Utc};
-use once_cell::sync::OnceCell;
+pub mod access;
+pub mod blobs;
+pub mod clock;
+pub mod codes;
+pub mod delivery;
+pub mod ephemeral;
+pub mod history;
+pub mod identity;
+pub mod invites;
+pub mod outbox;
+pub mod presence;
+pub mod profiles;
+pub mod receipts;
+pub mod redial;
+pub mod revisions;
+pub mod rooms;
+pub mod storage;
+
+use crate::{
+    API, AccessGrant, Attachment, AttachmentProgress, ChatMessage, ConnectionStatus, DeliveryState,
+    HistoryCursor, HybridTimestamp, Invite, InviteCodes, MAX_INLINE_IMAGE_SIZE, Member, Message,
+    MessageDelivery, PeerProfile, PeerStatus, Presence, Profile, ReadReceipt, RevisedMessage, Role,
+    RoomAccess, RoomInfo, Roster, SignalKind, TicketPreview, events,
+};
+use access::{ContactBook, member_access};
+use beelay_protocol::{
+    BeelayTicket, ContactCard, DocumentId, IrohBeelayProtocol, KeyhiveEntityId, NodeId, NodeTicket,
+    Router, SecretKey, Ticket,
+};
+use blobs::BlobStore;
+use chrono::{DateTime, TimeDelta, Utc};
+use clock::HybridClock;
+use delivery::DeliveryTracker;
+use ed25519_dalek::{Signature, Signer};
+use history::ChatHistory;
+use identity::{IdentityKeys, IdentityStore, IdentityUnlock};
+use invites::{InviteStore, InviteTicket, PendingInvite, PendingInvites, Redemption, parse_ticket};
+use outbox::Outbox;
+use presence::PresenceTracker;
+use profiles::{MAX_DISPLAY_NAME_LEN, MAX_STATUS_LEN, ProfileStore};
+use receipts::ReceiptStore;
+use redial::{KnownPeers, RedialTracker, parse_beelay_ticket};
+use rooms::{RoomRegistry, parse_room};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::DiskStorage;
+use tauri_plugin_opener::OpenerExt;
 
 pub struct AppData {
     router: Router,
     pub beelay_protocol: IrohBeelayProtocol,
-    // todo: refactor these OnceCells onace document id and node ticket setting is cleaned up
-    pub document_id: OnceCell<DocumentId>,
-    pub node_ticket: OnceCell<NodeTicket>,
+    pub history: ChatHistory,
+    identity: IdentityKeys,
+    identity_store: IdentityStore,
+    pub rooms: RoomRegistry,
+    pub clock: HybridClock,
+    pub deliveries: DeliveryTracker,
+    pub outbox: Outbox,
+    pub presence: PresenceTracker,
+    pub receipts: ReceiptStore,
+    pub profiles: ProfileStore,
+    pub contacts: ContactBook,
+    pub invites: InviteStore,
+    pub pending_invites: PendingInvites,
+    pub known_peers: KnownPeers,
+    pub redials: RedialTracker,
+    /// Shared with the protocol handler serving blobs to peers.
+    pub blobs: Arc<BlobStore>,
+    /// Shared with beelay, which stores the room documents in it.
+    storage: Arc<DiskStorage>,
+    // envelopes and blob requests reuse one connection per peer
+    connections: ephemeral::PeerConnections,
+    // flushes of the outbox run one at a time so queued messages are committed in order
+    flush_lock: tauri::async_runtime::Mutex<()>,
 }
 
 impl AppData {
-    pub fn new(router: Router, beelay_protocol: IrohBeelayProtocol) -> Self {
+    pub fn new(
+        router: Router,
+        beelay_protocol: IrohBeelayProtocol,
+        history: ChatHistory,
+        identity: IdentityKeys,
+        identity_store: IdentityStore,
+        rooms: RoomRegistry,
+        outbox: Outbox,
+        receipts: ReceiptStore,
+        profiles: ProfileStore,
+        contacts: ContactBook,
+        invites: InviteStore,
+        known_peers: KnownPeers,
+        blobs: Arc<BlobStore>,
+        storage: Arc<DiskStorage>,
+    ) -> Self {
+        let connections = ephemeral::PeerConnections::new(router.endpoint().clone());
         Self {
             router,
             beelay_protocol,
-            document_id: OnceCell::new(),
-            node_ticket: OnceCell::new(),
+            history,
+            identity,
+            identity_store,
+            rooms,
+            clock: HybridClock::new(),
+            deliveries: DeliveryTracker::new(),
+            outbox,
+            presence: PresenceTracker::new(),
+            receipts,
+            profiles,
+            contacts,
+            invites,
+            pending_invites: PendingInvites::new(),
+            known_peers,
+            redials: RedialTracker::new(),
+            blobs,
+            storage,
+            connections,
+            flush_lock: Default::default(),
+        }
+    }
+
+    /// Runs a message received in a room through the shared pipeline: advances the clock, records the author as
+    /// a member of the room and persists the message.  Returns `false` for messages seen before and messages
+    /// claiming a clock too far ahead of ours, which are rejected as they would stay at the end of the history
+    /// for good.  Messages failing signature verification are persisted (and shown flagged) until a verified copy
+    /// replaces them, but otherwise ignored, their claimed author may not have sent them.
+    pub fn ingest(&self, room: &DocumentId, message: &MessageWithMetaData) -> Result<bool, String> {
+        if self.clock.is_too_far_ahead(message.clock) {
+            eprintln!(
+                "Rejecting {} from {}, its clock is too far ahead",
+                message.message.id(),
+                message.peer_id
+            );
+            return Ok(false);
+        }
+        if message.verify(room) {
+            self.clock.observe(message.clock)?;
+            self.rooms.add_peer(room, message.peer_id)?;
+            self.presence.seen(message.peer_id, *message.timestamp())?;
+        }
+        self.history.record(room, message)
+    }
+
+    /// The peers taking part in a room with their presence, excluding this node.
+    pub fn members(&self, room: &DocumentId) -> Result<Vec<Member>, String> {
+        let this_node_id = self.beelay_protocol.node_id();
+        let mut members = self
+            .rooms
+            .members(room)?
+            .into_iter()
+            .filter(|peer| peer != &this_node_id)
+            .map(|peer| {
+                let (presence, last_seen) = self.presence.presence(&peer)?;
+                Ok(Member {
+                    id: peer.to_string(),
+                    presence,
+                    last_seen,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        members.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(members)
+    }
+
+    /// Whether a peer may fetch a blob: it has to be a member of a room referencing the blob, through an
+    /// attachment of its messages or as the avatar of the profile of one of its members.
+    pub fn may_fetch(&self, peer: &NodeId, hash: &str) -> Result<bool, String> {
+        let this_node_id = self.beelay_protocol.node_id().to_string();
+        let avatar_owners = self
+            .profiles
+            .all()?
+            .into_iter()
+            .filter(|(_, profile)| {
+                profile
+                    .avatar
+                    .as_ref()
+                    .is_some_and(|avatar| avatar.hash == hash)
+            })
+            .map(|(owner, _)| owner)
+            .collect::<Vec<_>>();
+        for room in self.rooms.rooms()? {
+            let members = self.rooms.members(&room)?;
+            if !members.contains(peer) {
+                continue;
+            }
+            let is_avatar = avatar_owners.iter().any(|owner| {
+                owner == &this_node_id || members.iter().any(|member| &member.to_string() == owner)
+            });
+            if is_avatar || self.history.references(&room, hash)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Sends an envelope to a peer in the background, envelopes are best effort so failures are only logged.
+    pub fn signal_peer(&self, peer: NodeId, envelope: ephemeral::Envelope) {
+        let connections = self.connections.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = connections.send(peer, &envelope).await {
+                eprintln!("Failed to signal {}: {}", peer, e);
+            }
+        });
+    }
+
+    /// Sends an envelope to every peer of a room that is not offline.
+    pub fn signal_members(
+        &self,
+        room: &DocumentId,
+        envelope: ephemeral::Envelope,
+    ) -> Result<(), String> {
+        let this_node_id = self.beelay_protocol.node_id();
+        for peer in self.rooms.members(room)? {
+            if peer == this_node_id || self.presence.presence(&peer)?.0 == Presence::Offline {
+                continue;
+            }
+            // signalled one by one, so a slow peer doesn't hold up the others
+            self.signal_peer(peer, envelope.clone());
+        }
+        Ok(())
+    }
+
+    /// Commits the profile of this node to a room's document, unless the room has this version of it already or
+    /// no profile is set yet.
+    pub async fn publish_profile<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: DocumentId,
+    ) -> Result<(), String> {
+        let Some(profile) = self.profiles.get(&self.beelay_protocol.node_id())? else {
+            return Ok(());
+        };
+        if self.profiles.is_published(&room, profile.updated_at)? {
+            return Ok(());
+        }
+        let updated_at = profile.updated_at;
+        let message = MessageWithMetaData::new(
+            &room,
+            Message::profile_update(profile),
+            &self.identity.node_secret_key(),
+            self.clock.tick()?,
+        )?;
+        // queued in the outbox first, so the update is committed once the room is reachable
+        self.submit(app, room, message).await?;
+        self.profiles.mark_published(&room, updated_at)
+    }
+
+    /// Stores the profile a peer committed to a room and lets the frontend know when it is newer than the one we
+    /// had.  Our own updates are already stored.
+    pub fn receive_profile<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        peer: NodeId,
+        profile: &Profile,
+    ) -> Result<(), String> {
+        if peer == self.beelay_protocol.node_id() || !self.profiles.save(&peer, profile)? {
+            return Ok(());
         }
+        // stored with a date in the future clamped, so the frontend gets what we kept
+        let profile = self.profiles.get(&peer)?.unwrap_or_else(|| profile.clone());
+        events::tauri::profile_changed(PeerProfile {
+            peer: peer.to_string(),
+            profile,
+        })
+        .emit(app)
+        .map_err(|e| e.to_string())
     }
 
-    pub fn get_document_id(&self) -> Result<&DocumentId, String> {
-        self.document_id
-            .get()
-            .ok_or("Document ID not set".to_string())
+    /// The profile of this node, empty until it is set.
+    pub fn own_profile(&self) -> Result<Profile, String> {
+        Ok(self
+            .profiles
+            .get(&self.beelay_protocol.node_id())?
+            .unwrap_or_default())
     }
 
-    pub fn set_document_id(&self, document_id: DocumentId) -> Result<(), DocumentId> {
-        self.document_id.set(document_id)
+    /// The keyhive identities with access to a room and their roles, ordered by member id.
+    pub async fn access(&self, room: &DocumentId) -> Result<Vec<AccessGrant>, String> {
+        let this_member = self
+            .beelay_protocol
+            .contact_card()
+            .await
+            .map_err(|e| e.to_string())?
+            .peer_id();
+        let mut grants: Vec<AccessGrant> = self
+            .beelay_protocol
+            .query_access(*room)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter_map(|(member, access)| {
+                Some(AccessGrant {
+                    role: access::role(access)?,
+                    this_node: member == this_member,
+                    member: member.to_string(),
+                })
+            })
+            .collect();
+        grants.sort_by(|a, b| a.member.cmp(&b.member));
+        Ok(grants)
+    }
+
+    /// Delegates access to a room to the identity of a contact card with the given role.
+    async fn delegate(
+        &self,
+        room: &DocumentId,
+        card: ContactCard,
+        role: Role,
+    ) -> Result<(), String> {
+        self.beelay_protocol
+            .add_member_to_doc(
+                *room,
+                KeyhiveEntityId::Individual(card),
+                member_access(role),
+            )
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Issues an invite into a room, returning the serialized invite ticket pointing to this node's node ticket.
+    pub fn issue_invite(
+        &self,
+        room: &DocumentId,
+        node_ticket: &NodeTicket,
+        expires_at: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+        invitee: Option<NodeId>,
+    ) -> Result<String, String> {
+        let invite = Invite {
+            id: uuid::Uuid::new_v4().to_string(),
+            room: room.to_string(),
+            created_at: Utc::now(),
+            expires_at,
+            max_uses,
+            invitee: invitee.map(|invitee| invitee.to_string()),
+            redeemed_by: Vec::new(),
+            revoked: false,
+        };
+        self.invites.save(&invite)?;
+        Ok(InviteTicket {
+            invite_id: invite.id,
+            expires_at,
+            node_ticket: Ticket::serialize(node_ticket),
+            title: self.rooms.title(room)?,
+        }
+        .serialize())
+    }
+
+    /// Lets a peer join a room through an invite, granting it write access through keyhive the first time it
+    /// redeems the invite.  Returns the reply to the peer: the room's beelay ticket once access is granted, or
+    /// the reason the join is refused when the invite doesn't allow it.  The use of the invite only counts once
+    /// access is granted, so a failing keyhive doesn't use up a single use invite.
+    pub async fn redeem_invite<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        peer: NodeId,
+        invite_id: &str,
+        contact_card: &str,
+    ) -> Result<ephemeral::Payload, String> {
+        let room = match self.invites.redeem(invite_id, &peer)? {
+            Redemption::Accepted(room) => {
+                let granted = async {
+                    let room = parse_room(&room)?;
+                    let (_, card) = self.contacts.add(contact_card)?;
+                    self.delegate(&room, card, Role::Writer).await?;
+                    Ok::<_, String>(room)
+                }
+                .await;
+                let room = match granted {
+                    Ok(room) => room,
+                    Err(e) => {
+                        return Err(match self.invites.release(invite_id, &peer) {
+                            Ok(()) => e,
+                            Err(release) => format!(
+                                "{}, giving the use of the invite back failed too: {}",
+                                e, release
+                            ),
+                        });
+                    }
+                };
+                self.emit_access(app, &room).await?;
+                room
+            }
+            // the role may have been changed since, it is left as it is
+            Redemption::Repeated(room) => parse_room(&room)?,
+            Redemption::Refused(reason) => {
+                return Ok(ephemeral::Payload::InviteRejected {
+                    invite_id: invite_id.to_string(),
+                    reason,
+                });
+            }
+        };
+        self.rooms.add_peer(&room, peer)?;
+        self.emit_roster(app, &room)?;
+        let ticket = self
+            .beelay_protocol
+            .beelay_ticket_for_document(room)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(ephemeral::Payload::Admitted {
+            invite_id: invite_id.to_string(),
+            ticket: Ticket::serialize(&ticket),
+        })
     }
 
-    pub fn get_node_ticket(&self) -> Result<&NodeTicket, String> {
-        self.node_ticket
-            .get()
-            .ok_or("Node Ticket not set".to_string())
+    /// Redeems an invite ticket with the node that issued it, which replies with the room to join once it
+    /// accepted the invite.
+    pub async fn redeem_with_inviter(&self, serialized: &str) -> Result<NodeId, String> {
+        let (node_ticket, invite) = parse_ticket(serialized)?;
+        let node_addr = node_ticket.node_addr().clone();
+        let inviter = node_addr.node_id;
+        let contact_card = self
+            .beelay_protocol
+            .contact_card()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_string();
+        self.pending_invites.add(
+            invite.invite_id.clone(),
+            PendingInvite {
+                inviter,
+                title: invite.title,
+            },
+        )?;
+        let payload = ephemeral::Payload::Redeem {
+            invite_id: invite.invite_id,
+            contact_card,
+        };
+        // the inviting node may not be known to the endpoint yet, so it is reached through its address
+        self.connections
+            .send(node_addr, &ephemeral::Envelope::new(String::new(), payload))
+            .await
+            .map_err(|e| format!("Failed to reach the inviting node: {}", e))?;
+        Ok(inviter)
     }
 
-    pub fn set_node_ticket(&self, node_ticket: NodeTicket) -> Result<(), NodeTicket> {
-        self.node_ticket.set(node_ticket)
+    /// Joins the room an invite this node redeemed admits it into.  Replies to invites that were not redeemed
+    /// with the replying peer are ignored.
+    pub async fn join_invited<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        peer: NodeId,
+        invite_id: &str,
+        ticket: &str,
+    ) -> Result<(), String> {
+        let Some(invite) = self.pending_invites.take(invite_id, &peer)? else {
+            return Ok(());
+        };
+        let ticket = parse_beelay_ticket(ticket)?;
+        if ticket.node_ticket().node_addr().node_id != peer {
+            return Err("The inviting node admitted us through another node".to_string());
+        }
+        // a new invite into a room left before is the one way back into it, dialing refuses rooms still left
+        let room = ticket.document_id();
+        if self.rooms.has_left(&room)? {
+            self.storage.restore_document(&room.to_string())?;
+            self.rooms
+                .join(room, ticket.node_ticket().clone(), invite.title.clone())?;
+        }
+        let (doc_id, _) = self.dial(app, ticket, invite.title).await?;
+        // joining by hand makes any redial of the room moot
+        self.redials.stop(&doc_id)
+    }
+
+    /// Sends the current access to a room to the frontend.
+    pub async fn emit_access<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: &DocumentId,
+    ) -> Result<(), String> {
+        events::tauri::membership_changed(RoomAccess {
+            room: room.to_string(),
+            grants: self.access(room).await?,
+        })
+        .emit(app)
+        .map_err(|e| e.to_string())
+    }
+
+    /// Sends the current members of a room to the frontend.
+    pub fn emit_roster<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: &DocumentId,
+    ) -> Result<(), String> {
+        events::tauri::roster_changed(Roster {
+            room: room.to_string(),
+            members: self.members(room)?,
+        })
+        .emit(app)
+        .map_err(|e| e.to_string())
+    }
+
+    /// Sends the rosters of the rooms whose connected peers went idle for too long, so they show as away without
+    /// waiting for another change to the room.  Runs until the app exits.
+    pub async fn watch_presence<R: tauri::Runtime>(&self, app: &tauri::AppHandle<R>) {
+        loop {
+            tokio::time::sleep(PRESENCE_CHECK_INTERVAL).await;
+            if let Err(e) = self.emit_gone_away(app) {
+                eprintln!("Failed to report idle peers: {}", e);
+            }
+        }
+    }
+
+    fn emit_gone_away<R: tauri::Runtime>(&self, app: &tauri::AppHandle<R>) -> Result<(), String> {
+        let gone_away = self.presence.gone_away()?;
+        if gone_away.is_empty() {
+            return Ok(());
+        }
+        for room in self.rooms.rooms()? {
+            let members = self.rooms.members(&room)?;
+            if members.iter().any(|peer| gone_away.contains(peer)) {
+                self.emit_roster(app, &room)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Dials the peer of a beelay ticket and joins the room it points to, remembering the ticket so the peer can be
+    /// redialed.  Dialing the same peer or room again is fine, e.g. when reconnecting.
+    async fn dial<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        ticket: BeelayTicket,
+        title: Option<String>,
+    ) -> Result<(DocumentId, NodeId), String> {
+        if self.rooms.has_left(&ticket.document_id())? {
+            return Err("This node left the room".to_string());
+        }
+        let serialized = Ticket::serialize(&ticket);
+        let (doc_id, node_ticket) = self
+            .beelay_protocol
+            .connect_via_beelay_ticket(ticket)
+            .await
+            .map_err(|e| e.to_string())?;
+        let peer = node_ticket.node_addr().node_id;
+        self.rooms.join(doc_id, node_ticket, title)?;
+        self.known_peers.remember(&doc_id, &serialized)?;
+        // connected once the document is discovered through the peer, or the connection to it is reported
+        emit_peer_status(app, &doc_id, &peer, ConnectionStatus::Connecting)?;
+        self.emit_roster(app, &doc_id)?;
+        Ok((doc_id, peer))
+    }
+
+    /// The peer a room was joined through, `None` for rooms created on this node.
+    pub fn known_peer(&self, room: &DocumentId) -> Result<Option<NodeId>, String> {
+        self.known_peers
+            .ticket(room)?
+            .map(|ticket| {
+                parse_beelay_ticket(&ticket).map(|ticket| ticket.node_ticket().node_addr().node_id)
+            })
+            .transpose()
+    }
+
+    /// Redials the peer a room was joined through until it is reachable again, backing off between attempts.
+    /// Only one redial runs per room, it ends early once `redials.stop` is called for the room.
+    pub async fn redial<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: DocumentId,
+    ) -> Result<(), String> {
+        let Some(serialized) = self.known_peers.ticket(&room)? else {
+            return Ok(());
+        };
+        let peer = parse_beelay_ticket(&serialized)?
+            .node_ticket()
+            .node_addr()
+            .node_id;
+        if !self.redials.start(room)? {
+            return Ok(());
+        }
+        let result = async {
+            while let Some(backoff) = self.redials.next_attempt(&room)? {
+                emit_peer_status(app, &room, &peer, ConnectionStatus::Reconnecting)?;
+                tokio::time::sleep(backoff).await;
+                if !self.redials.is_redialing(&room)? {
+                    break;
+                }
+                let ticket = parse_beelay_ticket(&serialized)?;
+                match self.dial(app, ticket, None).await {
+                    Ok(_) => self.redials.stop(&room)?,
+                    Err(e) => eprintln!("Failed to redial {} for {}: {}", peer, room, e),
+                }
+            }
+            Ok(())
+        }
+        .await;
+        // a failing redial must not keep the room from being redialed later
+        if result.is_err() {
+            self.redials.stop(&room)?;
+        }
+        result
+    }
+
+    /// Tells the peers of a room this node went offline, waiting for the notices to go out unlike `signal_peer`.
+    async fn announce_offline(&self, room: &DocumentId, peers: Vec<NodeId>) {
+        let this_node_id = self.beelay_protocol.node_id();
+        let envelope = ephemeral::Envelope::new(room.to_string(), ephemeral::Payload::Offline);
+        let notices = peers
+            .into_iter()
+            .filter(|peer| {
+                peer != &this_node_id
+                    && self
+                        .presence
+                        .presence(peer)
+                        .is_ok_and(|(presence, _)| presence != Presence::Offline)
+            })
+            .map(|peer| {
+                let connections = self.connections.clone();
+                let envelope = envelope.clone();
+                tauri::async_runtime::spawn(async move {
+                    let notice = connections.send(peer, &envelope);
+                    match tokio::time::timeout(OFFLINE_NOTICE_TIMEOUT, notice).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => eprintln!("Failed to tell {} we went offline: {}", peer, e),
+                        Err(_) => eprintln!("Timed out telling {} we went offline", peer),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        // sent all at once, so a slow peer doesn't hold up the others
+        for notice in notices {
+            let _ = notice.await;
+        }
+    }
+
+    /// Leaves a room: tells its peers, stops redialing and drops everything this node kept of the room (the
+    /// room's document, queued and tracked messages, history, read markers, published profiles and invites).
+    /// Beelay may still hear of the document from peers, but it is not stored again and its events are ignored.
+    /// Every step runs even when an earlier one fails, the failures are reported together.
+    pub async fn leave_room(&self, room: &DocumentId) -> Result<(), String> {
+        let mut errors = Vec::new();
+        let peers = self.rooms.leave(room).unwrap_or_else(|e| {
+            errors.push(e);
+            Vec::new()
+        });
+        self.announce_offline(room, peers).await;
+        let document = room.to_string();
+        errors.extend(
+            [
+                self.redials.stop(room),
+                self.known_peers.forget(room),
+                self.outbox.clear(room),
+                self.deliveries.forget(room),
+                self.history.forget(room),
+                self.receipts.forget(room),
+                self.profiles.forget(room),
+                self.invites.forget(&document),
+                self.storage.drop_document(&document),
+            ]
+            .into_iter()
+            .filter_map(Result::err),
+        );
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(format!(
+                "Failed to leave the room completely: {}",
+                errors.join("; ")
+            )),
+        }
+    }
+
+    /// Winds the node down before the app exits: stops redialing, delivers the queued messages while peers are
+    /// still connected, tells the peers of every room we went offline and closes the iroh connections through
+    /// the router.  Delivering and notifying are bounded by `SHUTDOWN_TIMEOUT`, so a stuck peer can't keep the
+    /// app from quitting.
+    pub async fn shutdown<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+    ) -> Result<(), String> {
+        let rooms = self.rooms.rooms()?;
+        for room in &rooms {
+            self.redials.stop(room)?;
+        }
+        let wind_down = async {
+            for room in &rooms {
+                if let Err(e) = self.flush_outbox(app, *room).await {
+                    eprintln!("Failed to flush the outbox of {}: {}", room, e);
+                }
+            }
+            for room in &rooms {
+                let peers = self.rooms.members(room).unwrap_or_default();
+                self.announce_offline(room, peers).await;
+            }
+        };
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, wind_down)
+            .await
+            .is_err()
+        {
+            eprintln!("Timed out winding down, quitting anyway");
+        }
+        self.router.shutdown().await.map_err(|e| e.to_string())
+    }
+
+    /// Tracks a signed message and queues it for delivery, then tries to deliver the room's queue right away.
+    pub async fn submit<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: DocumentId,
+        message: MessageWithMetaData,
+    ) -> Result<(), String> {
+        let pending = self.deliveries.track(room, message.clone())?;
+        emit_delivery(app, pending)?;
+        // queued first, so the message is kept until the room is reachable
+        self.outbox.enqueue(&room, &message)?;
+        if let Err(e) = self.flush_outbox(app, room).await {
+            // failures are reported per message through "delivery" events, queued messages are kept for a retry
+            eprintln!("Failed to flush the outbox: {e}");
+        }
+        Ok(())
+    }
+
+    /// A message of a room as shown, with its authorized revisions applied.  Revisions by other members are
+    /// authorized against the current access of the room, the keyhive members holding the admin role.
+    pub async fn chat_message(
+        &self,
+        room: &DocumentId,
+        message: MessageWithMetaData,
+    ) -> Result<ChatMessage, String> {
+        let revisions = self.history.revisions(room, message.message.id())?;
+        let admins = if revisions
+            .iter()
+            .any(|revision| revision.peer_id != message.peer_id)
+        {
+            self.access(room)
+                .await?
+                .into_iter()
+                .filter(|grant| grant.role == Role::Admin)
+                .map(|grant| grant.member)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let room_key = room.to_string();
+        let authorized = revisions
+            .into_iter()
+            .filter(|revision| revisions::may_revise(&room_key, &message, revision, &admins))
+            .collect::<Vec<_>>();
+        let mut chat_message = message.into_chat_message(room, &self.beelay_protocol.node_id());
+        revisions::apply(&mut chat_message, &authorized);
+        Ok(chat_message)
+    }
+
+    /// Emits the current state of a message that has revisions, once the message itself is recorded.
+    pub async fn emit_revised<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: &DocumentId,
+        id: &str,
+    ) -> Result<(), String> {
+        if self.history.revisions(room, id)?.is_empty() {
+            return Ok(());
+        }
+        let Some(message) = self.history.get(room, id)? else {
+            return Ok(());
+        };
+        let chat_message = self.chat_message(room, message).await?;
+        let (text, _) = chat_message.message.unpack_for_html_integration();
+        events::tauri::message_revised(RevisedMessage {
+            room: room.to_string(),
+            id: id.to_string(),
+            text,
+            edited_at: chat_message.edited_at,
+            deleted: chat_message.deleted,
+        })
+        .emit(app)
+        .map_err(|e| e.to_string())
+    }
+
+    /// Emits the current state of every revised message of a room, e.g. once its access changed and with it the
+    /// revisions authorized by an admin.
+    pub async fn emit_all_revised<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: &DocumentId,
+    ) -> Result<(), String> {
+        for id in self.history.revised(room)? {
+            self.emit_revised(app, room, &id).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends an edit or deletion of a message.  Revisions of messages authored by another node carry the proof
+    /// that this node is an admin of the room, and are refused when it is not.
+    pub async fn revise<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: DocumentId,
+        revision: Message,
+    ) -> Result<(), String> {
+        let target = revision
+            .revision()
+            .map(|revision| revision.target.clone())
+            .ok_or("Not a revision".to_string())?;
+        let message = self
+            .history
+            .get(&room, &target)?
+            .ok_or("Unknown message, it may not be delivered yet".to_string())?;
+        if self.chat_message(&room, message.clone()).await?.deleted {
+            return Err("The message was deleted".to_string());
+        }
+        let mut revision = MessageWithMetaData::new(
+            &room,
+            revision,
+            &self.identity.node_secret_key(),
+            self.clock.tick()?,
+        )?;
+        let this_node_id = self.beelay_protocol.node_id();
+        if message.peer_id != this_node_id || !message.verify(&room) {
+            let is_admin = self
+                .access(&room)
+                .await?
+                .iter()
+                .any(|grant| grant.this_node && grant.role == Role::Admin);
+            if !is_admin {
+                return Err(
+                    "Only the author of a message or an admin of the room may change it"
+                        .to_string(),
+                );
+            }
+            let contact_card = self
+                .beelay_protocol
+                .contact_card()
+                .await
+                .map_err(|e| e.to_string())?;
+            revision.prove_admin(&room, &contact_card, &self.identity)?;
+        }
+        self.submit(app, room, revision).await
+    }
+
+    /// Delivers the messages queued for a room, oldest first, once its document was discovered and a peer
+    /// is connected.  Stops at the first failure so later messages are not committed ahead of it.
+    pub async fn flush_outbox<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: DocumentId,
+    ) -> Result<(), String> {
+        let _flushing = self.flush_lock.lock().await;
+        if !self.rooms.is_ready(&room)? {
+            return Ok(());
+        }
+        for message in self.outbox.queued(&room)? {
+            let pending = self.deliveries.track(room, message.clone())?;
+            emit_delivery(app, pending)?;
+            self.deliver(app, room, message).await?;
+        }
+        Ok(())
+    }
+
+    /// Commits a tracked message to the room's document, emitting its delivery state as it changes.
+    async fn deliver<R: tauri::Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        room: DocumentId,
+        message: MessageWithMetaData,
+    ) -> Result<(), String> {
+        let result = async {
+            let node_ticket = self.rooms.node_ticket(&room)?;
+            let data = postcard::to_allocvec(&message).map_err(|e| e.to_string())?;
+            self.beelay_protocol
+                .add_data_to_document(data, room, node_ticket)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        .await;
+        // the message is synced once a peer acknowledges receiving it
+        let state = match &result {
+            Ok(()) => DeliveryState::Committed,
+            Err(reason) => DeliveryState::Failed {
+                reason: reason.clone(),
+            },
+        };
+        if let Some(update) = self.deliveries.advance(message.message.id(), state)? {
+            emit_delivery(app, update)?;
+        }
+        if result.is_err() {
+            return result;
+        }
+        // the message is committed whatever happens next, failing to clean up doesn't fail its delivery.  A
+        // message left in the outbox is committed again on the next flush, and recorded once by its id
+        if let Err(e) = self.outbox.remove(&room, &message) {
+            eprintln!(
+                "Failed to remove {} from the outbox: {}",
+                message.message.id(),
+                e
+            );
+        }
+        if let Err(e) = self.history.record(&room, &message) {
+            eprintln!("Failed to record {}: {}", message.message.id(), e);
+        }
+        if let Some(revision) = message.message.revision()
+            && let Err(e) = self.emit_revised(app, &room, &revision.target).await
+        {
+            // the revision is delivered either way, the frontend picks it up with the history
+            eprintln!("Failed to emit the revised message: {e}");
+        }
+        Ok(())
+    }
+
+    /// The clock of a message of a room, recorded in the history or still waiting in the outbox.
+    pub fn message_clock(
+        &self,
+        room: &DocumentId,
+        id: &str,
+    ) -> Result<Option<HybridTimestamp>, String> {
+        if let Some(clock) = self.history.clock(room, id)? {
+            return Ok(Some(clock));
+        }
+        Ok(self
+            .outbox
+            .queued(room)?
+            .into_iter()
+            .find(|message| message.message.id() == id)
+            .map(|message| message.clock))
+    }
+
+    /// Tracks the messages left in the outbox by a previous run again, as failed until their room is reachable
+    /// and they are flushed, so they can be retried.
+    pub fn restore_deliveries(&self) -> Result<(), String> {
+        for room in self.rooms.rooms()? {
+            for message in self.outbox.queued(&room)? {
+                self.deliveries.restore(room, message)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Acknowledges new messages received in a room to their authors, so they can mark them as synced.
+    pub fn acknowledge<'a>(
+        &self,
+        room: &DocumentId,
+        messages: impl IntoIterator<Item = &'a MessageWithMetaData>,
+    ) {
+        let this_node_id = self.beelay_protocol.node_id();
+        let mut received = HashMap::<NodeId, Vec<String>>::new();
+        for message in messages {
+            // only authors that really sent the message are told, and never ourselves
+            if message.peer_id != this_node_id && message.verify(room) {
+                received
+                    .entry(message.peer_id)
+                    .or_default()
+                    .push(message.message.id().to_string());
+            }
+        }
+        for (author, message_ids) in received {
+            for message_ids in message_ids.chunks(ephemeral::MAX_RECEIVED_IDS) {
+                let payload = ephemeral::Payload::Received {
+                    message_ids: message_ids.to_vec(),
+                };
+                self.signal_peer(author, ephemeral::Envelope::new(room.to_string(), payload));
+            }
+        }
     }
 }
 
+pub fn emit_delivery<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    update: MessageDelivery,
+) -> Result<(), String> {
+    events::tauri::delivery(update)
+        .emit(app)
+        .map_err(|e| e.to_string())
+}
+
+pub fn emit_peer_status<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    room: &DocumentId,
+    peer: &NodeId,
+    status: ConnectionStatus,
+) -> Result<(), String> {
+    events::tauri::connection(PeerStatus {
+        room: room.to_string(),
+        peer: peer.to_string(),
+        status,
+    })
+    .emit(app)
+    .map_err(|e| e.to_string())
+}
+
+/// How long telling a peer this node went offline may take.
+const OFFLINE_NOTICE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long delivering queued messages and notifying peers may hold up quitting the app.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the invite handed out with a newly created room stays valid.
+const DEFAULT_INVITE_VALIDITY: TimeDelta = TimeDelta::days(1);
+
+/// How often connected peers are checked for having gone away.
+const PRESENCE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Separates message signatures from anything else signed with the node key.
+const MESSAGE_SIGNATURE_CONTEXT: &str = "beelay-chat/message/1";
+
+/// Tags the contents of the bundles compacted by this app.  Beelay leaves the contents of a bundle to the
+/// application compacting the commits, so bundles in any other format are refused instead of misread.
+const BUNDLE_FORMAT: &str = "beelay-chat/bundle/1";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageWithMetaData {
     pub message: Message,
     pub peer_id: NodeId,
+    pub clock: HybridTimestamp,
+    /// Ed25519 signature of `peer_id`'s node key over the message id, room, clock, timestamp, text, attachments,
+    /// revision and profile.
+    pub signature: Vec<u8>,
+    /// Set on revisions of messages authored by another node, by an admin of the room.
+    pub admin_proof: Option<AdminProof>,
+}
+
+/// Proof that a revision was made by an admin of the room: the keyhive contact card of the admin and the signature
+/// of its keyhive key over the same bytes the node signature covers.  Admin access is granted to keyhive
+/// identities rather than nodes, so the node signature alone can't tell.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminProof {
+    pub contact_card: String,
+    pub signature: Vec<u8>,
 }
 
 impl MessageWithMetaData {
-    pub fn new(message: Message, peer_id: NodeId) -> Self {
-        Self { message, peer_id }
+    /// A message authored by this node in a room, signed with its node key.
+    pub fn new(
+        room: &DocumentId,
+        message: Message,
+        secret_key: &SecretKey,
+        clock: HybridTimestamp,
+    ) -> Result<Self, String> {
+        Self::signed(&room.to_string(), message, secret_key, clock)
+    }
+
+    fn signed(
+        room: &str,
+        message: Message,
+        secret_key: &SecretKey,
+        clock: HybridTimestamp,
+    ) -> Result<Self, String> {
+        let signature = secret_key
+            .sign(&Self::signed_bytes(room, &message, &clock)?)
+            .to_bytes()
+            .to_vec();
+        Ok(Self {
+            message,
+            peer_id: secret_key.public(),
+            clock,
+            signature,
+            admin_proof: None,
+        })
+    }
+
+    /// Signs the message with this node's keyhive identity, proving its author is the member of the room holding
+    /// the contact card.
+    pub fn prove_admin(
+        &mut self,
+        room: &DocumentId,
+        contact_card: &ContactCard,
+        identity: &IdentityKeys,
+    ) -> Result<(), String> {
+        let signature = identity.keyhive_signing_key().sign(&Self::signed_bytes(
+            &room.to_string(),
+            &self.message,
+            &self.clock,
+        )?);
+        self.admin_proof = Some(AdminProof {
+            contact_card: contact_card.to_string(),
+            signature: signature.to_bytes().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// The keyhive member id whose admin proof the message carries, `None` without a valid proof.  Whether the
+    /// member is an admin of the room is up to its keyhive access.
+    fn admin_in(&self, room: &str) -> Option<String> {
+        let proof = self.admin_proof.as_ref()?;
+        let contact_card = proof.contact_card.parse::<ContactCard>().ok()?;
+        let signed_bytes = Self::signed_bytes(room, &self.message, &self.clock).ok()?;
+        let signature = Signature::from_slice(&proof.signature).ok()?;
+        contact_card
+            .verifying_key()
+            .verify_strict(&signed_bytes, &signature)
+            .ok()?;
+        Some(contact_card.peer_id().to_string())
+    }
+
+    /// The bytes a message signature covers, the author is covered by the key it is verified with.  The clock is
+    /// covered so a relaying node can't reorder history by rewriting it.
+    fn signed_bytes(
+        room: &str,
+        message: &Message,
+        clock: &HybridTimestamp,
+    ) -> Result<Vec<u8>, String> {
+        postcard::to_allocvec(&(
+            MESSAGE_SIGNATURE_CONTEXT,
+            room,
+            clock,
+            &message.id,
+            message.timestamp,
+            &message.text,
+            &message.attachments,
+            &message.revision,
+            &message.profile,
+        ))
+        .map_err(|e| e.to_string())
+    }
+
+    /// Whether the message was signed by the node it claims as author, for the room it was found in.
+    pub fn verify(&self, room: &DocumentId) -> bool {
+        self.verify_in(&room.to_string())
+    }
+
+    fn verify_in(&self, room: &str) -> bool {
+        let Ok(signed_bytes) = Self::signed_bytes(room, &self.message, &self.clock) else {
+            return false;
+        };
+        let Ok(signature) = self.signature.as_slice().try_into() else {
+            return false;
+        };
+        self.peer_id.verify(&signed_bytes, &signature).is_ok()
     }
 
     pub fn timestamp(&self) -> &DateTime<Utc> {
         &self.message.timestamp
     }
+
+    /// Decodes the contents of a commit, `None` for empty commits like the initial one of a document.
+    pub fn decode(contents: &[u8]) -> Result<Option<Self>, String> {
+        if contents.is_empty() {
+            return Ok(None);
+        }
+        postcard::from_bytes(contents)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Compacts the contents of a range of commits, in order, into the contents of a bundle.
+    pub fn encode_bundle(commits: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+        postcard::to_allocvec(&(BUNDLE_FORMAT, commits)).map_err(|e| e.to_string())
+    }
+
+    /// Decodes the messages compacted into a bundle by `encode_bundle`.
+    pub fn decode_bundle(contents: &[u8]) -> Result<Vec<Self>, String> {
+        let (format, commits): (String, Vec<Vec<u8>>) =
+            postcard::from_bytes(contents).map_err(|e| e.to_string())?;
+        if format != BUNDLE_FORMAT {
+            return Err(format!("Unsupported bundle format {}", format));
+        }
+        commits
+            .iter()
+            .filter_map(|commit| Self::decode(commit).transpose())
+            .collect()
+    }
+
+    pub fn into_chat_message(self, room: &DocumentId, this_node_id: &NodeId) -> ChatMessage {
+        let verified = self.verify(room);
+        ChatMessage {
+            // a message claiming to be ours without our signature was not written by us
+            outgoing: verified && &self.peer_id == this_node_id,
+            author: self.peer_id.to_string(),
+            message: self.message,
+            clock: self.clock,
+            delivery: None,
+            verified,
+            edited_at: None,
+            deleted: false,
+        }
+    }
 }
 
 ipc_macros::impl_trait!(API, {
     #[tauri::command]
-    async fn get_serialized_ticket(state: tauri::State<'_, AppData>) -> Result<String, String> {
+    async fn get_serialized_ticket(
+        room: Option<String>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<String, String> {
+        match room {
+            Some(room) => create_invite(room, state).await,
+            None => create_room(String::new(), state).await,
+        }
+    }
+
+    #[tauri::command]
+    async fn create_room(
+        title: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<String, String> {
+        // a new document (room) is created for the ticket
         let beelay_ticket = state
             .beelay_protocol
             .beelay_ticket()
             .await
             .map_err(|e| e.to_string())?;
+        let document_id = beelay_ticket.document_id();
+        state.rooms.create(document_id, title)?;
         // we serialize to string here for now as passing the beelay ticket directly would
         // mean adding the beelay protocol as an import to the leptos side,
         // increasing import duplications between front and backends.
-        Ok(Ticket::serialize(&beelay_ticket))
+        // the first invite is meant for one person, further ones are created through `create_invite`
+        state.issue_invite(
+            &document_id,
+            beelay_ticket.node_ticket(),
+            Some(Utc::now() + DEFAULT_INVITE_VALIDITY),
+            Some(1),
+            None,
+        )
+    }
+
+    #[ipc(since = "2")]
+    #[tauri::command]
+    async fn create_invite(
+        room: String,
+        expires_at: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+        invitee: Option<String>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<String, String> {
+        let document_id = parse_room(&room)?;
+        if !state.rooms.contains(&document_id)? {
+            return Err("Unknown room".to_string());
+        }
+        let invitee = invitee
+            .map(|invitee| invitee.trim().parse::<NodeId>())
+            .transpose()
+            .map_err(|e| format!("Invalid invitee: {}", e))?;
+        if max_uses == Some(0) {
+            return Err("An invite needs at least one use".to_string());
+        }
+        let beelay_ticket = state
+            .beelay_protocol
+            .beelay_ticket_for_document(document_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        state.issue_invite(
+            &document_id,
+            beelay_ticket.node_ticket(),
+            expires_at,
+            max_uses,
+            invitee,
+        )
+    }
+
+    // frontends predating invite limits issue invites without any
+    #[ipc(compat)]
+    #[tauri::command]
+    async fn create_invite(
+        room: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<String, String> {
+        create_invite_v2(room, None, None, None, state).await
+    }
+
+    #[tauri::command]
+    async fn list_invites(
+        room: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<Vec<Invite>, String> {
+        state.invites.list(&room)
+    }
+
+    #[tauri::command]
+    async fn revoke_invite(id: String, state: tauri::State<'_, AppData>) -> Result<(), String> {
+        state.invites.revoke(&id).map(|_| ())
+    }
+
+    #[tauri::command]
+    async fn list_rooms(state: tauri::State<'_, AppData>) -> Result<Vec<RoomInfo>, String> {
+        Ok(state
+            .rooms
+            .list()?
+            .into_iter()
+            .map(|(room, title)| RoomInfo {
+                id: room.to_string(),
+                title,
+            })
+            .collect())
+    }
+
+    #[tauri::command]
+    async fn leave_room(room: String, state: tauri::State<'_, AppData>) -> Result<(), String> {
+        state.leave_room(&parse_room(&room)?).await
     }
 
     #[tauri::command]
@@ -78,32 +1282,581 @@ ipc_macros::impl_trait!(API, {
         ticket: String,
         state: tauri::State<'_, AppData>,
     ) -> Result<String, String> {
-        let ticket = Ticket::deserialize(&ticket).map_err(|e| e.to_string())?;
-        let (doc_id, node_ticket) = state
+        // the room is joined once the inviting node accepted the invite and replied with it
+        let inviter = state.redeem_with_inviter(&ticket).await?;
+        Ok(format!(
+            "Waiting for {} to accept the invite",
+            identity::fingerprint(&inviter)
+        ))
+    }
+
+    #[tauri::command]
+    async fn invite_codes(ticket: String) -> Result<InviteCodes, String> {
+        // only encode tickets that will parse again on the joining side
+        parse_ticket(&ticket)?;
+        Ok(InviteCodes {
+            code: codes::code(&ticket),
+            words: codes::words(&ticket),
+            link: codes::link(&ticket),
+        })
+    }
+
+    #[tauri::command]
+    async fn inspect_ticket(ticket: String) -> Result<TicketPreview, String> {
+        let (node_ticket, invite) = parse_ticket(&ticket)?;
+        let node_addr = node_ticket.node_addr().clone();
+        Ok(TicketPreview {
+            title: invite.title,
+            inviter: node_addr.node_id.to_string(),
+            inviter_fingerprint: identity::fingerprint(&node_addr.node_id),
+            relay: node_addr.relay_url.map(|relay_url| relay_url.to_string()),
+            direct_addresses: node_addr
+                .direct_addresses
+                .iter()
+                .map(|addr| addr.to_string())
+                .collect(),
+            expires_at: invite.expires_at,
+        })
+    }
+
+    #[ipc(since = "2")]
+    #[tauri::command]
+    async fn broadcast_message<R: tauri::Runtime>(
+        room: String,
+        message: Message,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        let document_id = parse_room(&room)?;
+        if message.revision().is_some() {
+            return Err("Messages are revised through edit_message and delete_message".to_string());
+        }
+        if message.profile().is_some() {
+            return Err("Profiles are published through set_profile".to_string());
+        }
+        let message_w_meta_data = MessageWithMetaData::new(
+            &document_id,
+            message,
+            &state.identity.node_secret_key(),
+            state.clock.tick()?,
+        )?;
+        state.submit(&app, document_id, message_w_meta_data).await
+    }
+
+    // frontends predating rooms broadcast without a room, which is only unambiguous while there is a single one
+    #[ipc(compat)]
+    #[tauri::command]
+    async fn broadcast_message<R: tauri::Runtime>(
+        message: Message,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        let room = match state.rooms.rooms()?.as_slice() {
+            [room] => room.to_string(),
+            [] => return Err("Document ID not set".to_string()),
+            _ => return Err("Multiple rooms joined, a room is required".to_string()),
+        };
+        broadcast_message_v2(room, message, app, state).await
+    }
+
+    #[tauri::command]
+    async fn retry_message<R: tauri::Runtime>(
+        id: String,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        let (room, message) = state.deliveries.retry(&id)?;
+        // the message keeps its id and clock, so it lands where it was first shown
+        let pending = state.deliveries.track(room, message.clone())?;
+        emit_delivery(&app, pending)?;
+        state.outbox.enqueue(&room, &message)?;
+        if let Err(e) = state.flush_outbox(&app, room).await {
+            eprintln!("Failed to flush the outbox: {e}");
+        }
+        Ok(())
+    }
+
+    #[tauri::command]
+    async fn edit_message<R: tauri::Runtime>(
+        room: String,
+        id: String,
+        text: String,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        if text.trim().is_empty() {
+            return Err("An edit needs text, delete the message instead".to_string());
+        }
+        state
+            .revise(&app, parse_room(&room)?, Message::edit(&id, text))
+            .await
+    }
+
+    #[tauri::command]
+    async fn delete_message<R: tauri::Runtime>(
+        room: String,
+        id: String,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        state
+            .revise(&app, parse_room(&room)?, Message::delete(&id))
+            .await
+    }
+
+    #[tauri::command]
+    async fn add_attachment(
+        path: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<Attachment, String> {
+        state.blobs.import(Path::new(&path))
+    }
+
+    #[tauri::command]
+    async fn fetch_attachment<R: tauri::Runtime>(
+        room: String,
+        attachment: Attachment,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        if state.blobs.contains(&attachment.hash) {
+            return Ok(());
+        }
+        let document_id = parse_room(&room)?;
+        let this_node_id = state.beelay_protocol.node_id();
+        let mut peers = state
+            .rooms
+            .members(&document_id)?
+            .into_iter()
+            .filter(|peer| peer != &this_node_id)
+            .map(|peer| Ok((state.presence.presence(&peer)?.0 == Presence::Offline, peer)))
+            .collect::<Result<Vec<_>, String>>()?;
+        // peers that are not offline are asked first, the others may still be reachable
+        peers.sort_by_key(|(offline, _)| *offline);
+        let mut error = "No peer of the room has the attachment".to_string();
+        for (_, peer) in peers {
+            let progress = |received| {
+                let progress = AttachmentProgress {
+                    room: room.clone(),
+                    hash: attachment.hash.clone(),
+                    received,
+                    size: attachment.size,
+                };
+                if let Err(e) = events::tauri::attachment_progress(progress).emit(&app) {
+                    eprintln!("Failed to emit attachment progress: {e}");
+                }
+            };
+            match blobs::fetch(&state.connections, peer, &attachment, progress).await {
+                Ok(bytes) => return state.blobs.insert(&attachment, &bytes),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    #[tauri::command]
+    async fn attachment_data_url(
+        attachment: Attachment,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<String, String> {
+        if !attachment.mime.starts_with("image/") || attachment.size > MAX_INLINE_IMAGE_SIZE {
+            return Err("The attachment can't be shown inline".to_string());
+        }
+        let bytes = state.blobs.read(&attachment.hash)?;
+        Ok(format!(
+            "data:{};base64,{}",
+            attachment.mime,
+            data_encoding::BASE64.encode(&bytes)
+        ))
+    }
+
+    #[tauri::command]
+    async fn reveal_attachment<R: tauri::Runtime>(
+        attachment: Attachment,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        let path = state.blobs.named_copy(&attachment)?;
+        // peers choose the name and type of attachments, so nothing is opened on their behalf
+        app.opener()
+            .reveal_item_in_dir(path)
+            .map_err(|e| e.to_string())
+    }
+
+    #[tauri::command]
+    async fn save_attachment(
+        attachment: Attachment,
+        path: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        state.blobs.save(&attachment, Path::new(&path))
+    }
+
+    #[tauri::command]
+    async fn send_signal(
+        room: String,
+        kind: SignalKind,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        let document_id = parse_room(&room)?;
+        let envelope = ephemeral::Envelope::new(room, ephemeral::Payload::Signal(kind));
+        state.signal_members(&document_id, envelope)
+    }
+
+    #[tauri::command]
+    async fn mark_read(
+        room: String,
+        message_id: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        let document_id = parse_room(&room)?;
+        let this_node_id = state.beelay_protocol.node_id();
+        let clock = state
+            .message_clock(&document_id, &message_id)?
+            .ok_or("Unknown message".to_string())?;
+        if !state
+            .receipts
+            .mark(&document_id, &this_node_id, &message_id, clock)?
+        {
+            return Ok(());
+        }
+        let envelope = ephemeral::Envelope::new(room, ephemeral::Payload::Read { message_id });
+        state.signal_members(&document_id, envelope)
+    }
+
+    #[tauri::command]
+    async fn list_receipts(
+        room: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<Vec<ReadReceipt>, String> {
+        let document_id = parse_room(&room)?;
+        let this_node_id = state.beelay_protocol.node_id();
+        Ok(state
+            .receipts
+            .markers(&document_id)?
+            .into_iter()
+            .filter(|(peer, _)| peer != &this_node_id)
+            .map(|(peer, message_id)| ReadReceipt {
+                room: room.clone(),
+                peer: peer.to_string(),
+                message_id,
+            })
+            .collect())
+    }
+
+    #[tauri::command]
+    async fn get_profile(state: tauri::State<'_, AppData>) -> Result<Profile, String> {
+        state.own_profile()
+    }
+
+    #[tauri::command]
+    async fn set_profile<R: tauri::Runtime>(
+        display_name: String,
+        avatar: Option<Attachment>,
+        status: String,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        if display_name.chars().count() > MAX_DISPLAY_NAME_LEN {
+            return Err(format!(
+                "Display name is longer than {} characters",
+                MAX_DISPLAY_NAME_LEN
+            ));
+        }
+        if status.chars().count() > MAX_STATUS_LEN {
+            return Err(format!(
+                "Status is longer than {} characters",
+                MAX_STATUS_LEN
+            ));
+        }
+        if let Some(avatar) = &avatar {
+            // peers show avatars inline, like image attachments
+            if !avatar.mime.starts_with("image/") || avatar.size > MAX_INLINE_IMAGE_SIZE {
+                return Err(format!(
+                    "The avatar must be an image of at most {} MiB",
+                    MAX_INLINE_IMAGE_SIZE / 1024 / 1024
+                ));
+            }
+            if !state.blobs.contains(&avatar.hash) {
+                return Err("Add the avatar with add_attachment first".to_string());
+            }
+        }
+        let profile = Profile {
+            display_name,
+            avatar,
+            status,
+            updated_at: Utc::now(),
+        };
+        if !state
+            .profiles
+            .save(&state.beelay_protocol.node_id(), &profile)?
+        {
+            return Err("A more recent profile is set already".to_string());
+        }
+        // committed to the documents of the rooms, so peers that are offline now receive it once they sync
+        for room in state.rooms.rooms()? {
+            state.publish_profile(&app, room).await?;
+        }
+        Ok(())
+    }
+
+    #[tauri::command]
+    async fn list_profiles(state: tauri::State<'_, AppData>) -> Result<Vec<PeerProfile>, String> {
+        let this_node_id = state.beelay_protocol.node_id().to_string();
+        Ok(state
+            .profiles
+            .all()?
+            .into_iter()
+            .filter(|(peer, _)| peer != &this_node_id)
+            .map(|(peer, profile)| PeerProfile { peer, profile })
+            .collect())
+    }
+
+    #[tauri::command]
+    async fn list_members(
+        room: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<Vec<Member>, String> {
+        state.members(&parse_room(&room)?)
+    }
+
+    #[tauri::command]
+    async fn get_contact_card(state: tauri::State<'_, AppData>) -> Result<String, String> {
+        Ok(state
             .beelay_protocol
-            .connect_via_beelay_ticket(ticket)
+            .contact_card()
             .await
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| e.to_string())?
+            .to_string())
+    }
+
+    #[tauri::command]
+    async fn list_access(
+        room: String,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<Vec<AccessGrant>, String> {
+        state.access(&parse_room(&room)?).await
+    }
+
+    #[tauri::command]
+    async fn add_member<R: tauri::Runtime>(
+        room: String,
+        contact_card: String,
+        role: Role,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        let document_id = parse_room(&room)?;
+        let (_, card) = state.contacts.add(&contact_card)?;
+        state.delegate(&document_id, card, role).await?;
+        state.emit_access(&app, &document_id).await
+    }
+
+    #[tauri::command]
+    async fn set_member_role<R: tauri::Runtime>(
+        room: String,
+        member: String,
+        role: Role,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        let document_id = parse_room(&room)?;
+        let card = state.contacts.get(&member)?;
+        let previous = state
+            .access(&document_id)
+            .await?
+            .into_iter()
+            .find(|grant| grant.member == member)
+            .map(|grant| grant.role);
+        // keyhive only widens access by delegating again, narrowing it requires revoking the previous delegation
         state
-            .set_node_ticket(node_ticket)
-            .expect("Should be a valid node ticket set only once");
-        Ok(format!("Connected with document {}", doc_id))
+            .beelay_protocol
+            .remove_member_from_doc(document_id, KeyhiveEntityId::Individual(card.clone()))
+            .await
+            .map_err(|e| e.to_string())?;
+        let delegated = match state.delegate(&document_id, card.clone(), role).await {
+            // the member would be left without any access, so the revoked role is delegated again
+            Err(e) => match previous {
+                Some(previous) => match state.delegate(&document_id, card, previous).await {
+                    Ok(()) => Err(e),
+                    Err(restore) => Err(format!(
+                        "{}, restoring the previous role failed too: {}",
+                        e, restore
+                    )),
+                },
+                None => Err(e),
+            },
+            Ok(()) => Ok(()),
+        };
+        state.emit_access(&app, &document_id).await?;
+        delegated
     }
 
     #[tauri::command]
-    async fn broadcast_message(
-        message: Message,
+    async fn remove_member<R: tauri::Runtime>(
+        room: String,
+        member: String,
+        app: tauri::AppHandle<R>,
         state: tauri::State<'_, AppData>,
     ) -> Result<(), String> {
-        let document_id = state.get_document_id()?;
-        let node_ticket = state.get_node_ticket()?;
-        let message_w_meta_data =
-            MessageWithMetaData::new(message, state.beelay_protocol.node_id());
-        let data = postcard::to_allocvec(&message_w_meta_data).map_err(|e| e.to_string())?;
+        let document_id = parse_room(&room)?;
+        let card = state.contacts.get(&member)?;
         state
             .beelay_protocol
-            .add_data_to_document(data, *document_id, node_ticket.clone())
+            .remove_member_from_doc(document_id, KeyhiveEntityId::Individual(card))
             .await
-            .map_err(|e| e.to_string())
+            .map_err(|e| e.to_string())?;
+        state.emit_access(&app, &document_id).await
+    }
+
+    #[tauri::command]
+    async fn load_history(
+        room: String,
+        before: Option<HistoryCursor>,
+        limit: u32,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<Vec<ChatMessage>, String> {
+        let this_node_id = state.beelay_protocol.node_id();
+        let document_id = parse_room(&room)?;
+        // revisions and profile updates waiting in the outbox are applied once they are delivered
+        let queued = state
+            .outbox
+            .queued(&document_id)?
+            .into_iter()
+            .filter(|message| {
+                message.message.revision().is_none() && message.message.profile().is_none()
+            })
+            .collect::<Vec<_>>();
+        let mut messages = Vec::new();
+        for message in state.history.load(&room, before.as_ref(), limit as usize)? {
+            messages.push(state.chat_message(&document_id, message).await?);
+        }
+        for message in &mut messages {
+            message.delivery = state.deliveries.state(message.message.id())?;
+        }
+        // queued messages are newer than anything synced, so they only belong on the newest page
+        if before.is_none() {
+            let loaded = messages.len();
+            for message in queued {
+                if !messages[..loaded]
+                    .iter()
+                    .any(|loaded| loaded.message.id() == message.message.id())
+                {
+                    let delivery = state
+                        .deliveries
+                        .state(message.message.id())?
+                        .or(Some(DeliveryState::Pending));
+                    messages.push(ChatMessage {
+                        delivery,
+                        ..message.into_chat_message(&document_id, &this_node_id)
+                    });
+                }
+            }
+            messages.sort_by(|a, b| (a.clock, a.message.id()).cmp(&(b.clock, b.message.id())));
+        }
+        Ok(messages)
+    }
+
+    #[tauri::command]
+    async fn get_node_fingerprint(state: tauri::State<'_, AppData>) -> Result<String, String> {
+        Ok(identity::fingerprint(&state.beelay_protocol.node_id()))
+    }
+
+    #[tauri::command]
+    async fn set_identity_passphrase(
+        passphrase: Option<String>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        state
+            .identity_store
+            .save(&state.identity, passphrase.as_deref())
+    }
+
+    #[tauri::command]
+    async fn rotate_identity<R: tauri::Runtime>(
+        passphrase: Option<String>,
+        app: tauri::AppHandle<R>,
+        state: tauri::State<'_, AppData>,
+    ) -> Result<(), String> {
+        state
+            .identity_store
+            .save(&IdentityKeys::generate(), passphrase.as_deref())?;
+        // the node has to be started again with the new keys
+        app.restart()
+    }
+
+    #[tauri::command]
+    async fn is_identity_locked(state: tauri::State<'_, IdentityUnlock>) -> Result<bool, String> {
+        state.is_locked()
+    }
+
+    #[tauri::command]
+    async fn unlock_identity(
+        passphrase: String,
+        state: tauri::State<'_, IdentityUnlock>,
+    ) -> Result<(), String> {
+        state.unlock(&passphrase).await
     }
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_message() -> MessageWithMetaData {
+        let secret_key = IdentityKeys::generate().node_secret_key();
+        let clock = HybridTimestamp {
+            millis: 1_000,
+            counter: 0,
+        };
+        MessageWithMetaData::signed("room", Message::new("hi".into()), &secret_key, clock).unwrap()
+    }
+
+    #[test]
+    fn signature_covers_the_room() {
+        let message = signed_message();
+        assert!(message.verify_in("room"));
+        assert!(!message.verify_in("other room"));
+    }
+
+    #[test]
+    fn bundles_decode_into_their_messages() {
+        let first = signed_message();
+        let second = signed_message();
+        let commits = [
+            // the initial commit of a document is empty
+            Vec::new(),
+            postcard::to_allocvec(&first).unwrap(),
+            postcard::to_allocvec(&second).unwrap(),
+        ];
+        let bundle = MessageWithMetaData::encode_bundle(&commits).unwrap();
+        let messages = MessageWithMetaData::decode_bundle(&bundle).unwrap();
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| message.message.id())
+                .collect::<Vec<_>>(),
+            [first.message.id(), second.message.id()]
+        );
+        assert!(messages.iter().all(|message| message.verify_in("room")));
+    }
+
+    #[test]
+    fn bundles_of_another_format_are_refused() {
+        let commits = vec![postcard::to_allocvec(&signed_message()).unwrap()];
+        let foreign = postcard::to_allocvec(&("automerge", &commits)).unwrap();
+        assert!(MessageWithMetaData::decode_bundle(&foreign).is_err());
+        let untagged = postcard::to_allocvec(&commits).unwrap();
+        assert!(MessageWithMetaData::decode_bundle(&untagged).is_err());
+    }
+
+    #[test]
+    fn tampered_clock_fails_verification() {
+        let mut message = signed_message();
+        message.clock.millis += 1;
+        assert!(!message.verify_in("room"));
+        message.clock.millis -= 1;
+        message.clock.counter += 1;
+        assert!(!message.verify_in("room"));
+    }
+}
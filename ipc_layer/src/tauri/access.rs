@@ -0,0 +1,77 @@
+//! Room membership through keyhive.  Access to a room's document is delegated to keyhive identities, each with a
+//! role, and identities are exchanged as contact cards.  Keyhive needs a member's contact card to revoke or change
+//! its access, so the cards of the members added on this node are kept in a redb database under the app data
+//! directory, keyed by member id.
+use crate::Role;
+use beelay_protocol::{ContactCard, MemberAccess};
+use redb::{Database, TableDefinition};
+use std::path::Path;
+
+/// Member id -> serialized contact card.
+const CONTACTS: TableDefinition<&str, &str> = TableDefinition::new("contacts");
+
+/// The keyhive access a role is delegated as.
+pub fn member_access(role: Role) -> MemberAccess {
+    match role {
+        Role::Admin => MemberAccess::Admin,
+        Role::Writer => MemberAccess::Write,
+        Role::Reader => MemberAccess::Read,
+    }
+}
+
+/// The role of a keyhive access, `None` for peers that may only pull the document (e.g. sync servers).
+pub fn role(access: MemberAccess) -> Option<Role> {
+    match access {
+        MemberAccess::Admin => Some(Role::Admin),
+        MemberAccess::Write => Some(Role::Writer),
+        MemberAccess::Read => Some(Role::Reader),
+        MemberAccess::Pull => None,
+    }
+}
+
+pub struct ContactBook {
+    db: Database,
+}
+
+impl ContactBook {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the table up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(CONTACTS).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(Self { db })
+    }
+
+    /// Parses and keeps a serialized contact card, returning it with the member id it belongs to.
+    pub fn add(&self, contact_card: &str) -> Result<(String, ContactCard), String> {
+        let card = contact_card
+            .trim()
+            .parse::<ContactCard>()
+            .map_err(|e| e.to_string())?;
+        let member = card.peer_id().to_string();
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(CONTACTS).map_err(|e| e.to_string())?;
+            table
+                .insert(member.as_str(), card.to_string().as_str())
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok((member, card))
+    }
+
+    /// The contact card of a member added on this node.  Members added on other nodes can't be resolved from the
+    /// keyhive access list, which only holds their ids.
+    pub fn get(&self, member: &str) -> Result<ContactCard, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(CONTACTS).map_err(|e| e.to_string())?;
+        let card = table
+            .get(member)
+            .map_err(|e| e.to_string())?
+            .ok_or("Unknown member, only members added on this node can be changed".to_string())?;
+        card.value()
+            .parse::<ContactCard>()
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,239 @@
+//! Attachments as content-addressed blobs, stored as files named by their BLAKE3 hash in a directory under the app
+//! data directory.  Messages only carry the hash, name, mime type and size of their attachments, the bytes are
+//! fetched from the peers of the room on demand and verified against the hash before they are stored.  Blob
+//! requests travel over the same ALPN as ephemeral envelopes, on bidirectional streams: the requesting peer sends
+//! the hex hash, the serving peer answers whether it has the blob, its size and its bytes.  Knowing a hash is not
+//! enough, the app decides through a `BlobRequest` whether the requesting peer may fetch the blob.
+use super::ephemeral::PeerConnections;
+use crate::Attachment;
+use beelay_protocol::NodeId;
+use iroh::endpoint::{RecvStream, SendStream};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::async_runtime::Sender;
+use tokio::sync::oneshot;
+
+/// Largest attachment accepted, blobs are held in memory while they are transferred.
+pub const MAX_ATTACHMENT_SIZE: u64 = 64 * 1024 * 1024;
+/// Length of a hex encoded BLAKE3 hash.
+const HASH_LEN: usize = 64;
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Progress is reported every time this many more bytes were received, and once the transfer completed.
+const PROGRESS_STEP: u64 = 512 * 1024;
+
+fn hash(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_hex().to_string()
+}
+
+/// The mime type of a file, guessed from its extension.
+fn mime(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "txt" | "md" => "text/plain",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Debug)]
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        Ok(Self { dir })
+    }
+
+    /// The file a blob is stored in, hashes are checked so they can't point outside the store.
+    fn path(&self, hash: &str) -> Result<PathBuf, String> {
+        if hash.len() != HASH_LEN || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Invalid attachment hash".to_string());
+        }
+        Ok(self.dir.join(hash))
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.path(hash).is_ok_and(|path| path.is_file())
+    }
+
+    /// Stores a file as a blob, returning the attachment referencing it.
+    pub fn import(&self, path: &Path) -> Result<Attachment, String> {
+        let size = fs::metadata(path).map_err(|e| e.to_string())?.len();
+        if size > MAX_ATTACHMENT_SIZE {
+            return Err(format!(
+                "Attachments are limited to {} MiB",
+                MAX_ATTACHMENT_SIZE / 1024 / 1024
+            ));
+        }
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or("The file has no name".to_string())?
+            .to_string();
+        let attachment = Attachment {
+            hash: hash(&bytes),
+            mime: mime(&name).to_string(),
+            name,
+            size: bytes.len() as u64,
+        };
+        self.insert(&attachment, &bytes)?;
+        Ok(attachment)
+    }
+
+    /// Stores the bytes of an attachment, unless they don't match its hash.
+    pub fn insert(&self, attachment: &Attachment, bytes: &[u8]) -> Result<(), String> {
+        if hash(bytes) != attachment.hash {
+            return Err("The attachment does not match its hash".to_string());
+        }
+        let path = self.path(&attachment.hash)?;
+        if path.is_file() {
+            return Ok(());
+        }
+        // written aside and moved in place, so an interrupted write never passes for the blob
+        let partial = path.with_extension("partial");
+        fs::write(&partial, bytes).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &path).map_err(|e| e.to_string())
+    }
+
+    pub fn read(&self, hash: &str) -> Result<Vec<u8>, String> {
+        fs::read(self.path(hash)?).map_err(|_| "The attachment was not downloaded yet".to_string())
+    }
+
+    /// Copies an attachment to a path chosen by the user.
+    pub fn save(&self, attachment: &Attachment, to: &Path) -> Result<(), String> {
+        fs::copy(self.path(&attachment.hash)?, to)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// A copy of an attachment under its own name, so it can be opened with the app registered for its type.
+    pub fn named_copy(&self, attachment: &Attachment) -> Result<PathBuf, String> {
+        let blob = self.path(&attachment.hash)?;
+        // names come from peers, only their last component is used
+        let name = Path::new(&attachment.name)
+            .file_name()
+            .ok_or("Invalid attachment name".to_string())?;
+        let dir = self.dir.join("named").join(&attachment.hash);
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(name);
+        if !path.is_file() {
+            fs::copy(&blob, &path).map_err(|e| e.to_string())?;
+        }
+        Ok(path)
+    }
+}
+
+/// A peer asking for a blob, answered with whether it may fetch it.
+#[derive(Debug)]
+pub struct BlobRequest {
+    pub peer: NodeId,
+    pub hash: String,
+    pub allowed: oneshot::Sender<bool>,
+}
+
+/// Answers a blob request a peer opened a bidirectional stream for.  Blobs the app doesn't allow the peer to
+/// fetch are answered as if this node didn't have them.
+pub async fn serve(
+    store: &BlobStore,
+    requests: &Sender<BlobRequest>,
+    peer: NodeId,
+    mut send: SendStream,
+    mut recv: RecvStream,
+) -> Result<(), String> {
+    let request = recv
+        .read_to_end(HASH_LEN)
+        .await
+        .map_err(|e| e.to_string())?;
+    let hash = String::from_utf8(request).map_err(|_| "Malformed blob request".to_string())?;
+    let (allowed, answer) = oneshot::channel();
+    let request = BlobRequest {
+        peer,
+        hash: hash.clone(),
+        allowed,
+    };
+    // no answer, e.g. while the app is shutting down, means no
+    let allowed = requests.send(request).await.is_ok() && answer.await.unwrap_or(false);
+    match store.read(&hash).ok().filter(|_| allowed) {
+        Some(bytes) => {
+            send.write_all(&[1]).await.map_err(|e| e.to_string())?;
+            send.write_all(&(bytes.len() as u64).to_be_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+            send.write_all(&bytes).await.map_err(|e| e.to_string())?;
+        }
+        None => send.write_all(&[0]).await.map_err(|e| e.to_string())?,
+    }
+    send.finish().map_err(|e| e.to_string())?;
+    send.stopped().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Fetches the bytes of an attachment from a peer, reporting the number of bytes received as they arrive.
+pub async fn fetch(
+    connections: &PeerConnections,
+    peer: NodeId,
+    attachment: &Attachment,
+    mut progress: impl FnMut(u64),
+) -> Result<Vec<u8>, String> {
+    let connection = connections.connection(peer).await?;
+    let (mut send, mut recv) = match connection.open_bi().await {
+        Ok(streams) => streams,
+        Err(e) => {
+            connections.forget(peer, &connection)?;
+            return Err(e.to_string());
+        }
+    };
+    send.write_all(attachment.hash.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    send.finish().map_err(|e| e.to_string())?;
+    let mut found = [0; 1];
+    recv.read_exact(&mut found)
+        .await
+        .map_err(|e| e.to_string())?;
+    if found[0] != 1 {
+        return Err("The peer does not have the attachment".to_string());
+    }
+    let mut size = [0; 8];
+    recv.read_exact(&mut size)
+        .await
+        .map_err(|e| e.to_string())?;
+    let size = u64::from_be_bytes(size);
+    if size != attachment.size || size > MAX_ATTACHMENT_SIZE {
+        return Err("The peer announced an attachment of the wrong size".to_string());
+    }
+    let mut bytes = Vec::with_capacity(size as usize);
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut reported = 0;
+    while (bytes.len() as u64) < size {
+        let Some(read) = recv.read(&mut chunk).await.map_err(|e| e.to_string())? else {
+            break;
+        };
+        bytes.extend_from_slice(&chunk[..read]);
+        let received = bytes.len() as u64;
+        if received - reported >= PROGRESS_STEP || received >= size {
+            progress(received);
+            reported = received;
+        }
+    }
+    if bytes.len() as u64 != size || hash(&bytes) != attachment.hash {
+        return Err("The attachment received does not match its hash".to_string());
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,111 @@
+//! Hybrid logical clock ordering the messages this node sends.  Every message carries the `HybridTimestamp`
+//! it was sent at, and every received message advances the clock, so a message always orders after
+//! everything its sender had seen even when wall clocks between peers disagree.  Remote clocks more than
+//! `MAX_DRIFT` ahead of the local wall clock are not merged and the messages carrying them are rejected, so a
+//! single peer can't push the clock of the room into the future or pin its messages to the end of the history.
+use crate::HybridTimestamp;
+use chrono::Utc;
+use std::sync::Mutex;
+
+/// How far, in milliseconds, a remote clock may run ahead of the local wall clock and still be merged.
+pub const MAX_DRIFT: i64 = 5 * 60 * 1000;
+
+#[derive(Default)]
+pub struct HybridClock {
+    last: Mutex<HybridTimestamp>,
+}
+
+impl HybridClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Timestamp for a message sent by this node, greater than anything sent or observed before.
+    pub fn tick(&self) -> Result<HybridTimestamp, String> {
+        self.tick_at(Utc::now().timestamp_millis())
+    }
+
+    fn tick_at(&self, now: i64) -> Result<HybridTimestamp, String> {
+        let mut last = self.last.lock().map_err(|e| e.to_string())?;
+        *last = last.successor(now);
+        Ok(*last)
+    }
+
+    /// Whether a timestamp received from a peer is more than `MAX_DRIFT` ahead of the local wall clock.
+    pub fn is_too_far_ahead(&self, remote: HybridTimestamp) -> bool {
+        Self::is_too_far_ahead_at(remote, Utc::now().timestamp_millis())
+    }
+
+    fn is_too_far_ahead_at(remote: HybridTimestamp, now: i64) -> bool {
+        remote.millis > now.saturating_add(MAX_DRIFT)
+    }
+
+    /// Merges a timestamp received from a peer, so later ticks order after it.  Returns `false` when the
+    /// timestamp is more than `MAX_DRIFT` ahead of the local wall clock and was rejected.
+    pub fn observe(&self, remote: HybridTimestamp) -> Result<bool, String> {
+        self.observe_at(remote, Utc::now().timestamp_millis())
+    }
+
+    fn observe_at(&self, remote: HybridTimestamp, now: i64) -> Result<bool, String> {
+        if Self::is_too_far_ahead_at(remote, now) {
+            return Ok(false);
+        }
+        let mut last = self.last.lock().map_err(|e| e.to_string())?;
+        if remote > *last {
+            *last = remote;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: i64, counter: u32) -> HybridTimestamp {
+        HybridTimestamp { millis, counter }
+    }
+
+    #[test]
+    fn successor_follows_the_wall_clock() {
+        assert_eq!(at(10, 7).successor(20), at(20, 0));
+        assert_eq!(at(10, 7).successor(10), at(10, 8));
+        // a wall clock running behind never moves the timestamp backwards
+        assert_eq!(at(10, 7).successor(5), at(10, 8));
+    }
+
+    #[test]
+    fn successor_rolls_over_an_exhausted_counter() {
+        assert_eq!(at(10, u32::MAX).successor(10), at(11, 0));
+        assert!(at(10, u32::MAX).successor(10) > at(10, u32::MAX));
+    }
+
+    #[test]
+    fn ticks_order_after_observed_clocks() {
+        let clock = HybridClock::new();
+        let now = 1_000_000;
+        assert!(clock.observe_at(at(now + 1_000, 3), now).unwrap());
+        let tick = clock.tick_at(now).unwrap();
+        assert_eq!(tick, at(now + 1_000, 4));
+        // older remote clocks don't move the clock back
+        assert!(clock.observe_at(at(now - 1_000, 0), now).unwrap());
+        assert!(clock.tick_at(now).unwrap() > tick);
+    }
+
+    #[test]
+    fn far_future_clocks_are_rejected() {
+        let clock = HybridClock::new();
+        let now = 1_000_000;
+        assert!(!clock.observe_at(at(now + MAX_DRIFT + 1, 0), now).unwrap());
+        assert_eq!(clock.tick_at(now).unwrap(), at(now, 0));
+        assert!(clock.observe_at(at(now + MAX_DRIFT, 0), now).unwrap());
+        assert!(HybridClock::is_too_far_ahead_at(
+            at(now + MAX_DRIFT + 1, 0),
+            now
+        ));
+        assert!(!HybridClock::is_too_far_ahead_at(
+            at(now + MAX_DRIFT, 0),
+            now
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+//! Compact forms of serialized tickets for sharing them outside the app.  A ticket is deflated and prefixed with
+//! a format version, a CRC-32 of both is appended so typos are caught before dialing, and the bytes are then
+//! written either as an invite code (base32, which QR codes encode in their compact alphanumeric mode) or as a
+//! word code with one word per byte.  The word code is as long as the deflated ticket, dozens of words, so it is
+//! meant for copying a ticket by hand where nothing can be pasted, not for reading it aloud.  Invite codes can
+//! also be wrapped in a `beelay-chat://join?code=...` link.  `decode` turns any of these forms back into the
+//! serialized ticket.
+use data_encoding::BASE32_NOPAD;
+
+/// Prefix of invite codes, the base32 encoded bytes follow.
+const CODE_PREFIX: &str = "BCI";
+/// Scheme and path of deep links into the join screen.
+pub const LINK_PREFIX: &str = "beelay-chat://join";
+/// Version of the byte layout: version byte, deflated ticket, big endian CRC-32 of the preceding bytes.
+const CODE_VERSION: u8 = 1;
+/// Upper bound for inflated tickets, so a crafted code can't exhaust memory.
+const MAX_TICKET_LEN: usize = 16 * 1024;
+
+/// One word per byte value, short and distinct so typos are caught.
+const WORDS: [&str; 256] = [
+    "acid", "acorn", "actor", "adobe", "agent", "alarm", "album", "alley", "amber", "angle",
+    "ankle", "apple", "apron", "arena", "arrow", "atlas", "attic", "audio", "award", "bacon",
+    "badge", "bagel", "baker", "bamboo", "banjo", "barn", "basil", "beach", "beard", "bench",
+    "berry", "bingo", "birch", "bison", "blade", "blaze", "bloom", "board", "bonus", "boots",
+    "brick", "broom", "brush", "bucket", "buddy", "bugle", "cabin", "cable", "cactus", "camel",
+    "candy", "canoe", "cargo", "carpet", "castle", "cedar", "chalk", "cherry", "chess", "chief",
+    "cider", "cinema", "circus", "clam", "cliff", "clock", "cloud", "clover", "cobra", "cocoa",
+    "comet", "coral", "cotton", "couch", "crane", "crown", "cube", "daisy", "dance", "delta",
+    "denim", "desert", "diary", "dingo", "disco", "dock", "dolphin", "donkey", "dragon", "drum",
+    "eagle", "easel", "echo", "elbow", "elder", "ember", "emerald", "engine", "fabric", "falcon",
+    "fancy", "feather", "fence", "ferry", "fiddle", "field", "flame", "flute", "forest", "fossil",
+    "frost", "fudge", "galaxy", "garden", "garlic", "gecko", "ginger", "glacier", "globe", "goose",
+    "grape", "gravel", "guitar", "hammer", "harbor", "hazel", "helmet", "heron", "hockey", "honey",
+    "hotel", "husky", "igloo", "index", "island", "ivory", "jacket", "jaguar", "jelly", "jewel",
+    "jigsaw", "juice", "jungle", "kayak", "kettle", "kiwi", "koala", "ladder", "lagoon", "lemon",
+    "lilac", "lizard", "lobster", "locket", "lotus", "magnet", "mango", "maple", "marble",
+    "meadow", "melon", "mint", "mirror", "mitten", "monkey", "mosaic", "motor", "muffin", "napkin",
+    "nectar", "needle", "nickel", "noodle", "nutmeg", "oasis", "ocean", "olive", "onion", "opera",
+    "orbit", "orchid", "otter", "oyster", "paddle", "panda", "parrot", "pasta", "peanut", "pebble",
+    "pepper", "piano", "pickle", "pilot", "pirate", "planet", "plum", "pony", "poppy", "potato",
+    "prism", "pumpkin", "puzzle", "quartz", "quiver", "rabbit", "radio", "raven", "ribbon",
+    "river", "robin", "rocket", "saddle", "salmon", "sandal", "scarf", "shadow", "silver",
+    "sketch", "socket", "spider", "spruce", "squid", "stereo", "summit", "sunset", "tablet",
+    "tango", "teapot", "thistle", "thunder", "tiger", "timber", "toast", "tomato", "topaz",
+    "tractor", "tulip", "tunnel", "turtle", "tuxedo", "unicorn", "valley", "vanilla", "velvet",
+    "violin", "volcano", "waffle", "walnut", "walrus", "willow", "window", "wizard", "yacht",
+    "yogurt", "zebra", "zephyr",
+];
+
+/// Deflates a serialized ticket into the versioned and checksummed bytes the codes are written from.
+fn pack(ticket: &str) -> Vec<u8> {
+    let mut bytes = vec![CODE_VERSION];
+    bytes.extend(miniz_oxide::deflate::compress_to_vec(ticket.as_bytes(), 9));
+    let checksum = crc32fast::hash(&bytes);
+    bytes.extend(checksum.to_be_bytes());
+    bytes
+}
+
+fn unpack(bytes: &[u8]) -> Result<String, String> {
+    if bytes.len() < 5 {
+        return Err("The code is too short".to_string());
+    }
+    let (data, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(data).to_be_bytes() != checksum {
+        return Err("The code has a typo, its checksum does not match".to_string());
+    }
+    match data[0] {
+        CODE_VERSION => {}
+        version => {
+            return Err(format!(
+                "Unsupported code version {}, the app may need an update",
+                version
+            ));
+        }
+    }
+    let ticket = miniz_oxide::inflate::decompress_to_vec_with_limit(&data[1..], MAX_TICKET_LEN)
+        .map_err(|_| "Malformed code".to_string())?;
+    String::from_utf8(ticket).map_err(|_| "Malformed code".to_string())
+}
+
+/// The invite code of a serialized ticket.
+pub fn code(ticket: &str) -> String {
+    format!("{CODE_PREFIX}{}", BASE32_NOPAD.encode(&pack(ticket)))
+}
+
+/// The word code of a serialized ticket, words are separated by dashes.
+pub fn words(ticket: &str) -> String {
+    pack(ticket)
+        .into_iter()
+        .map(|byte| WORDS[byte as usize])
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The deep link of a serialized ticket.
+pub fn link(ticket: &str) -> String {
+    format!("{LINK_PREFIX}?code={}", code(ticket))
+}
+
+/// Turns a deep link, invite code or word code back into the serialized ticket it was made from.  Anything else
+/// is taken to be a serialized ticket already.
+pub fn decode(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    if let Some(query) = input.strip_prefix(LINK_PREFIX) {
+        return decode_link(query);
+    }
+    if input.len() > CODE_PREFIX.len()
+        && input.is_char_boundary(CODE_PREFIX.len())
+        && input[..CODE_PREFIX.len()].eq_ignore_ascii_case(CODE_PREFIX)
+    {
+        // codes read back by hand may come in lower case or with spaces
+        let encoded = input[CODE_PREFIX.len()..]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_uppercase();
+        if let Ok(bytes) = BASE32_NOPAD.decode(encoded.as_bytes()) {
+            return unpack(&bytes);
+        }
+    }
+    let tokens = input
+        .split(|c: char| c == '-' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    if tokens.len() > 1
+        && tokens
+            .iter()
+            .all(|token| token.chars().all(|c| c.is_ascii_alphabetic()))
+    {
+        let bytes = tokens
+            .iter()
+            .map(|token| {
+                WORDS
+                    .iter()
+                    .position(|word| word == token)
+                    .map(|byte| byte as u8)
+                    .ok_or(format!("Unknown word \"{}\" in the word code", token))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        return unpack(&bytes);
+    }
+    Ok(input.to_string())
+}
+
+/// Reads the ticket out of the `code` or `ticket` parameter of a deep link's query.
+fn decode_link(query: &str) -> Result<String, String> {
+    let query = query
+        .trim_start_matches('/')
+        .strip_prefix('?')
+        .ok_or("The link has no invite in it".to_string())?;
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value)?;
+        match key {
+            "code" => return decode(&value),
+            "ticket" => return Ok(value),
+            _ => {}
+        }
+    }
+    Err("The link has no invite in it".to_string())
+}
+
+fn percent_decode(value: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'%' => {
+                let hex = [
+                    input.next().unwrap_or_default(),
+                    input.next().unwrap_or_default(),
+                ];
+                let byte = std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or("Malformed link".to_string())?;
+                bytes.push(byte);
+            }
+            b'+' => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| "Malformed link".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKET: &str = "chatinvite3:invite::nodeticket:Room: with colons";
+
+    #[test]
+    fn codes_round_trip() {
+        assert_eq!(decode(&code(TICKET)).unwrap(), TICKET);
+        assert_eq!(decode(&code(TICKET).to_lowercase()).unwrap(), TICKET);
+        assert_eq!(decode(&words(TICKET)).unwrap(), TICKET);
+        assert_eq!(decode(&words(TICKET).replace('-', " ")).unwrap(), TICKET);
+        assert_eq!(decode(&link(TICKET)).unwrap(), TICKET);
+        assert_eq!(decode(TICKET).unwrap(), TICKET);
+    }
+
+    #[test]
+    fn typos_fail_the_checksum() {
+        let mut bytes = pack(TICKET);
+        bytes[1] ^= 1;
+        let code = format!("{CODE_PREFIX}{}", BASE32_NOPAD.encode(&bytes));
+        assert!(decode(&code).unwrap_err().contains("checksum"));
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        let mut bytes = vec![CODE_VERSION + 1];
+        bytes.extend(miniz_oxide::deflate::compress_to_vec(TICKET.as_bytes(), 9));
+        bytes.extend(crc32fast::hash(&bytes).to_be_bytes());
+        let code = format!("{CODE_PREFIX}{}", BASE32_NOPAD.encode(&bytes));
+        assert!(
+            decode(&code)
+                .unwrap_err()
+                .contains("Unsupported code version")
+        );
+    }
+}
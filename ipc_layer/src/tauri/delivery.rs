@@ -0,0 +1,148 @@
+//! Delivery state of the messages this node sends.  A message is `Pending` until it is committed to the
+//! room's document, `Committed` once the local commit is observed or `add_data_to_document` returned, `Synced`
+//! once a peer of the room acknowledges receiving it, and `Failed` otherwise.  Failed messages are kept so they
+//! can be retried, synced ones are no longer tracked.  Tracking is in memory, messages still in the outbox are
+//! tracked again as failed on the next launch so they can be retried.
+use super::MessageWithMetaData;
+use crate::{DeliveryState, MessageDelivery};
+use beelay_protocol::DocumentId;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+struct Delivery {
+    room: DocumentId,
+    message: MessageWithMetaData,
+    state: DeliveryState,
+}
+
+impl Delivery {
+    fn update(&self) -> MessageDelivery {
+        MessageDelivery {
+            room: self.room.to_string(),
+            id: self.message.message.id().to_string(),
+            state: self.state.clone(),
+            clock: self.message.clock,
+        }
+    }
+}
+
+/// Whether a message may move from one state to the other.  States only move forward, so a late commit
+/// notice can't undo a sync, and only a retry leaves the failed state.
+fn can_advance(from: &DeliveryState, to: &DeliveryState) -> bool {
+    match (from, to) {
+        (DeliveryState::Synced | DeliveryState::Failed { .. }, _) => false,
+        (_, DeliveryState::Failed { .. } | DeliveryState::Synced) => true,
+        (DeliveryState::Pending, DeliveryState::Committed) => true,
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+pub struct DeliveryTracker {
+    deliveries: Mutex<HashMap<String, Delivery>>,
+}
+
+impl DeliveryTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking a message as pending.
+    pub fn track(
+        &self,
+        room: DocumentId,
+        message: MessageWithMetaData,
+    ) -> Result<MessageDelivery, String> {
+        let mut deliveries = self.deliveries.lock().map_err(|e| e.to_string())?;
+        let delivery = Delivery {
+            room,
+            message,
+            state: DeliveryState::Pending,
+        };
+        let update = delivery.update();
+        deliveries.insert(update.id.clone(), delivery);
+        Ok(update)
+    }
+
+    /// Tracks a message left in the outbox by a previous run as failed, so it can be retried.
+    pub fn restore(&self, room: DocumentId, message: MessageWithMetaData) -> Result<(), String> {
+        let mut deliveries = self.deliveries.lock().map_err(|e| e.to_string())?;
+        let delivery = Delivery {
+            room,
+            message,
+            state: DeliveryState::Failed {
+                reason: "Not sent before the app was closed".to_string(),
+            },
+        };
+        deliveries.insert(delivery.message.message.id().to_string(), delivery);
+        Ok(())
+    }
+
+    /// The state of a tracked message, `None` once it is synced or when it is not ours.
+    pub fn state(&self, id: &str) -> Result<Option<DeliveryState>, String> {
+        let deliveries = self.deliveries.lock().map_err(|e| e.to_string())?;
+        Ok(deliveries.get(id).map(|delivery| delivery.state.clone()))
+    }
+
+    /// Marks a message as synced once a peer of its room acknowledged receiving it, returning the update to emit.
+    /// Acknowledgements for messages of another room are ignored.
+    pub fn acknowledge(
+        &self,
+        room: &DocumentId,
+        id: &str,
+    ) -> Result<Option<MessageDelivery>, String> {
+        let is_room = {
+            let deliveries = self.deliveries.lock().map_err(|e| e.to_string())?;
+            deliveries
+                .get(id)
+                .is_some_and(|delivery| &delivery.room == room)
+        };
+        if !is_room {
+            return Ok(None);
+        }
+        self.advance(id, DeliveryState::Synced)
+    }
+
+    /// Moves a tracked message to a later state, returning the update to emit when it changed.
+    pub fn advance(
+        &self,
+        id: &str,
+        state: DeliveryState,
+    ) -> Result<Option<MessageDelivery>, String> {
+        let mut deliveries = self.deliveries.lock().map_err(|e| e.to_string())?;
+        let Some(delivery) = deliveries.get_mut(id) else {
+            return Ok(None);
+        };
+        if !can_advance(&delivery.state, &state) {
+            return Ok(None);
+        }
+        delivery.state = state;
+        let update = delivery.update();
+        if update.state == DeliveryState::Synced {
+            deliveries.remove(id);
+        }
+        Ok(Some(update))
+    }
+
+    /// Stops tracking the messages of a room, e.g. when leaving it.
+    pub fn forget(&self, room: &DocumentId) -> Result<(), String> {
+        let mut deliveries = self.deliveries.lock().map_err(|e| e.to_string())?;
+        deliveries.retain(|_, delivery| &delivery.room != room);
+        Ok(())
+    }
+
+    /// Takes a failed message back out for another attempt.
+    pub fn retry(&self, id: &str) -> Result<(DocumentId, MessageWithMetaData), String> {
+        let mut deliveries = self.deliveries.lock().map_err(|e| e.to_string())?;
+        match deliveries.get(id) {
+            Some(Delivery {
+                state: DeliveryState::Failed { .. },
+                ..
+            }) => {}
+            Some(_) => return Err("Message is still being delivered".to_string()),
+            None => return Err("Unknown message".to_string()),
+        }
+        let delivery = deliveries.remove(id).ok_or("Unknown message".to_string())?;
+        Ok((delivery.room, delivery.message))
+    }
+}
@@ -0,0 +1,238 @@
+//! Ephemeral signals (typing indicators, presence pings and similar), read markers, invite redemptions, delivery
+//! acknowledgements and offline notices exchanged directly between the peers of a room over their own iroh ALPN,
+//! alongside beelay.  Unlike messages they are never added to the room's document, so they don't bloat its permanent
+//! commit history.  Every envelope carries an expiry, envelopes arriving after it are dropped and the frontend
+//! forgets signals once it passed.  Envelopes travel on unidirectional streams, the same connections also carry
+//! requests for attachment blobs on bidirectional ones.  One connection is kept per peer and shared by everything
+//! sent to it.
+use super::blobs::{self, BlobRequest, BlobStore};
+use crate::SignalKind;
+use beelay_protocol::NodeId;
+use chrono::{DateTime, TimeDelta, Utc};
+use iroh::endpoint::Connection;
+use iroh::protocol::{AcceptError, ProtocolHandler};
+use iroh::{Endpoint, NodeAddr};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::async_runtime::Sender;
+
+pub const ALPN: &[u8] = b"beelay-chat/ephemeral/0";
+
+// The handler is registered with the router of beelay_protocol, which only accepts it when both build on the same
+// iroh.  Two iroh versions would make their node ids distinct types and fail the build here instead.
+const _: fn(beelay_protocol::NodeId) -> iroh::NodeId = |node_id| node_id;
+
+/// Envelopes are a few hundred bytes at most, anything larger is not an envelope of ours.
+const MAX_ENVELOPE_SIZE: usize = 4096;
+
+/// How many message ids a `Received` envelope carries at most, so it stays within `MAX_ENVELOPE_SIZE`.
+pub const MAX_RECEIVED_IDS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Payload {
+    Signal(SignalKind),
+    /// The id of the last message the sending peer read in the room.
+    Read {
+        message_id: String,
+    },
+    /// Redeems an invite with the node that issued it, sent by a peer joining a room through an invite ticket.
+    /// The peer doesn't know the room yet, so the envelope's room is empty.
+    Redeem {
+        invite_id: String,
+        contact_card: String,
+    },
+    /// The node that issued an invite accepted it, with the serialized beelay ticket of the room to join.
+    Admitted {
+        invite_id: String,
+        ticket: String,
+    },
+    /// The node that issued an invite refused to let the sending peer join through it.
+    InviteRejected {
+        invite_id: String,
+        reason: String,
+    },
+    /// The sending peer received these messages authored by the receiving node.
+    Received {
+        message_ids: Vec<String>,
+    },
+    /// The sending peer left the room or is shutting down.
+    Offline,
+}
+
+impl Payload {
+    /// How long the payload stays relevant.  Read markers are sent again whenever a peer pings the room, so a
+    /// stale one is not worth delivering either.
+    fn ttl(&self) -> TimeDelta {
+        match self {
+            Payload::Signal(SignalKind::Typing | SignalKind::StoppedTyping) => {
+                TimeDelta::seconds(5)
+            }
+            Payload::Signal(SignalKind::Ping)
+            | Payload::Read { .. }
+            | Payload::Redeem { .. }
+            | Payload::Admitted { .. }
+            | Payload::InviteRejected { .. }
+            | Payload::Received { .. }
+            | Payload::Offline => TimeDelta::seconds(60),
+        }
+    }
+}
+
+/// An envelope on the wire, the sending peer is taken from the connection rather than trusted from the payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub room: String,
+    pub payload: Payload,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Envelope {
+    pub fn new(room: String, payload: Payload) -> Self {
+        Self {
+            expires_at: Utc::now() + payload.ttl(),
+            room,
+            payload,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
+/// Accepts the envelopes peers send to this node and forwards them with the id of the sending peer, and serves
+/// the blobs peers request from the store once the app allowed them.
+#[derive(Debug, Clone)]
+pub struct EphemeralHandler {
+    tx: Sender<(NodeId, Envelope)>,
+    blobs: Arc<BlobStore>,
+    blob_requests: Sender<BlobRequest>,
+}
+
+impl EphemeralHandler {
+    pub fn new(
+        tx: Sender<(NodeId, Envelope)>,
+        blobs: Arc<BlobStore>,
+        blob_requests: Sender<BlobRequest>,
+    ) -> Self {
+        Self {
+            tx,
+            blobs,
+            blob_requests,
+        }
+    }
+}
+
+impl ProtocolHandler for EphemeralHandler {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let peer = connection.remote_node_id().map_err(AcceptError::from_err)?;
+        // one blob request per bidirectional stream, served alongside the envelopes
+        let requests = connection.clone();
+        let store = self.blobs.clone();
+        let blob_requests = self.blob_requests.clone();
+        tauri::async_runtime::spawn(async move {
+            while let Ok((send, recv)) = requests.accept_bi().await {
+                if let Err(e) = blobs::serve(&store, &blob_requests, peer, send, recv).await {
+                    eprintln!("Failed to serve a blob to {}: {}", peer, e);
+                }
+            }
+        });
+        // one envelope per unidirectional stream, until the peer closes the connection
+        while let Ok(mut recv) = connection.accept_uni().await {
+            let Ok(data) = recv.read_to_end(MAX_ENVELOPE_SIZE).await else {
+                continue;
+            };
+            match postcard::from_bytes::<Envelope>(&data) {
+                Ok(envelope) if !envelope.is_expired() => {
+                    if self.tx.send((peer, envelope)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Skipping undecodable envelope from {}: {}", peer, e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Connections to peers on this ALPN, opened on first use and kept until they close, so envelopes and blob
+/// requests sent to the same peer share one connection and only open a stream each.
+#[derive(Debug, Clone)]
+pub struct PeerConnections {
+    endpoint: Endpoint,
+    connections: Arc<Mutex<HashMap<NodeId, Connection>>>,
+}
+
+impl PeerConnections {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self {
+            endpoint,
+            connections: Default::default(),
+        }
+    }
+
+    /// The connection to a peer, connecting again when there is none yet or the previous one was closed.  Peers
+    /// this node has not been connected to yet are reached through their address.
+    pub async fn connection(&self, peer: impl Into<NodeAddr>) -> Result<Connection, String> {
+        let addr = peer.into();
+        let peer = addr.node_id;
+        let cached = {
+            let connections = self.connections.lock().map_err(|e| e.to_string())?;
+            connections.get(&peer).cloned()
+        };
+        if let Some(connection) = cached
+            && connection.close_reason().is_none()
+        {
+            return Ok(connection);
+        }
+        let connection = self
+            .endpoint
+            .connect(addr, ALPN)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut connections = self.connections.lock().map_err(|e| e.to_string())?;
+        connections.insert(peer, connection.clone());
+        Ok(connection)
+    }
+
+    /// Drops the connection to a peer after it failed, unless it was replaced meanwhile.
+    pub fn forget(&self, peer: NodeId, connection: &Connection) -> Result<(), String> {
+        let mut connections = self.connections.lock().map_err(|e| e.to_string())?;
+        if connections
+            .get(&peer)
+            .is_some_and(|cached| cached.stable_id() == connection.stable_id())
+        {
+            connections.remove(&peer);
+        }
+        Ok(())
+    }
+
+    /// Sends an envelope to a peer.  Envelopes are best effort, there is no acknowledgement.  A cached connection
+    /// may have been lost since it was last used, so sending is tried once more on a fresh one.
+    pub async fn send(&self, peer: impl Into<NodeAddr>, envelope: &Envelope) -> Result<(), String> {
+        let addr = peer.into();
+        let peer = addr.node_id;
+        let data = postcard::to_allocvec(envelope).map_err(|e| e.to_string())?;
+        let connection = self.connection(addr.clone()).await?;
+        if send_on(&connection, &data).await.is_ok() {
+            return Ok(());
+        }
+        self.forget(peer, &connection)?;
+        let connection = self.connection(addr).await?;
+        let result = send_on(&connection, &data).await;
+        if result.is_err() {
+            self.forget(peer, &connection)?;
+        }
+        result
+    }
+}
+
+async fn send_on(connection: &Connection, data: &[u8]) -> Result<(), String> {
+    let mut send = connection.open_uni().await.map_err(|e| e.to_string())?;
+    send.write_all(data).await.map_err(|e| e.to_string())?;
+    send.finish().map_err(|e| e.to_string())?;
+    send.stopped().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
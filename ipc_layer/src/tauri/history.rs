@@ -0,0 +1,437 @@
+//! Local chat history, persisted in a redb database under the app data directory so conversations
+//! survive app restarts.  Every `MessageWithMetaData` sent or received is recorded per document, edits and
+//! deletions apart from the conversation, by the message they revise.  Profile updates are only recorded by id,
+//! the profile store keeps the profiles themselves.
+use super::MessageWithMetaData;
+use crate::{HistoryCursor, HybridTimestamp};
+use beelay_protocol::DocumentId;
+use redb::{Database, ReadableTable, TableDefinition};
+use std::ops::Bound;
+use std::path::Path;
+
+/// (room, clock milliseconds, clock counter, message id) -> postcard encoded `MessageWithMetaData`.
+/// Keys follow the hybrid logical clock order, the id breaks ties between peers.
+const MESSAGES: TableDefinition<(&str, i64, u32, &str), &[u8]> =
+    TableDefinition::new("messages_by_clock");
+
+/// (room, message or revision id) -> clock milliseconds and counter it was recorded with, to find messages by id.
+/// Every id is recorded once, so a message replayed with another clock is not stored twice.
+const MESSAGE_CLOCKS: TableDefinition<(&str, &str), (i64, u32)> =
+    TableDefinition::new("message_clocks");
+
+/// (room, revised message id, clock milliseconds, clock counter, revision id) -> postcard encoded
+/// `MessageWithMetaData`.  Revisions may arrive before the message they revise, they are kept either way.
+const REVISIONS: TableDefinition<(&str, &str, i64, u32, &str), &[u8]> =
+    TableDefinition::new("revisions");
+
+/// (room, message or revision id) -> id of the message it revises, empty for anything else.  Ids recorded from a
+/// copy failing signature verification, which the first verified copy replaces so a forged copy arriving first
+/// can't shadow the genuine message.
+const UNVERIFIED: TableDefinition<(&str, &str), &str> = TableDefinition::new("unverified_messages");
+
+pub struct ChatHistory {
+    db: Database,
+}
+
+impl ChatHistory {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the table up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(MESSAGES).map_err(|e| e.to_string())?;
+        txn.open_table(MESSAGE_CLOCKS).map_err(|e| e.to_string())?;
+        txn.open_table(REVISIONS).map_err(|e| e.to_string())?;
+        txn.open_table(UNVERIFIED).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(Self { db })
+    }
+
+    /// Records a message for the given document.  Returns `false` when a message with the same id was already
+    /// recorded, whatever its clock, recording it again is a no-op.  The exception is a verified copy of a message
+    /// only recorded from a copy failing verification, which replaces it.
+    pub fn record(&self, room: &DocumentId, message: &MessageWithMetaData) -> Result<bool, String> {
+        self.record_in(&room.to_string(), message)
+    }
+
+    fn record_in(&self, room: &str, message: &MessageWithMetaData) -> Result<bool, String> {
+        let data = postcard::to_allocvec(message).map_err(|e| e.to_string())?;
+        let verified = message.verify_in(room);
+        let (millis, counter, id) = (
+            message.clock.millis,
+            message.clock.counter,
+            message.message.id(),
+        );
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut clocks = txn.open_table(MESSAGE_CLOCKS).map_err(|e| e.to_string())?;
+            let mut unverified = txn.open_table(UNVERIFIED).map_err(|e| e.to_string())?;
+            let mut messages = txn.open_table(MESSAGES).map_err(|e| e.to_string())?;
+            let mut revisions = txn.open_table(REVISIONS).map_err(|e| e.to_string())?;
+            let recorded = clocks
+                .get((room, id))
+                .map_err(|e| e.to_string())?
+                .map(|clock| clock.value());
+            if let Some((recorded_millis, recorded_counter)) = recorded {
+                if !verified {
+                    return Ok(false);
+                }
+                let Some(target) = unverified
+                    .remove((room, id))
+                    .map_err(|e| e.to_string())?
+                    .map(|target| target.value().to_string())
+                else {
+                    return Ok(false);
+                };
+                messages
+                    .remove((room, recorded_millis, recorded_counter, id))
+                    .map_err(|e| e.to_string())?;
+                revisions
+                    .remove((room, target.as_str(), recorded_millis, recorded_counter, id))
+                    .map_err(|e| e.to_string())?;
+            }
+            clocks
+                .insert((room, id), (millis, counter))
+                .map_err(|e| e.to_string())?;
+            if !verified {
+                let target = message
+                    .message
+                    .revision()
+                    .map_or("", |revision| revision.target.as_str());
+                unverified
+                    .insert((room, id), target)
+                    .map_err(|e| e.to_string())?;
+            }
+            match (message.message.revision(), message.message.profile()) {
+                // only the id of a profile update is kept, so it is recognized when it is synced again
+                (_, Some(_)) => {}
+                (Some(revision), None) => {
+                    let key = (room, revision.target.as_str(), millis, counter, id);
+                    revisions
+                        .insert(key, data.as_slice())
+                        .map_err(|e| e.to_string())?;
+                }
+                (None, None) => {
+                    messages
+                        .insert((room, millis, counter, id), data.as_slice())
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    /// Deletes the messages recorded for a room, e.g. when leaving it.
+    pub fn forget(&self, room: &DocumentId) -> Result<(), String> {
+        let room = room.to_string();
+        let room = room.as_str();
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(MESSAGES).map_err(|e| e.to_string())?;
+            table
+                // message ids are uuids, so they all sort before the last char
+                .retain_in(
+                    (room, i64::MIN, 0, "")..=(room, i64::MAX, u32::MAX, "\u{10ffff}"),
+                    |_, _| false,
+                )
+                .map_err(|e| e.to_string())?;
+            let mut clocks = txn.open_table(MESSAGE_CLOCKS).map_err(|e| e.to_string())?;
+            clocks
+                .retain_in((room, "")..=(room, "\u{10ffff}"), |_, _| false)
+                .map_err(|e| e.to_string())?;
+            let mut revisions = txn.open_table(REVISIONS).map_err(|e| e.to_string())?;
+            revisions
+                .retain_in(
+                    (room, "", i64::MIN, 0, "")
+                        ..=(room, "\u{10ffff}", i64::MAX, u32::MAX, "\u{10ffff}"),
+                    |_, _| false,
+                )
+                .map_err(|e| e.to_string())?;
+            let mut unverified = txn.open_table(UNVERIFIED).map_err(|e| e.to_string())?;
+            unverified
+                .retain_in((room, "")..=(room, "\u{10ffff}"), |_, _| false)
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    /// Whether a message or revision recorded for a room carries an attachment with the given hash.  Entries
+    /// that don't decode are skipped.
+    pub fn references(&self, room: &DocumentId, hash: &str) -> Result<bool, String> {
+        let room = room.to_string();
+        let room = room.as_str();
+        let carries = |data: &[u8]| {
+            postcard::from_bytes::<MessageWithMetaData>(data).is_ok_and(|message| {
+                message
+                    .message
+                    .attachments()
+                    .iter()
+                    .any(|attachment| attachment.hash == hash)
+            })
+        };
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(MESSAGES).map_err(|e| e.to_string())?;
+        for entry in table
+            .range((room, i64::MIN, 0, "")..=(room, i64::MAX, u32::MAX, "\u{10ffff}"))
+            .map_err(|e| e.to_string())?
+        {
+            let (_, data) = entry.map_err(|e| e.to_string())?;
+            if carries(data.value()) {
+                return Ok(true);
+            }
+        }
+        let revisions = txn.open_table(REVISIONS).map_err(|e| e.to_string())?;
+        for entry in revisions
+            .range(
+                (room, "", i64::MIN, 0, "")
+                    ..=(room, "\u{10ffff}", i64::MAX, u32::MAX, "\u{10ffff}"),
+            )
+            .map_err(|e| e.to_string())?
+        {
+            let (_, data) = entry.map_err(|e| e.to_string())?;
+            if carries(data.value()) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The message of a room with the given id, `None` for revisions and messages not recorded yet.
+    pub fn get(&self, room: &DocumentId, id: &str) -> Result<Option<MessageWithMetaData>, String> {
+        let room = room.to_string();
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let clocks = txn.open_table(MESSAGE_CLOCKS).map_err(|e| e.to_string())?;
+        let Some(clock) = clocks.get((room.as_str(), id)).map_err(|e| e.to_string())? else {
+            return Ok(None);
+        };
+        let (millis, counter) = clock.value();
+        let table = txn.open_table(MESSAGES).map_err(|e| e.to_string())?;
+        table
+            .get((room.as_str(), millis, counter, id))
+            .map_err(|e| e.to_string())?
+            .map(|data| postcard::from_bytes(data.value()).map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    /// The clock a message or revision of a room was recorded with, `None` when it is not recorded yet.
+    pub fn clock(&self, room: &DocumentId, id: &str) -> Result<Option<HybridTimestamp>, String> {
+        let room = room.to_string();
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let clocks = txn.open_table(MESSAGE_CLOCKS).map_err(|e| e.to_string())?;
+        Ok(clocks
+            .get((room.as_str(), id))
+            .map_err(|e| e.to_string())?
+            .map(|clock| {
+                let (millis, counter) = clock.value();
+                HybridTimestamp { millis, counter }
+            }))
+    }
+
+    /// The revisions recorded for a message, in clock order.  Revisions that don't decode are skipped.
+    pub fn revisions(
+        &self,
+        room: &DocumentId,
+        target: &str,
+    ) -> Result<Vec<MessageWithMetaData>, String> {
+        let room = room.to_string();
+        let room = room.as_str();
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(REVISIONS).map_err(|e| e.to_string())?;
+        table
+            .range(
+                (room, target, i64::MIN, 0, "")..=(room, target, i64::MAX, u32::MAX, "\u{10ffff}"),
+            )
+            .map_err(|e| e.to_string())?
+            .map(|entry| {
+                let (_, data) = entry.map_err(|e| e.to_string())?;
+                Ok(postcard::from_bytes::<MessageWithMetaData>(data.value()).ok())
+            })
+            .filter_map(Result::transpose)
+            .collect()
+    }
+
+    /// The ids of the messages of a room that have revisions recorded, whether or not the messages are.
+    pub fn revised(&self, room: &DocumentId) -> Result<Vec<String>, String> {
+        let room = room.to_string();
+        let room = room.as_str();
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(REVISIONS).map_err(|e| e.to_string())?;
+        let mut targets = Vec::<String>::new();
+        for entry in table
+            .range(
+                (room, "", i64::MIN, 0, "")
+                    ..=(room, "\u{10ffff}", i64::MAX, u32::MAX, "\u{10ffff}"),
+            )
+            .map_err(|e| e.to_string())?
+        {
+            let (key, _) = entry.map_err(|e| e.to_string())?;
+            let (_, target, ..) = key.value();
+            // keys are ordered by target, so its revisions are next to each other
+            if targets.last().is_none_or(|last| last != target) {
+                targets.push(target.to_string());
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Loads up to `limit` of the most recent messages of a room ordered strictly before the `before` cursor
+    /// (or the newest ones when `None`), returned oldest first.
+    pub fn load(
+        &self,
+        room: &str,
+        before: Option<&HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<MessageWithMetaData>, String> {
+        let end = match before {
+            Some(cursor) => Bound::Excluded((
+                room,
+                cursor.clock.millis,
+                cursor.clock.counter,
+                cursor.id.as_str(),
+            )),
+            None => Bound::Included((room, i64::MAX, u32::MAX, "\u{10ffff}")),
+        };
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(MESSAGES).map_err(|e| e.to_string())?;
+        let mut messages = table
+            .range((Bound::Included((room, i64::MIN, 0, "")), end))
+            .map_err(|e| e.to_string())?
+            .rev()
+            .take(limit)
+            .map(|entry| {
+                let (_, data) = entry.map_err(|e| e.to_string())?;
+                postcard::from_bytes::<MessageWithMetaData>(data.value()).map_err(|e| e.to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        messages.reverse();
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::identity::IdentityKeys;
+    use super::*;
+    use crate::Message;
+
+    const ROOM: &str = "room";
+
+    fn history(name: &str) -> ChatHistory {
+        let dir =
+            std::env::temp_dir().join(format!("history-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        ChatHistory::open(dir.join("history.redb")).unwrap()
+    }
+
+    fn message(id: &str, millis: i64, counter: u32) -> MessageWithMetaData {
+        let mut message = Message::new(id.to_string());
+        message.id = id.to_string();
+        let secret_key = IdentityKeys::generate().node_secret_key();
+        MessageWithMetaData::signed(
+            ROOM,
+            message,
+            &secret_key,
+            HybridTimestamp { millis, counter },
+        )
+        .unwrap()
+    }
+
+    fn ids(messages: &[MessageWithMetaData]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message.message.id())
+            .collect()
+    }
+
+    fn cursor(message: &MessageWithMetaData) -> HistoryCursor {
+        HistoryCursor {
+            clock: message.clock,
+            id: message.message.id().to_string(),
+        }
+    }
+
+    #[test]
+    fn messages_follow_the_clock_and_ties_the_id() {
+        let history = history("order");
+        for (id, millis, counter) in [("b", 10, 0), ("z", 10, 1), ("a", 10, 0), ("c", 9, 5)] {
+            assert!(
+                history
+                    .record_in(ROOM, &message(id, millis, counter))
+                    .unwrap()
+            );
+        }
+        assert_eq!(
+            ids(&history.load(ROOM, None, 10).unwrap()),
+            ["c", "a", "b", "z"]
+        );
+    }
+
+    #[test]
+    fn pages_end_strictly_before_the_cursor() {
+        let history = history("paging");
+        for (id, millis, counter) in [
+            ("a", 10, 0),
+            ("b", 10, 0),
+            ("c", 10, 0),
+            ("d", 10, 1),
+            ("e", 11, 0),
+        ] {
+            history
+                .record_in(ROOM, &message(id, millis, counter))
+                .unwrap();
+        }
+        let newest = history.load(ROOM, None, 2).unwrap();
+        assert_eq!(ids(&newest), ["d", "e"]);
+        // messages sharing the clock of the cursor are split by id, none is skipped or repeated
+        let older = history.load(ROOM, Some(&cursor(&newest[0])), 2).unwrap();
+        assert_eq!(ids(&older), ["b", "c"]);
+        let oldest = history.load(ROOM, Some(&cursor(&older[0])), 2).unwrap();
+        assert_eq!(ids(&oldest), ["a"]);
+        assert!(
+            history
+                .load(ROOM, Some(&cursor(&oldest[0])), 2)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn verified_copies_replace_forged_ones() {
+        let history = history("forged");
+        let genuine = message("a", 10, 0);
+        let mut forged = genuine.clone();
+        forged.message.text = "forged".to_string();
+        forged.clock.millis = 20;
+        assert!(history.record_in(ROOM, &forged).unwrap());
+        // another forgery doesn't replace the first one
+        assert!(!history.record_in(ROOM, &forged).unwrap());
+        assert!(history.record_in(ROOM, &genuine).unwrap());
+        let messages = history.load(ROOM, None, 10).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message.text, genuine.message.text);
+        assert_eq!(messages[0].clock, genuine.clock);
+        // nothing replaces the genuine message
+        assert!(!history.record_in(ROOM, &forged).unwrap());
+        assert!(!history.record_in(ROOM, &genuine).unwrap());
+    }
+
+    #[test]
+    fn messages_are_recorded_once_whatever_their_clock() {
+        let history = history("dedup");
+        assert!(history.record_in(ROOM, &message("a", 10, 0)).unwrap());
+        assert!(!history.record_in(ROOM, &message("a", 10, 0)).unwrap());
+        assert!(!history.record_in(ROOM, &message("a", 20, 0)).unwrap());
+        let messages = history.load(ROOM, None, 10).unwrap();
+        assert_eq!(ids(&messages), ["a"]);
+        assert_eq!(
+            messages[0].clock,
+            HybridTimestamp {
+                millis: 10,
+                counter: 0
+            }
+        );
+        // other rooms keep their own history
+        assert!(history.load("other room", None, 10).unwrap().is_empty());
+    }
+}
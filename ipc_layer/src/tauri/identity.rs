@@ -0,0 +1,244 @@
+//! Stable node identity.  The iroh node secret key and the keyhive signing key are generated once and stored
+//! in the app data directory, so the `NodeId` (and every ticket or QR code shared with it) survives restarts.
+//! The stored keys can optionally be encrypted with a passphrase (argon2 key derivation + XChaCha20Poly1305).
+use beelay_protocol::{NodeId, SecretKey, SigningKey};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::async_runtime::Sender;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct IdentityKeys {
+    node_secret: [u8; 32],
+    keyhive_secret: [u8; 32],
+}
+
+impl IdentityKeys {
+    pub fn generate() -> Self {
+        let mut keys = Self {
+            node_secret: [0; 32],
+            keyhive_secret: [0; 32],
+        };
+        OsRng.fill_bytes(&mut keys.node_secret);
+        OsRng.fill_bytes(&mut keys.keyhive_secret);
+        keys
+    }
+
+    /// The iroh secret key the node id is derived from.
+    pub fn node_secret_key(&self) -> SecretKey {
+        SecretKey::from_bytes(&self.node_secret)
+    }
+
+    /// The key this node signs keyhive operations with.
+    pub fn keyhive_signing_key(&self) -> SigningKey {
+        SigningKey::from_bytes(&self.keyhive_secret)
+    }
+}
+
+/// On-disk representation of the identity file.
+#[derive(Serialize, Deserialize)]
+enum StoredIdentity {
+    Plain(IdentityKeys),
+    Encrypted {
+        salt: [u8; 16],
+        nonce: Vec<u8>,
+        ciphertext: Vec<u8>,
+    },
+}
+
+/// Replaces the identity file without ever leaving a truncated one behind: the new contents are written and synced
+/// to a file next to it, which is then renamed over it.  The file is only readable by its owner, without a
+/// passphrase it holds the plain keys.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<(), String> {
+    let partial = path.with_extension("partial");
+    // a leftover of an interrupted write would keep its permissions, the mode only applies to new files
+    if partial.exists() {
+        fs::remove_file(&partial).map_err(|e| e.to_string())?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&partial).map_err(|e| e.to_string())?;
+    file.write_all(data).map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())?;
+    fs::rename(&partial, path).map_err(|e| e.to_string())?;
+    // the rename is only durable once the directory entry is
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        fs::File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, String> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+#[derive(Clone)]
+pub struct IdentityStore {
+    path: PathBuf,
+}
+
+impl IdentityStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn read(&self) -> Result<Option<StoredIdentity>, String> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read(&self.path).map_err(|e| e.to_string())?;
+        postcard::from_bytes(&data)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Whether a passphrase is needed to load the stored identity.
+    pub fn is_encrypted(&self) -> Result<bool, String> {
+        Ok(matches!(
+            self.read()?,
+            Some(StoredIdentity::Encrypted { .. })
+        ))
+    }
+
+    /// Loads the stored identity, generating and saving a new one on first launch.
+    pub fn load_or_generate(&self, passphrase: Option<&str>) -> Result<IdentityKeys, String> {
+        match self.read()? {
+            Some(StoredIdentity::Plain(keys)) => Ok(keys),
+            Some(StoredIdentity::Encrypted {
+                salt,
+                nonce,
+                ciphertext,
+            }) => {
+                let passphrase =
+                    passphrase.ok_or("A passphrase is required to unlock the identity")?;
+                let data = cipher(passphrase, &salt)?
+                    .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+                    .map_err(|_| "Wrong passphrase".to_string())?;
+                postcard::from_bytes(&data).map_err(|e| e.to_string())
+            }
+            None => {
+                let keys = IdentityKeys::generate();
+                self.save(&keys, passphrase)?;
+                Ok(keys)
+            }
+        }
+    }
+
+    /// Stores the identity, encrypted when a passphrase is given.
+    pub fn save(&self, keys: &IdentityKeys, passphrase: Option<&str>) -> Result<(), String> {
+        let stored = match passphrase {
+            None => StoredIdentity::Plain(keys.clone()),
+            Some(passphrase) => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+                let data = postcard::to_allocvec(keys).map_err(|e| e.to_string())?;
+                let ciphertext = cipher(passphrase, &salt)?
+                    .encrypt(&nonce, data.as_slice())
+                    .map_err(|e| e.to_string())?;
+                StoredIdentity::Encrypted {
+                    salt,
+                    nonce: nonce.to_vec(),
+                    ciphertext,
+                }
+            }
+        };
+        let data = postcard::to_allocvec(&stored).map_err(|e| e.to_string())?;
+        write_atomically(&self.path, &data)
+    }
+}
+
+/// Hands the unlocked identity over to the backend setup when the stored identity is passphrase protected.
+/// Managed by tauri before the node starts, so the UI can unlock it while the rest of `AppData` doesn't exist yet.
+pub struct IdentityUnlock {
+    store: IdentityStore,
+    unlocked: Sender<IdentityKeys>,
+}
+
+impl IdentityUnlock {
+    pub fn new(store: IdentityStore, unlocked: Sender<IdentityKeys>) -> Self {
+        Self { store, unlocked }
+    }
+
+    pub fn is_locked(&self) -> Result<bool, String> {
+        // the sender is closed once setup received the identity
+        Ok(!self.unlocked.is_closed() && self.store.is_encrypted()?)
+    }
+
+    pub async fn unlock(&self, passphrase: &str) -> Result<(), String> {
+        let keys = self.store.load_or_generate(Some(passphrase))?;
+        self.unlocked.send(keys).await.map_err(|e| e.to_string())
+    }
+}
+
+/// A short, human comparable fingerprint of a node id, e.g. `k3xm-9a2f-qq7d-1bzp`.
+pub fn fingerprint(node_id: &NodeId) -> String {
+    node_id
+        .to_string()
+        .chars()
+        .take(16)
+        .collect::<Vec<_>>()
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> IdentityStore {
+        let dir =
+            std::env::temp_dir().join(format!("identity-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        IdentityStore::new(dir.join("identity.bin"))
+    }
+
+    #[test]
+    fn saved_identity_replaces_the_previous_one() {
+        let store = store("replace");
+        let first = store.load_or_generate(None).unwrap();
+        let second = IdentityKeys::generate();
+        store.save(&second, None).unwrap();
+        let loaded = store.load_or_generate(None).unwrap();
+        assert_eq!(loaded.node_secret, second.node_secret);
+        assert_ne!(loaded.node_secret, first.node_secret);
+        assert!(!store.path.with_extension("partial").exists());
+    }
+
+    #[test]
+    fn encrypted_identity_needs_its_passphrase() {
+        let store = store("encrypted");
+        let keys = IdentityKeys::generate();
+        store.save(&keys, Some("secret")).unwrap();
+        assert!(store.is_encrypted().unwrap());
+        assert!(store.load_or_generate(Some("wrong")).is_err());
+        let loaded = store.load_or_generate(Some("secret")).unwrap();
+        assert_eq!(loaded.keyhive_secret, keys.keyhive_secret);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn identity_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let store = store("private");
+        store.load_or_generate(None).unwrap();
+        let mode = fs::metadata(&store.path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,425 @@
+//! Invites into rooms, persisted in a redb database under the app data directory.  The `InviteTicket` this node
+//! hands out only names the invite and how to reach this node, not the room.  A peer joining through it redeems
+//! the invite with the issuing node, which only grants it access to the room and replies with the room's beelay
+//! ticket while the invite is not revoked, has not expired, has uses left and, when bound to an invitee, is
+//! redeemed by that invitee.  Invites this node redeemed are kept in memory until the issuing node replies.
+use super::codes;
+use crate::Invite;
+use beelay_protocol::{NodeId, NodeTicket, Ticket};
+use chrono::{DateTime, Utc};
+use redb::{Database, ReadableTable, TableDefinition};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Invite id -> postcard encoded `Invite`.
+const INVITES: TableDefinition<&str, &[u8]> = TableDefinition::new("invites");
+
+/// Prefix of serialized invite tickets, followed by the format version.
+const TICKET_PREFIX: &str = "chatinvite";
+/// Version 3 replaced the beelay ticket of the room by the node ticket of the inviting node.  Earlier versions
+/// handed out the room before the invite was redeemed and are refused, as are plain beelay tickets.
+const TICKET_VERSION: u32 = 3;
+
+/// Parses an invite ticket in any of its shared forms, refusing invites that expired.  Returns the node ticket of
+/// the inviting node along with the invite.
+pub fn parse_ticket(serialized: &str) -> Result<(NodeTicket, InviteTicket), String> {
+    if serialized.trim().is_empty() {
+        return Err("The ticket is empty".to_string());
+    }
+    let invite = InviteTicket::deserialize(&codes::decode(serialized)?)?;
+    if let Some(expires_at) = invite.expires_at
+        && expires_at <= Utc::now()
+    {
+        return Err("The invite expired".to_string());
+    }
+    let node_ticket = <NodeTicket as Ticket>::deserialize(&invite.node_ticket)
+        .map_err(|e| format!("Malformed ticket: {}", e))?;
+    Ok((node_ticket, invite))
+}
+
+/// Outcome of redeeming an invite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redemption {
+    /// The peer joins the given room through the invite for the first time.
+    Accepted(String),
+    /// The peer redeemed the invite into the given room before, e.g. when its first redemption got lost.
+    Repeated(String),
+    /// The join is refused, for the given reason.
+    Refused(String),
+}
+
+/// The node ticket of an inviting node together with the invite it was issued for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InviteTicket {
+    pub invite_id: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The serialized node ticket of the inviting node.
+    pub node_ticket: String,
+    /// The title of the room, if the inviting node knows it.
+    pub title: Option<String>,
+}
+
+impl InviteTicket {
+    /// `chatinvite3:<invite id>:<expiry in unix milliseconds, empty for none>:<node ticket>:<room title>`,
+    /// the title comes last as it may contain anything.
+    pub fn serialize(&self) -> String {
+        let expires_at = self
+            .expires_at
+            .map(|expires_at| expires_at.timestamp_millis().to_string())
+            .unwrap_or_default();
+        format!(
+            "{TICKET_PREFIX}{TICKET_VERSION}:{}:{}:{}:{}",
+            self.invite_id,
+            expires_at,
+            self.node_ticket,
+            self.title.as_deref().unwrap_or_default()
+        )
+    }
+
+    pub fn deserialize(serialized: &str) -> Result<Self, String> {
+        let (header, rest) = serialized
+            .trim()
+            .split_once(':')
+            .ok_or("Malformed invite ticket".to_string())?;
+        let version = header
+            .strip_prefix(TICKET_PREFIX)
+            .ok_or("Not an invite ticket".to_string())?;
+        let mut parts = match version {
+            "3" => rest.splitn(4, ':'),
+            "1" | "2" => {
+                return Err(
+                    "The invite was issued by an older version of the app, ask for a new one"
+                        .to_string(),
+                );
+            }
+            _ => {
+                return Err(format!(
+                    "Unsupported invite ticket version {}, the app may need an update",
+                    version
+                ));
+            }
+        };
+        let (Some(invite_id), Some(expires_at), Some(node_ticket)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("Malformed invite ticket".to_string());
+        };
+        let title = parts
+            .next()
+            .filter(|title| !title.is_empty())
+            .map(str::to_string);
+        let expires_at = match expires_at {
+            "" => None,
+            millis => Some(
+                millis
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(DateTime::from_timestamp_millis)
+                    .ok_or("Malformed invite expiry".to_string())?,
+            ),
+        };
+        Ok(Self {
+            invite_id: invite_id.to_string(),
+            expires_at,
+            node_ticket: node_ticket.to_string(),
+            title,
+        })
+    }
+}
+
+pub struct InviteStore {
+    db: Database,
+}
+
+impl InviteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the table up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(INVITES).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(Self { db })
+    }
+
+    pub fn save(&self, invite: &Invite) -> Result<(), String> {
+        let data = postcard::to_allocvec(invite).map_err(|e| e.to_string())?;
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(INVITES).map_err(|e| e.to_string())?;
+            table
+                .insert(invite.id.as_str(), data.as_slice())
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Invite>, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(INVITES).map_err(|e| e.to_string())?;
+        table
+            .get(id)
+            .map_err(|e| e.to_string())?
+            .map(|data| postcard::from_bytes(data.value()).map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    /// The invites issued for a room, oldest first.
+    pub fn list(&self, room: &str) -> Result<Vec<Invite>, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(INVITES).map_err(|e| e.to_string())?;
+        let mut invites = table
+            .iter()
+            .map_err(|e| e.to_string())?
+            .map(|entry| {
+                let (_, data) = entry.map_err(|e| e.to_string())?;
+                postcard::from_bytes::<Invite>(data.value()).map_err(|e| e.to_string())
+            })
+            .filter(|invite| invite.as_ref().map_or(true, |invite| invite.room == room))
+            .collect::<Result<Vec<_>, String>>()?;
+        invites.sort_by_key(|invite| invite.created_at);
+        Ok(invites)
+    }
+
+    /// Deletes the invites issued for a room, e.g. when leaving it.
+    pub fn forget(&self, room: &str) -> Result<(), String> {
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(INVITES).map_err(|e| e.to_string())?;
+            table
+                .retain(|_, data| {
+                    // keep what doesn't decode rather than guessing its room
+                    postcard::from_bytes::<Invite>(data).map_or(true, |invite| invite.room != room)
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    pub fn revoke(&self, id: &str) -> Result<Invite, String> {
+        let mut invite = self.get(id)?.ok_or("Unknown invite".to_string())?;
+        invite.revoked = true;
+        self.save(&invite)?;
+        Ok(invite)
+    }
+
+    /// Redeems an invite for a peer, counting a use unless the peer redeemed it before.
+    pub fn redeem(&self, id: &str, peer: &NodeId) -> Result<Redemption, String> {
+        let refused = |reason: &str| Ok(Redemption::Refused(reason.to_string()));
+        let Some(mut invite) = self.get(id)? else {
+            return refused("Unknown invite");
+        };
+        let peer = peer.to_string();
+        if invite.revoked {
+            return refused("The invite was revoked");
+        }
+        if invite.redeemed_by.contains(&peer) {
+            return Ok(Redemption::Repeated(invite.room));
+        }
+        if invite
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return refused("The invite expired");
+        }
+        if invite
+            .max_uses
+            .is_some_and(|max_uses| invite.redeemed_by.len() >= max_uses as usize)
+        {
+            return refused("The invite was used up");
+        }
+        if invite
+            .invitee
+            .as_ref()
+            .is_some_and(|invitee| invitee != &peer)
+        {
+            return refused("The invite is for someone else");
+        }
+        invite.redeemed_by.push(peer);
+        self.save(&invite)?;
+        Ok(Redemption::Accepted(invite.room))
+    }
+
+    /// Gives back the use a redemption counted, when the peer could not be granted access after all.
+    pub fn release(&self, id: &str, peer: &NodeId) -> Result<(), String> {
+        let mut invite = self.get(id)?.ok_or("Unknown invite".to_string())?;
+        let peer = peer.to_string();
+        invite.redeemed_by.retain(|redeemer| redeemer != &peer);
+        self.save(&invite)
+    }
+}
+
+/// An invite this node redeemed and awaits the reply of the issuing node to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingInvite {
+    pub inviter: NodeId,
+    pub title: Option<String>,
+}
+
+/// The invites this node redeemed, by invite id.  Only the node an invite was redeemed with may answer it, so
+/// peers can't make this node join rooms it was never invited into.
+#[derive(Default)]
+pub struct PendingInvites {
+    invites: Mutex<HashMap<String, PendingInvite>>,
+}
+
+impl PendingInvites {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, invite_id: String, invite: PendingInvite) -> Result<(), String> {
+        let mut invites = self.invites.lock().map_err(|e| e.to_string())?;
+        invites.insert(invite_id, invite);
+        Ok(())
+    }
+
+    /// Takes a redeemed invite once `peer` answers it, `None` if it was not redeemed with `peer`.
+    pub fn take(&self, invite_id: &str, peer: &NodeId) -> Result<Option<PendingInvite>, String> {
+        let mut invites = self.invites.lock().map_err(|e| e.to_string())?;
+        if invites
+            .get(invite_id)
+            .is_none_or(|invite| &invite.inviter != peer)
+        {
+            return Ok(None);
+        }
+        Ok(invites.remove(invite_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::identity::IdentityKeys;
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn store(name: &str) -> InviteStore {
+        let dir =
+            std::env::temp_dir().join(format!("invites-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        InviteStore::open(dir.join("invites.redb")).unwrap()
+    }
+
+    fn node() -> NodeId {
+        IdentityKeys::generate().node_secret_key().public()
+    }
+
+    fn invite(
+        store: &InviteStore,
+        expires_at: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+    ) -> Invite {
+        let invite = Invite {
+            id: "invite".to_string(),
+            room: "room".to_string(),
+            created_at: Utc::now(),
+            expires_at,
+            max_uses,
+            invitee: None,
+            redeemed_by: Vec::new(),
+            revoked: false,
+        };
+        store.save(&invite).unwrap();
+        invite
+    }
+
+    fn is_refused(redemption: Redemption) -> bool {
+        matches!(redemption, Redemption::Refused(_))
+    }
+
+    #[test]
+    fn expired_invites_are_refused() {
+        let store = store("expired");
+        invite(&store, Some(Utc::now() - TimeDelta::minutes(1)), None);
+        assert!(is_refused(store.redeem("invite", &node()).unwrap()));
+    }
+
+    #[test]
+    fn used_up_invites_are_refused() {
+        let store = store("used-up");
+        invite(&store, None, Some(1));
+        let (first, second) = (node(), node());
+        assert_eq!(
+            store.redeem("invite", &first).unwrap(),
+            Redemption::Accepted("room".to_string())
+        );
+        assert!(is_refused(store.redeem("invite", &second).unwrap()));
+        // redeeming again doesn't count as another use
+        assert_eq!(
+            store.redeem("invite", &first).unwrap(),
+            Redemption::Repeated("room".to_string())
+        );
+    }
+
+    #[test]
+    fn released_uses_can_be_redeemed_again() {
+        let store = store("released");
+        invite(&store, None, Some(1));
+        let (failed, retried) = (node(), node());
+        store.redeem("invite", &failed).unwrap();
+        // granting access to the first peer failed, the invite is not used up
+        store.release("invite", &failed).unwrap();
+        assert!(store.get("invite").unwrap().unwrap().redeemed_by.is_empty());
+        assert_eq!(
+            store.redeem("invite", &retried).unwrap(),
+            Redemption::Accepted("room".to_string())
+        );
+        assert!(is_refused(store.redeem("invite", &failed).unwrap()));
+    }
+
+    #[test]
+    fn bound_invites_are_only_redeemed_by_their_invitee() {
+        let store = store("bound");
+        let mut bound = invite(&store, None, None);
+        let invitee = node();
+        bound.invitee = Some(invitee.to_string());
+        store.save(&bound).unwrap();
+        assert!(is_refused(store.redeem("invite", &node()).unwrap()));
+        assert_eq!(
+            store.redeem("invite", &invitee).unwrap(),
+            Redemption::Accepted("room".to_string())
+        );
+    }
+
+    #[test]
+    fn revoked_invites_are_refused() {
+        let store = store("revoked");
+        invite(&store, None, None);
+        let peer = node();
+        store.redeem("invite", &peer).unwrap();
+        store.revoke("invite").unwrap();
+        assert!(is_refused(store.redeem("invite", &peer).unwrap()));
+        assert!(is_refused(store.redeem("invite", &node()).unwrap()));
+    }
+
+    #[test]
+    fn tickets_of_earlier_versions_are_refused() {
+        assert!(InviteTicket::deserialize("chatinvite2:invite::beelayticket:Room").is_err());
+        assert!(InviteTicket::deserialize("beelayticket").is_err());
+        let ticket = InviteTicket {
+            invite_id: "invite".to_string(),
+            expires_at: None,
+            node_ticket: "nodeticket".to_string(),
+            title: Some("Room: with colons".to_string()),
+        };
+        assert_eq!(
+            InviteTicket::deserialize(&ticket.serialize()).unwrap(),
+            ticket
+        );
+    }
+
+    #[test]
+    fn only_the_inviter_answers_a_pending_invite() {
+        let pending = PendingInvites::new();
+        let inviter = node();
+        let invite = PendingInvite {
+            inviter,
+            title: None,
+        };
+        pending.add("invite".to_string(), invite.clone()).unwrap();
+        assert_eq!(pending.take("invite", &node()).unwrap(), None);
+        assert_eq!(pending.take("invite", &inviter).unwrap(), Some(invite));
+        assert_eq!(pending.take("invite", &inviter).unwrap(), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! Outbox of the messages this node sends, persisted in a redb database under the app data directory.
+//! Every message is queued before it is committed and only removed once it is committed, so messages typed
+//! before a room's document is discovered or while no peer is connected survive restarts and are flushed
+//! in order once the room is ready.
+use super::MessageWithMetaData;
+use beelay_protocol::DocumentId;
+use redb::{Database, TableDefinition};
+use std::path::Path;
+
+/// (room, clock milliseconds, clock counter, message id) -> postcard encoded `MessageWithMetaData`.
+/// Same key layout as the history, so a room's queue is iterated in the order its messages were sent.
+const QUEUED: TableDefinition<(&str, i64, u32, &str), &[u8]> = TableDefinition::new("outbox");
+
+pub struct Outbox {
+    db: Database,
+}
+
+impl Outbox {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the table up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(QUEUED).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(Self { db })
+    }
+
+    pub fn enqueue(&self, room: &DocumentId, message: &MessageWithMetaData) -> Result<(), String> {
+        let data = postcard::to_allocvec(message).map_err(|e| e.to_string())?;
+        let room = room.to_string();
+        let key = (
+            room.as_str(),
+            message.clock.millis,
+            message.clock.counter,
+            message.message.id(),
+        );
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(QUEUED).map_err(|e| e.to_string())?;
+            table
+                .insert(key, data.as_slice())
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    pub fn remove(&self, room: &DocumentId, message: &MessageWithMetaData) -> Result<(), String> {
+        let room = room.to_string();
+        let key = (
+            room.as_str(),
+            message.clock.millis,
+            message.clock.counter,
+            message.message.id(),
+        );
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(QUEUED).map_err(|e| e.to_string())?;
+            table.remove(key).map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    /// Drops the messages queued for a room, e.g. when leaving it.
+    pub fn clear(&self, room: &DocumentId) -> Result<(), String> {
+        let room = room.to_string();
+        let room = room.as_str();
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(QUEUED).map_err(|e| e.to_string())?;
+            table
+                .retain_in(
+                    (room, i64::MIN, 0, "")..=(room, i64::MAX, u32::MAX, "\u{10ffff}"),
+                    |_, _| false,
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    /// The messages queued for a room, in the order they were sent.
+    pub fn queued(&self, room: &DocumentId) -> Result<Vec<MessageWithMetaData>, String> {
+        let room = room.to_string();
+        let room = room.as_str();
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(QUEUED).map_err(|e| e.to_string())?;
+        table
+            // message ids are uuids, so they all sort before the last char
+            .range((room, i64::MIN, 0, "")..=(room, i64::MAX, u32::MAX, "\u{10ffff}"))
+            .map_err(|e| e.to_string())?
+            .map(|entry| {
+                let (_, data) = entry.map_err(|e| e.to_string())?;
+                postcard::from_bytes::<MessageWithMetaData>(data.value()).map_err(|e| e.to_string())
+            })
+            .collect()
+    }
+}
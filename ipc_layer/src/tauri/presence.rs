@@ -0,0 +1,157 @@
+//! Presence of the peers this node knows of.  A peer is online while connected and active, away while
+//! connected but idle for longer than `AWAY_AFTER`, and offline otherwise.  Activity is the last time a
+//! connection event, an ephemeral signal or a message from the peer was seen, which is also reported as its
+//! last seen time.  Times claimed by the peer are capped at the local clock, so a peer can't stay online by
+//! sending timestamps from the future.
+use crate::Presence;
+use beelay_protocol::NodeId;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// How long a connected peer may stay idle before it is reported as away.
+const AWAY_AFTER: TimeDelta = TimeDelta::minutes(5);
+
+#[derive(Default)]
+struct Seen {
+    connected: bool,
+    last_seen: Option<DateTime<Utc>>,
+    // whether the peer was reported as gone away since it was last active
+    away_reported: bool,
+}
+
+impl Seen {
+    fn active_at(&mut self, at: DateTime<Utc>) {
+        if self.last_seen < Some(at) {
+            self.last_seen = Some(at);
+            self.away_reported = false;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PresenceTracker {
+    peers: Mutex<HashMap<NodeId, Seen>>,
+}
+
+impl PresenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that a connection to the peer was established or is still in use.
+    pub fn connected(&self, peer: NodeId) -> Result<(), String> {
+        let mut peers = self.peers.lock().map_err(|e| e.to_string())?;
+        let seen = peers.entry(peer).or_default();
+        seen.connected = true;
+        seen.active_at(Utc::now());
+        Ok(())
+    }
+
+    pub fn disconnected(&self, peer: NodeId) -> Result<(), String> {
+        let mut peers = self.peers.lock().map_err(|e| e.to_string())?;
+        peers.entry(peer).or_default().connected = false;
+        Ok(())
+    }
+
+    /// Records activity of the peer at the given time, e.g. when a message it sent was received.
+    /// Older activity, like messages synced from history, never moves the last seen time back, and times ahead
+    /// of the local clock count as now.
+    pub fn seen(&self, peer: NodeId, at: DateTime<Utc>) -> Result<(), String> {
+        let mut peers = self.peers.lock().map_err(|e| e.to_string())?;
+        peers.entry(peer).or_default().active_at(at.min(Utc::now()));
+        Ok(())
+    }
+
+    /// The connected peers that went idle for longer than `AWAY_AFTER` since this was last asked, each reported
+    /// once until it is active again.
+    pub fn gone_away(&self) -> Result<Vec<NodeId>, String> {
+        self.gone_away_at(Utc::now())
+    }
+
+    fn gone_away_at(&self, now: DateTime<Utc>) -> Result<Vec<NodeId>, String> {
+        let mut peers = self.peers.lock().map_err(|e| e.to_string())?;
+        Ok(peers
+            .iter_mut()
+            .filter(|(_, seen)| {
+                seen.connected
+                    && !seen.away_reported
+                    && seen
+                        .last_seen
+                        .is_some_and(|last_seen| now - last_seen >= AWAY_AFTER)
+            })
+            .map(|(peer, seen)| {
+                seen.away_reported = true;
+                *peer
+            })
+            .collect())
+    }
+
+    /// The presence of a peer and the last time it was seen, if ever.
+    pub fn presence(&self, peer: &NodeId) -> Result<(Presence, Option<DateTime<Utc>>), String> {
+        let peers = self.peers.lock().map_err(|e| e.to_string())?;
+        let Some(seen) = peers.get(peer) else {
+            return Ok((Presence::Offline, None));
+        };
+        let presence = match seen.last_seen {
+            Some(last_seen) if seen.connected && Utc::now() - last_seen < AWAY_AFTER => {
+                Presence::Online
+            }
+            _ if seen.connected => Presence::Away,
+            _ => Presence::Offline,
+        };
+        Ok((presence, seen.last_seen))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::identity::IdentityKeys;
+    use super::*;
+
+    fn peer() -> NodeId {
+        IdentityKeys::generate().node_secret_key().public()
+    }
+
+    #[test]
+    fn future_activity_counts_as_now() {
+        let presence = PresenceTracker::new();
+        let peer = peer();
+        presence.connected(peer).unwrap();
+        presence
+            .seen(peer, Utc::now() + TimeDelta::days(365))
+            .unwrap();
+        let (_, last_seen) = presence.presence(&peer).unwrap();
+        assert!(last_seen.unwrap() <= Utc::now());
+        // the peer still goes away once idle
+        let later = Utc::now() + AWAY_AFTER + TimeDelta::seconds(1);
+        assert_eq!(presence.gone_away_at(later).unwrap(), [peer]);
+    }
+
+    #[test]
+    fn idle_peers_are_reported_once_until_active_again() {
+        let presence = PresenceTracker::new();
+        let peer = peer();
+        presence.connected(peer).unwrap();
+        assert!(presence.gone_away().unwrap().is_empty());
+        let later = Utc::now() + AWAY_AFTER + TimeDelta::seconds(1);
+        assert_eq!(presence.gone_away_at(later).unwrap(), [peer]);
+        assert!(presence.gone_away_at(later).unwrap().is_empty());
+        presence.seen(peer, Utc::now()).unwrap();
+        let later = Utc::now() + AWAY_AFTER + TimeDelta::seconds(1);
+        assert_eq!(presence.gone_away_at(later).unwrap(), [peer]);
+    }
+
+    #[test]
+    fn disconnected_peers_are_offline_not_away() {
+        let presence = PresenceTracker::new();
+        let peer = peer();
+        assert_eq!(presence.presence(&peer).unwrap(), (Presence::Offline, None));
+        presence.connected(peer).unwrap();
+        assert_eq!(presence.presence(&peer).unwrap().0, Presence::Online);
+        presence.disconnected(peer).unwrap();
+        assert_eq!(presence.presence(&peer).unwrap().0, Presence::Offline);
+        let later = Utc::now() + AWAY_AFTER + TimeDelta::seconds(1);
+        assert!(presence.gone_away_at(later).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+//! Profiles (display name, avatar image, status text) of this node and of the peers it met, persisted in a redb
+//! database under the app data directory.  Every node commits its own profile to the documents of its rooms as a
+//! signed profile update, peers keep the most recent one they received for each `NodeId`, so a profile is bound to
+//! the node that signed it and can't be published on behalf of another node.  The store also remembers which
+//! version of our own profile each room was sent, so it is committed once per room and change.
+use crate::Profile;
+use beelay_protocol::{DocumentId, NodeId};
+use chrono::{DateTime, Utc};
+use redb::{Database, ReadableTable, TableDefinition};
+use std::path::Path;
+
+/// Node id -> postcard encoded `Profile`.
+const PROFILES: TableDefinition<&str, &[u8]> = TableDefinition::new("profiles");
+/// Room id -> `updated_at` (microseconds) of the last version of our own profile committed to the room.
+const PUBLISHED: TableDefinition<&str, i64> = TableDefinition::new("published_profiles");
+
+/// Longest display name accepted.
+pub const MAX_DISPLAY_NAME_LEN: usize = 64;
+/// Longest status text accepted.
+pub const MAX_STATUS_LEN: usize = 140;
+
+pub struct ProfileStore {
+    db: Database,
+}
+
+impl ProfileStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the table up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(PROFILES).map_err(|e| e.to_string())?;
+        txn.open_table(PUBLISHED).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(Self { db })
+    }
+
+    /// Stores the profile of a node unless a more recent one is stored already.  Returns `false` when the
+    /// profile was not newer, so it is only announced once.  A profile dated in the future is stored as updated
+    /// now, so a peer can't keep its later updates from replacing it.
+    pub fn save(&self, node_id: &NodeId, profile: &Profile) -> Result<bool, String> {
+        let profile = &Profile {
+            updated_at: profile.updated_at.min(Utc::now()),
+            ..profile.clone()
+        };
+        if self
+            .get(node_id)?
+            .is_some_and(|stored| stored.updated_at >= profile.updated_at)
+        {
+            return Ok(false);
+        }
+        let data = postcard::to_allocvec(profile).map_err(|e| e.to_string())?;
+        let node_id = node_id.to_string();
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(PROFILES).map_err(|e| e.to_string())?;
+            table
+                .insert(node_id.as_str(), data.as_slice())
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    pub fn get(&self, node_id: &NodeId) -> Result<Option<Profile>, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(PROFILES).map_err(|e| e.to_string())?;
+        table
+            .get(node_id.to_string().as_str())
+            .map_err(|e| e.to_string())?
+            .map(|data| postcard::from_bytes(data.value()).map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    /// Whether the version of our own profile updated at `updated_at` was committed to a room already.
+    pub fn is_published(
+        &self,
+        room: &DocumentId,
+        updated_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(PUBLISHED).map_err(|e| e.to_string())?;
+        Ok(table
+            .get(room.to_string().as_str())
+            .map_err(|e| e.to_string())?
+            .is_some_and(|published| published.value() == updated_at.timestamp_micros()))
+    }
+
+    /// Remembers that the version of our own profile updated at `updated_at` was committed to a room.
+    pub fn mark_published(
+        &self,
+        room: &DocumentId,
+        updated_at: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(PUBLISHED).map_err(|e| e.to_string())?;
+            table
+                .insert(room.to_string().as_str(), updated_at.timestamp_micros())
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    /// Forgets what was published to a room, e.g. when leaving it.
+    pub fn forget(&self, room: &DocumentId) -> Result<(), String> {
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(PUBLISHED).map_err(|e| e.to_string())?;
+            table
+                .remove(room.to_string().as_str())
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    /// All stored profiles, keyed by the node id they belong to.
+    pub fn all(&self) -> Result<Vec<(String, Profile)>, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(PROFILES).map_err(|e| e.to_string())?;
+        table
+            .iter()
+            .map_err(|e| e.to_string())?
+            .map(|entry| {
+                let (node_id, data) = entry.map_err(|e| e.to_string())?;
+                let profile = postcard::from_bytes(data.value()).map_err(|e| e.to_string())?;
+                Ok((node_id.value().to_string(), profile))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,101 @@
+//! Read markers: the id of the last message every peer, this node included, read in each room.  Markers are
+//! exchanged over the ephemeral channel, peers send theirs again whenever the room is pinged.  Every marker keeps
+//! the clock of its message and only moves forward, so a receipt arriving late doesn't move it back.  Markers are
+//! persisted in a redb database under the app data directory, next to the history.
+use crate::HybridTimestamp;
+use beelay_protocol::{DocumentId, NodeId};
+use redb::{Database, ReadableTable, TableDefinition};
+use std::path::Path;
+
+/// (room, peer) -> clock milliseconds, clock counter and id of the last message the peer read.
+const MARKERS: TableDefinition<(&str, &str), (i64, u32, &str)> =
+    TableDefinition::new("read_markers");
+
+pub struct ReceiptStore {
+    db: Database,
+}
+
+impl ReceiptStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the table up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(MARKERS).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(Self { db })
+    }
+
+    /// Moves the read marker of a peer in a room to a message sent at `clock`, returning `false` when the marker
+    /// already was at that message or a later one.
+    pub fn mark(
+        &self,
+        room: &DocumentId,
+        peer: &NodeId,
+        message_id: &str,
+        clock: HybridTimestamp,
+    ) -> Result<bool, String> {
+        let room = room.to_string();
+        let peer = peer.to_string();
+        let key = (room.as_str(), peer.as_str());
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(MARKERS).map_err(|e| e.to_string())?;
+            if let Some(marker) = table.get(key).map_err(|e| e.to_string())? {
+                let (millis, counter, id) = marker.value();
+                // ties are ordered by id, like the history
+                if (millis, counter, id) >= (clock.millis, clock.counter, message_id) {
+                    return Ok(false);
+                }
+            }
+            table
+                .insert(key, (clock.millis, clock.counter, message_id))
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    /// The read marker of a peer in a room, if it read anything yet.
+    pub fn marker(&self, room: &DocumentId, peer: &NodeId) -> Result<Option<String>, String> {
+        let room = room.to_string();
+        let peer = peer.to_string();
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(MARKERS).map_err(|e| e.to_string())?;
+        Ok(table
+            .get((room.as_str(), peer.as_str()))
+            .map_err(|e| e.to_string())?
+            .map(|marker| marker.value().2.to_string()))
+    }
+
+    pub fn forget(&self, room: &DocumentId) -> Result<(), String> {
+        let room = room.to_string();
+        let room = room.as_str();
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(MARKERS).map_err(|e| e.to_string())?;
+            table
+                .retain_in((room, "")..=(room, "\u{10ffff}"), |_, _| false)
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    /// The read markers of all peers in a room.  Markers of peers whose id doesn't parse are skipped.
+    pub fn markers(&self, room: &DocumentId) -> Result<Vec<(NodeId, String)>, String> {
+        let room = room.to_string();
+        let room = room.as_str();
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(MARKERS).map_err(|e| e.to_string())?;
+        let mut markers = Vec::new();
+        for entry in table
+            .range((room, "")..=(room, "\u{10ffff}"))
+            .map_err(|e| e.to_string())?
+        {
+            let (key, marker) = entry.map_err(|e| e.to_string())?;
+            if let Ok(peer) = key.value().1.parse::<NodeId>() {
+                markers.push((peer, marker.value().2.to_string()));
+            }
+        }
+        Ok(markers)
+    }
+}
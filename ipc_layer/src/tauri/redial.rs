@@ -0,0 +1,132 @@
+//! Redialing the peers rooms were joined through.  The beelay ticket every room was joined with is kept in a redb
+//! database under the app data directory, so the node can dial the same peer again once the connection to it
+//! drops or the app restarts.  Redials back off exponentially, from `MIN_BACKOFF` up to `MAX_BACKOFF`, until the
+//! peer is connected again.  Peers that joined rooms created on this node hold the ticket, so they redial us.
+use beelay_protocol::{BeelayTicket, DocumentId, Ticket};
+use redb::{Database, ReadableTable, TableDefinition};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Room id -> serialized beelay ticket the room was joined through.
+const KNOWN_PEERS: TableDefinition<&str, &str> = TableDefinition::new("known_peers");
+
+/// Delay before the first redial, doubled on every failed attempt.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest delay between two redials.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Parses the serialized beelay ticket a room was joined through.
+pub fn parse_beelay_ticket(serialized: &str) -> Result<BeelayTicket, String> {
+    <BeelayTicket as Ticket>::deserialize(serialized)
+        .map_err(|e| format!("Malformed ticket: {}", e))
+}
+
+pub struct KnownPeers {
+    db: Database,
+}
+
+impl KnownPeers {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the table up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(KNOWN_PEERS).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        Ok(Self { db })
+    }
+
+    /// Remembers the ticket a room was joined through, replacing the one it was joined through before.
+    pub fn remember(&self, room: &DocumentId, ticket: &str) -> Result<(), String> {
+        let room = room.to_string();
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(KNOWN_PEERS).map_err(|e| e.to_string())?;
+            table
+                .insert(room.as_str(), ticket)
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    pub fn forget(&self, room: &DocumentId) -> Result<(), String> {
+        let room = room.to_string();
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(KNOWN_PEERS).map_err(|e| e.to_string())?;
+            table.remove(room.as_str()).map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    /// The ticket a room was joined through, `None` for rooms created on this node.
+    pub fn ticket(&self, room: &DocumentId) -> Result<Option<String>, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(KNOWN_PEERS).map_err(|e| e.to_string())?;
+        Ok(table
+            .get(room.to_string().as_str())
+            .map_err(|e| e.to_string())?
+            .map(|ticket| ticket.value().to_string()))
+    }
+
+    /// All rooms joined through a ticket.
+    pub fn rooms(&self) -> Result<Vec<DocumentId>, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(KNOWN_PEERS).map_err(|e| e.to_string())?;
+        table
+            .iter()
+            .map_err(|e| e.to_string())?
+            .map(|entry| {
+                let (room, _) = entry.map_err(|e| e.to_string())?;
+                room.value()
+                    .parse::<DocumentId>()
+                    .map_err(|e| e.to_string())
+            })
+            .collect()
+    }
+}
+
+/// The rooms being redialed, with the number of failed attempts so far.
+#[derive(Default)]
+pub struct RedialTracker {
+    attempts: Mutex<HashMap<DocumentId, u32>>,
+}
+
+impl RedialTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts redialing a room, returning `false` when it is being redialed already.
+    pub fn start(&self, room: DocumentId) -> Result<bool, String> {
+        let mut attempts = self.attempts.lock().map_err(|e| e.to_string())?;
+        if attempts.contains_key(&room) {
+            return Ok(false);
+        }
+        attempts.insert(room, 0);
+        Ok(true)
+    }
+
+    /// How long to wait before the next attempt, `None` once the room is not redialed anymore.
+    pub fn next_attempt(&self, room: &DocumentId) -> Result<Option<Duration>, String> {
+        let mut attempts = self.attempts.lock().map_err(|e| e.to_string())?;
+        Ok(attempts.get_mut(room).map(|attempt| {
+            let backoff = MIN_BACKOFF.saturating_mul(2u32.saturating_pow(*attempt));
+            *attempt = attempt.saturating_add(1);
+            backoff.min(MAX_BACKOFF)
+        }))
+    }
+
+    pub fn is_redialing(&self, room: &DocumentId) -> Result<bool, String> {
+        let attempts = self.attempts.lock().map_err(|e| e.to_string())?;
+        Ok(attempts.contains_key(room))
+    }
+
+    /// Stops redialing a room, e.g. because the peer connected again.
+    pub fn stop(&self, room: &DocumentId) -> Result<(), String> {
+        let mut attempts = self.attempts.lock().map_err(|e| e.to_string())?;
+        attempts.remove(room);
+        Ok(())
+    }
+}
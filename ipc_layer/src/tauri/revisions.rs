@@ -0,0 +1,143 @@
+//! Folding edits and deletions into the messages they revise.  Revisions are commits of their own, so peers may
+//! receive concurrent ones in any order.  Whatever the order, a deletion is final, and otherwise the edit with the
+//! highest hybrid clock wins, ties broken by revision id.  Revisions by other members than the author need an
+//! admin of the room, judged by the room's current access and again whenever it changes, so peers show the same
+//! text once their keyhive views of the room agree.
+use super::MessageWithMetaData;
+use crate::{ChatMessage, RevisionKind};
+
+/// Whether a revision may change the message it references: it must be signed by its author, who either authored
+/// the message too or proves to be one of the room's `admins`, given as keyhive member ids.  Admins may also revise
+/// unverified messages, e.g. to delete ones impersonating a member.
+pub fn may_revise(
+    room: &str,
+    message: &MessageWithMetaData,
+    revision: &MessageWithMetaData,
+    admins: &[String],
+) -> bool {
+    if !revision.verify_in(room) {
+        return false;
+    }
+    if revision.peer_id == message.peer_id && message.verify_in(room) {
+        return true;
+    }
+    revision
+        .admin_in(room)
+        .is_some_and(|member| admins.contains(&member))
+}
+
+/// Applies the authorized revisions of a message to it.
+pub fn apply(message: &mut ChatMessage, revisions: &[MessageWithMetaData]) {
+    let kind = |revision: &MessageWithMetaData| revision.message.revision().map(|r| r.kind);
+    if revisions
+        .iter()
+        .any(|revision| kind(revision) == Some(RevisionKind::Delete))
+    {
+        message.message.text = String::new();
+        message.message.attachments = Vec::new();
+        message.deleted = true;
+        return;
+    }
+    let edit = revisions
+        .iter()
+        .filter(|revision| kind(revision) == Some(RevisionKind::Edit))
+        .max_by(|a, b| (a.clock, a.message.id()).cmp(&(b.clock, b.message.id())));
+    if let Some(edit) = edit {
+        message.message.text = edit.message.text.clone();
+        message.edited_at = Some(*edit.timestamp());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::identity::IdentityKeys;
+    use super::*;
+    use crate::{HybridTimestamp, Message};
+    use beelay_protocol::SecretKey;
+
+    const ROOM: &str = "room";
+
+    fn signed(message: Message, secret_key: &SecretKey, millis: i64) -> MessageWithMetaData {
+        let clock = HybridTimestamp { millis, counter: 0 };
+        MessageWithMetaData::signed(ROOM, message, secret_key, clock).unwrap()
+    }
+
+    fn edit(id: &str, millis: i64, text: &str) -> MessageWithMetaData {
+        let mut edit = Message::edit("message", text.to_string());
+        edit.id = id.to_string();
+        signed(edit, &IdentityKeys::generate().node_secret_key(), millis)
+    }
+
+    fn shown(revisions: &[MessageWithMetaData]) -> ChatMessage {
+        let mut chat_message = ChatMessage {
+            message: Message::new("original".to_string()),
+            outgoing: false,
+            author: String::new(),
+            clock: HybridTimestamp::default(),
+            delivery: None,
+            verified: true,
+            edited_at: None,
+            deleted: false,
+        };
+        apply(&mut chat_message, revisions);
+        chat_message
+    }
+
+    #[test]
+    fn the_edit_with_the_highest_clock_wins() {
+        let (first, second) = (edit("b", 10, "first"), edit("a", 20, "second"));
+        for revisions in [[first.clone(), second.clone()], [second, first]] {
+            let chat_message = shown(&revisions);
+            assert_eq!(chat_message.message.text, "second");
+            assert!(chat_message.edited_at.is_some());
+            assert!(!chat_message.deleted);
+        }
+    }
+
+    #[test]
+    fn concurrent_edits_are_decided_by_id() {
+        let (a, b) = (edit("a", 10, "a"), edit("b", 10, "b"));
+        assert_eq!(shown(&[a.clone(), b.clone()]).message.text, "b");
+        assert_eq!(shown(&[b, a]).message.text, "b");
+    }
+
+    #[test]
+    fn deletions_win_over_later_edits() {
+        let mut delete = Message::delete("message");
+        delete.id = "delete".to_string();
+        let delete = signed(delete, &IdentityKeys::generate().node_secret_key(), 10);
+        let chat_message = shown(&[delete, edit("edit", 20, "edited")]);
+        assert!(chat_message.deleted);
+        assert!(chat_message.message.text.is_empty());
+    }
+
+    #[test]
+    fn only_the_author_revises_without_an_admin_proof() {
+        let (author, other) = (
+            IdentityKeys::generate().node_secret_key(),
+            IdentityKeys::generate().node_secret_key(),
+        );
+        let message = signed(Message::new("original".to_string()), &author, 1);
+        let by_author = signed(
+            Message::edit(message.message.id(), "edited".into()),
+            &author,
+            2,
+        );
+        let by_other = signed(
+            Message::edit(message.message.id(), "edited".into()),
+            &other,
+            2,
+        );
+        assert!(may_revise(ROOM, &message, &by_author, &[]));
+        assert!(!may_revise(ROOM, &message, &by_other, &[]));
+        // the author's signature has to hold for the room the revision is found in
+        assert!(!may_revise("other room", &message, &by_author, &[]));
+        let mut forged = by_author.clone();
+        forged.message.text = "forged".to_string();
+        assert!(!may_revise(ROOM, &message, &forged, &[]));
+        // a message impersonating the author can't be revised on its behalf
+        let mut impersonated = message.clone();
+        impersonated.message.text = "forged".to_string();
+        assert!(!may_revise(ROOM, &impersonated, &by_author, &[]));
+    }
+}
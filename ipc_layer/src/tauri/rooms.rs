@@ -0,0 +1,285 @@
+//! Registry of the chat rooms this node takes part in.  Every room is a beelay document, keyed by its
+//! `DocumentId`, and tracks the ticket of the peer we sync it with, the peers seen in it and whether the
+//! document has been discovered yet.  Rooms are registered when created, when connecting through a ticket or
+//! when a document is discovered, so a single node can take part in several conversations at once.  Peers take
+//! part in a room once they joined it through its ticket, were admitted through one of its invites or authored
+//! a message in its document, connecting alone doesn't make them members.  Rooms left are remembered until
+//! they are created or joined again, so events still arriving for them are ignored.  Rooms with their titles
+//! and peers, and the rooms left, are persisted in a redb database under the app data directory.  Tickets and
+//! discovery are connection state and start over on every launch.
+use beelay_protocol::{DocumentId, NodeId, NodeTicket};
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Room id -> postcard encoded `StoredRoom`.
+const ROOMS: TableDefinition<&str, &[u8]> = TableDefinition::new("rooms");
+/// Ids of the rooms this node left.
+const LEFT: TableDefinition<&str, ()> = TableDefinition::new("left_rooms");
+
+#[derive(Default)]
+struct Room {
+    // only known on the node that created the room and the nodes invited with a title, titles are not part of
+    // the document
+    title: Option<String>,
+    node_ticket: Option<NodeTicket>,
+    peers: HashSet<NodeId>,
+    discovered: bool,
+}
+
+/// The part of a room kept across restarts.
+#[derive(Serialize, Deserialize)]
+struct StoredRoom {
+    title: Option<String>,
+    peers: Vec<NodeId>,
+}
+
+impl From<&Room> for StoredRoom {
+    fn from(room: &Room) -> Self {
+        Self {
+            title: room.title.clone(),
+            peers: room.peers.iter().copied().collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Rooms {
+    rooms: HashMap<DocumentId, Room>,
+    // the tickets of the peers connected since launch, adopted by the rooms they are members of that have no
+    // ticket of their own
+    connections: HashMap<NodeId, NodeTicket>,
+    left: HashSet<DocumentId>,
+}
+
+pub struct RoomRegistry {
+    inner: Mutex<Rooms>,
+    db: Database,
+}
+
+/// Parses a room id received from the frontend.
+pub fn parse_room(room: &str) -> Result<DocumentId, String> {
+    room.parse::<DocumentId>().map_err(|e| e.to_string())
+}
+
+impl RoomRegistry {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the tables up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(ROOMS).map_err(|e| e.to_string())?;
+        txn.open_table(LEFT).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        let inner = Self::load(&db)?;
+        Ok(Self {
+            inner: Mutex::new(inner),
+            db,
+        })
+    }
+
+    fn load(db: &Database) -> Result<Rooms, String> {
+        let mut inner = Rooms::default();
+        let txn = db.begin_read().map_err(|e| e.to_string())?;
+        let rooms = txn.open_table(ROOMS).map_err(|e| e.to_string())?;
+        for entry in rooms.iter().map_err(|e| e.to_string())? {
+            let (id, data) = entry.map_err(|e| e.to_string())?;
+            let stored: StoredRoom =
+                postcard::from_bytes(data.value()).map_err(|e| e.to_string())?;
+            let room = Room {
+                title: stored.title,
+                peers: stored.peers.into_iter().collect(),
+                ..Room::default()
+            };
+            inner.rooms.insert(parse_room(id.value())?, room);
+        }
+        let left = txn.open_table(LEFT).map_err(|e| e.to_string())?;
+        for entry in left.iter().map_err(|e| e.to_string())? {
+            let (id, _) = entry.map_err(|e| e.to_string())?;
+            inner.left.insert(parse_room(id.value())?);
+        }
+        Ok(inner)
+    }
+
+    /// Writes a room through to the database, `None` once it was left.
+    fn persist(&self, id: &DocumentId, room: Option<&Room>) -> Result<(), String> {
+        let key = id.to_string();
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut rooms = txn.open_table(ROOMS).map_err(|e| e.to_string())?;
+            let mut left = txn.open_table(LEFT).map_err(|e| e.to_string())?;
+            match room {
+                Some(room) => {
+                    let data = postcard::to_allocvec(&StoredRoom::from(room))
+                        .map_err(|e| e.to_string())?;
+                    rooms
+                        .insert(key.as_str(), data.as_slice())
+                        .map_err(|e| e.to_string())?;
+                    left.remove(key.as_str()).map_err(|e| e.to_string())?;
+                }
+                None => {
+                    rooms.remove(key.as_str()).map_err(|e| e.to_string())?;
+                    left.insert(key.as_str(), ()).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    /// Registers a room created on this node, it is synced with the first peer admitted through one of its invites.
+    pub fn create(&self, room: DocumentId, title: String) -> Result<(), String> {
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        inner.left.remove(&room);
+        let entry = inner.rooms.entry(room).or_default();
+        entry.title = Some(title).filter(|title| !title.is_empty());
+        self.persist(&room, Some(&*entry))
+    }
+
+    /// Registers a room joined through a ticket, syncing with the peer the ticket points to.  The title is the
+    /// one the inviting node put in the ticket, if any.
+    pub fn join(
+        &self,
+        room: DocumentId,
+        node_ticket: NodeTicket,
+        title: Option<String>,
+    ) -> Result<(), String> {
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        inner.left.remove(&room);
+        let entry = inner.rooms.entry(room).or_default();
+        if entry.title.is_none() {
+            entry.title = title.filter(|title| !title.is_empty());
+        }
+        entry.peers.insert(node_ticket.node_addr().node_id);
+        entry.node_ticket = Some(node_ticket);
+        self.persist(&room, Some(&*entry))
+    }
+
+    /// Marks a room as discovered, registering it if this is the first time we hear of it.  Returns the peer
+    /// the room is synced with, unless none of its members has connected yet.
+    pub fn discovered(&self, room: DocumentId) -> Result<Option<NodeId>, String> {
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        let Rooms {
+            rooms, connections, ..
+        } = &mut *inner;
+        let entry = rooms.entry(room).or_default();
+        entry.discovered = true;
+        if entry.node_ticket.is_none() {
+            // only a peer known to take part in the room can sync it, not whoever happens to be connected
+            entry.node_ticket = entry
+                .peers
+                .iter()
+                .find_map(|peer| connections.get(peer))
+                .cloned();
+        }
+        self.persist(&room, Some(&*entry))?;
+        Ok(entry
+            .node_ticket
+            .as_ref()
+            .map(|node_ticket| node_ticket.node_addr().node_id))
+    }
+
+    /// Records a peer connection and returns the rooms that peer takes part in.  A connection alone doesn't make
+    /// the peer a member of any room, rooms without a ticket of their own are synced with it only when it is a
+    /// member already.
+    pub fn connected(&self, node_ticket: NodeTicket) -> Result<Vec<DocumentId>, String> {
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        let node_id = node_ticket.node_addr().node_id;
+        let mut rooms = Vec::new();
+        for (room, entry) in inner.rooms.iter_mut() {
+            if !entry.peers.contains(&node_id) {
+                continue;
+            }
+            if entry.node_ticket.is_none() {
+                entry.node_ticket = Some(node_ticket.clone());
+            }
+            rooms.push(*room);
+        }
+        inner.connections.insert(node_id, node_ticket);
+        Ok(rooms)
+    }
+
+    /// Records a peer as a member of the room: the author of a message in its document or a peer admitted
+    /// through an invite.  The room is synced with it if it has no ticket of its own and the peer is connected.
+    pub fn add_peer(&self, room: &DocumentId, peer: NodeId) -> Result<(), String> {
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        let Rooms {
+            rooms, connections, ..
+        } = &mut *inner;
+        let entry = rooms.entry(*room).or_default();
+        if entry.node_ticket.is_none() {
+            entry.node_ticket = connections.get(&peer).cloned();
+        }
+        if entry.peers.insert(peer) {
+            self.persist(room, Some(&*entry))?;
+        }
+        Ok(())
+    }
+
+    /// The ticket of the peer a room is synced with, required to add data to its document.
+    pub fn node_ticket(&self, room: &DocumentId) -> Result<NodeTicket, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        let entry = inner.rooms.get(room).ok_or("Unknown room".to_string())?;
+        if !entry.discovered {
+            return Err("Room not discovered yet".to_string());
+        }
+        entry
+            .node_ticket
+            .clone()
+            .ok_or("Node Ticket not set".to_string())
+    }
+
+    /// Whether data can be added to a room's document: it was discovered and has a peer to sync with.
+    pub fn is_ready(&self, room: &DocumentId) -> Result<bool, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        let entry = inner.rooms.get(room).ok_or("Unknown room".to_string())?;
+        Ok(entry.discovered && entry.node_ticket.is_some())
+    }
+
+    /// The peers seen in a room, through its ticket, its invites or the messages they authored.
+    pub fn members(&self, room: &DocumentId) -> Result<Vec<NodeId>, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        let entry = inner.rooms.get(room).ok_or("Unknown room".to_string())?;
+        Ok(entry.peers.iter().copied().collect())
+    }
+
+    /// Unregisters a room this node leaves, returning the peers seen in it.
+    pub fn leave(&self, room: &DocumentId) -> Result<Vec<NodeId>, String> {
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        let entry = inner.rooms.remove(room).ok_or("Unknown room".to_string())?;
+        inner.left.insert(*room);
+        self.persist(room, None)?;
+        Ok(entry.peers.into_iter().collect())
+    }
+
+    pub fn has_left(&self, room: &DocumentId) -> Result<bool, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        Ok(inner.left.contains(room))
+    }
+
+    pub fn title(&self, room: &DocumentId) -> Result<Option<String>, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        Ok(inner.rooms.get(room).and_then(|entry| entry.title.clone()))
+    }
+
+    pub fn contains(&self, room: &DocumentId) -> Result<bool, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        Ok(inner.rooms.contains_key(room))
+    }
+
+    /// All rooms this node takes part in.
+    pub fn rooms(&self) -> Result<Vec<DocumentId>, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        Ok(inner.rooms.keys().copied().collect())
+    }
+
+    /// All rooms this node takes part in, with their titles.
+    pub fn list(&self) -> Result<Vec<(DocumentId, Option<String>)>, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        Ok(inner
+            .rooms
+            .iter()
+            .map(|(room, entry)| (*room, entry.title.clone()))
+            .collect())
+    }
+}
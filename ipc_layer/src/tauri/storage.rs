@@ -0,0 +1,298 @@
+//! Storage adapters for the beelay node.  Beelay addresses everything it stores (commits, bundles, sedimentree
+//! metadata and keyhive state) with a `StorageKey` made of path-like components, so any ordered key-value store
+//! can back it.  `DiskStorage` keeps documents in a redb database under the app data directory so a node can
+//! resume syncing the same documents after a restart, `MemoryStorage` keeps them in memory for tests.  Beelay
+//! keys everything of a document below a component holding the document id, so `DiskStorage` can drop the
+//! documents of rooms this node left and refuses to store them again until they are joined again.
+use beelay_protocol::{StorageAdapter, StorageKey};
+use redb::{Database, ReadableTable, TableDefinition};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Storage key components joined by `/` -> stored bytes.
+const ENTRIES: TableDefinition<&str, &[u8]> = TableDefinition::new("beelay_entries");
+/// Ids of the documents dropped when leaving their room.
+const DROPPED: TableDefinition<&str, ()> = TableDefinition::new("dropped_documents");
+
+const SEPARATOR: char = '/';
+
+fn encode_key(key: &StorageKey) -> String {
+    key.components().join(&SEPARATOR.to_string())
+}
+
+fn decode_key(key: &str) -> StorageKey {
+    StorageKey::from(
+        key.split(SEPARATOR)
+            .map(|component| component.to_string())
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// The half-open range of encoded keys below all components of `prefix`, the key of `prefix` itself is not
+/// part of it and is looked up separately.  `0` is the character following the separator, so `a/b/`..`a/b0`
+/// covers everything below `a/b` without siblings like `a/b-c`.
+fn prefix_range(prefix: &StorageKey) -> (String, String) {
+    let prefix = encode_key(prefix);
+    if prefix.is_empty() {
+        return (String::new(), char::MAX.to_string());
+    }
+    (format!("{}{}", prefix, SEPARATOR), format!("{}0", prefix))
+}
+
+pub struct DiskStorage {
+    db: Database,
+    // mirrors `DROPPED`, checked on every write
+    dropped: Mutex<HashSet<String>>,
+}
+
+impl DiskStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let db = Database::create(path).map_err(|e| e.to_string())?;
+        // create the tables up front so reads on a fresh database don't fail
+        let txn = db.begin_write().map_err(|e| e.to_string())?;
+        txn.open_table(ENTRIES).map_err(|e| e.to_string())?;
+        txn.open_table(DROPPED).map_err(|e| e.to_string())?;
+        txn.commit().map_err(|e| e.to_string())?;
+        let dropped = {
+            let txn = db.begin_read().map_err(|e| e.to_string())?;
+            let table = txn.open_table(DROPPED).map_err(|e| e.to_string())?;
+            table
+                .iter()
+                .map_err(|e| e.to_string())?
+                .map(|entry| {
+                    let (document, _) = entry.map_err(|e| e.to_string())?;
+                    Ok(document.value().to_string())
+                })
+                .collect::<Result<HashSet<_>, String>>()?
+        };
+        Ok(Self {
+            db,
+            dropped: Mutex::new(dropped),
+        })
+    }
+
+    /// Deletes everything stored for a document and refuses to store it again, e.g. when leaving its room.
+    pub fn drop_document(&self, document: &str) -> Result<(), String> {
+        let mut dropped = self.dropped.lock().map_err(|e| e.to_string())?;
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(DROPPED).map_err(|e| e.to_string())?;
+            table.insert(document, ()).map_err(|e| e.to_string())?;
+            let mut table = txn.open_table(ENTRIES).map_err(|e| e.to_string())?;
+            table
+                .retain(|key, _| !key.split(SEPARATOR).any(|component| component == document))
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())?;
+        dropped.insert(document.to_string());
+        Ok(())
+    }
+
+    /// Stores a dropped document again, once its room is joined again.
+    pub fn restore_document(&self, document: &str) -> Result<(), String> {
+        let mut dropped = self.dropped.lock().map_err(|e| e.to_string())?;
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(DROPPED).map_err(|e| e.to_string())?;
+            table.remove(document).map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())?;
+        dropped.remove(document);
+        Ok(())
+    }
+
+    fn is_dropped(&self, key: &StorageKey) -> Result<bool, String> {
+        let dropped = self.dropped.lock().map_err(|e| e.to_string())?;
+        Ok(key
+            .components()
+            .iter()
+            .any(|component| dropped.contains(component)))
+    }
+}
+
+impl StorageAdapter for DiskStorage {
+    fn load(&self, key: &StorageKey) -> Result<Option<Vec<u8>>, String> {
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(ENTRIES).map_err(|e| e.to_string())?;
+        let data = table
+            .get(encode_key(key).as_str())
+            .map_err(|e| e.to_string())?;
+        Ok(data.map(|data| data.value().to_vec()))
+    }
+
+    fn load_range(&self, prefix: &StorageKey) -> Result<HashMap<StorageKey, Vec<u8>>, String> {
+        let (start, end) = prefix_range(prefix);
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = txn.open_table(ENTRIES).map_err(|e| e.to_string())?;
+        let mut entries = table
+            .range(start.as_str()..end.as_str())
+            .map_err(|e| e.to_string())?
+            .map(|entry| {
+                let (key, data) = entry.map_err(|e| e.to_string())?;
+                Ok((decode_key(key.value()), data.value().to_vec()))
+            })
+            .collect::<Result<HashMap<_, _>, String>>()?;
+        let exact = encode_key(prefix);
+        if !exact.is_empty()
+            && let Some(data) = table.get(exact.as_str()).map_err(|e| e.to_string())?
+        {
+            entries.insert(decode_key(&exact), data.value().to_vec());
+        }
+        Ok(entries)
+    }
+
+    fn put(&self, key: StorageKey, data: Vec<u8>) -> Result<(), String> {
+        if self.is_dropped(&key)? {
+            return Err("The document belongs to a room this node left".to_string());
+        }
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(ENTRIES).map_err(|e| e.to_string())?;
+            table
+                .insert(encode_key(&key).as_str(), data.as_slice())
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+
+    fn delete(&self, key: StorageKey) -> Result<(), String> {
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(ENTRIES).map_err(|e| e.to_string())?;
+            table
+                .remove(encode_key(&key).as_str())
+                .map_err(|e| e.to_string())?;
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+}
+
+/// Keeps everything in memory, nothing survives the process.  Intended for tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageAdapter for MemoryStorage {
+    fn load(&self, key: &StorageKey) -> Result<Option<Vec<u8>>, String> {
+        let entries = self.entries.lock().map_err(|e| e.to_string())?;
+        Ok(entries.get(&encode_key(key)).cloned())
+    }
+
+    fn load_range(&self, prefix: &StorageKey) -> Result<HashMap<StorageKey, Vec<u8>>, String> {
+        let (start, end) = prefix_range(prefix);
+        let entries = self.entries.lock().map_err(|e| e.to_string())?;
+        let mut range = entries
+            .range(start..end)
+            .map(|(key, data)| (decode_key(key), data.clone()))
+            .collect::<HashMap<_, _>>();
+        let exact = encode_key(prefix);
+        if !exact.is_empty()
+            && let Some(data) = entries.get(&exact)
+        {
+            range.insert(decode_key(&exact), data.clone());
+        }
+        Ok(range)
+    }
+
+    fn put(&self, key: StorageKey, data: Vec<u8>) -> Result<(), String> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+        entries.insert(encode_key(&key), data);
+        Ok(())
+    }
+
+    fn delete(&self, key: StorageKey) -> Result<(), String> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+        entries.remove(&encode_key(&key));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(path: &str) -> StorageKey {
+        decode_key(path)
+    }
+
+    fn paths(entries: HashMap<StorageKey, Vec<u8>>) -> Vec<String> {
+        let mut paths = entries.keys().map(encode_key).collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn put_load_delete() {
+        let storage = MemoryStorage::new();
+        assert_eq!(storage.load(&key("doc/commit")).unwrap(), None);
+        storage.put(key("doc/commit"), vec![1, 2]).unwrap();
+        assert_eq!(storage.load(&key("doc/commit")).unwrap(), Some(vec![1, 2]));
+        storage.put(key("doc/commit"), vec![3]).unwrap();
+        assert_eq!(storage.load(&key("doc/commit")).unwrap(), Some(vec![3]));
+        storage.delete(key("doc/commit")).unwrap();
+        assert_eq!(storage.load(&key("doc/commit")).unwrap(), None);
+        // deleting a missing key is not an error
+        storage.delete(key("doc/commit")).unwrap();
+    }
+
+    #[test]
+    fn load_range_covers_the_prefix_and_the_keys_below_it() {
+        let storage = MemoryStorage::new();
+        for path in ["a", "a/b", "a/b/c", "a/b/c/d", "a/b-c", "a/bc", "a/c"] {
+            storage.put(key(path), path.as_bytes().to_vec()).unwrap();
+        }
+        let range = storage.load_range(&key("a/b")).unwrap();
+        assert_eq!(range.get(&key("a/b")), Some(&b"a/b".to_vec()));
+        assert_eq!(paths(range), ["a/b", "a/b/c", "a/b/c/d"]);
+        assert_eq!(
+            paths(storage.load_range(&key("a/b/c/d")).unwrap()),
+            ["a/b/c/d"]
+        );
+        assert!(storage.load_range(&key("b")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn dropped_documents_are_deleted_and_not_stored_again() {
+        let dir = std::env::temp_dir().join(format!("storage-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage = DiskStorage::open(dir.join("beelay.redb")).unwrap();
+        for path in ["dags/left/a", "dags/left", "dags/kept/a", "dags/leftover"] {
+            storage.put(key(path), Vec::new()).unwrap();
+        }
+        storage.drop_document("left").unwrap();
+        let everything = StorageKey::from(Vec::<String>::new());
+        assert_eq!(
+            paths(storage.load_range(&everything).unwrap()),
+            ["dags/kept/a", "dags/leftover"]
+        );
+        assert!(storage.put(key("dags/left/b"), Vec::new()).is_err());
+        // the dropped documents survive a restart
+        drop(storage);
+        let storage = DiskStorage::open(dir.join("beelay.redb")).unwrap();
+        assert!(storage.put(key("dags/left/b"), Vec::new()).is_err());
+        storage.restore_document("left").unwrap();
+        storage.put(key("dags/left/b"), Vec::new()).unwrap();
+    }
+
+    #[test]
+    fn empty_prefix_covers_everything() {
+        let storage = MemoryStorage::new();
+        for path in ["a", "a/b", "b/c"] {
+            storage.put(key(path), Vec::new()).unwrap();
+        }
+        let everything = StorageKey::from(Vec::<String>::new());
+        assert_eq!(
+            paths(storage.load_range(&everything).unwrap()),
+            ["a", "a/b", "b/c"]
+        );
+    }
+}
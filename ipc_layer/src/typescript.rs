@@ -0,0 +1,61 @@
+//! Assembles the TypeScript definitions of the IPC contract for frontends that are not written in Rust.
+//!
+//! Every piece is generated by `ipc_macros` from the `API` trait, the `derive_events!` list and the payload
+//! types annotated with `#[ipc_macros::ts_interface]`, so the Rust definitions remain the single source of truth.
+//! New payload types shared over IPC must be annotated and added to `PAYLOAD_TYPES` below.
+use crate::{
+    AccessGrant, Attachment, AttachmentProgress, ChatMessage, ConnectionPath, ConnectionStatus,
+    DeliveryState, EphemeralSignal, HistoryBackfill, HistoryCursor, HybridTimestamp, Invite,
+    InviteCodes, InviteRejection, Member, Message, MessageDelivery, PeerPath, PeerProfile,
+    PeerStatus, Presence, Profile, ReadReceipt, RevisedMessage, Revision, RevisionKind, Role,
+    RoomAccess, RoomInfo, RoomMessage, Roster, SignalKind, TicketPreview,
+};
+
+const HEADER: &str =
+    "// This file is generated from the ipc_layer crate, do not edit it by hand.\n";
+
+/// Declarations for every payload type referenced by the commands and events.
+const PAYLOAD_TYPES: &[&str] = &[
+    Message::TS_DEFINITION,
+    Attachment::TS_DEFINITION,
+    AttachmentProgress::TS_DEFINITION,
+    RevisionKind::TS_DEFINITION,
+    Revision::TS_DEFINITION,
+    RevisedMessage::TS_DEFINITION,
+    HybridTimestamp::TS_DEFINITION,
+    HistoryCursor::TS_DEFINITION,
+    ChatMessage::TS_DEFINITION,
+    DeliveryState::TS_DEFINITION,
+    MessageDelivery::TS_DEFINITION,
+    HistoryBackfill::TS_DEFINITION,
+    RoomInfo::TS_DEFINITION,
+    RoomMessage::TS_DEFINITION,
+    ConnectionStatus::TS_DEFINITION,
+    ConnectionPath::TS_DEFINITION,
+    PeerStatus::TS_DEFINITION,
+    PeerPath::TS_DEFINITION,
+    Presence::TS_DEFINITION,
+    Member::TS_DEFINITION,
+    Roster::TS_DEFINITION,
+    SignalKind::TS_DEFINITION,
+    EphemeralSignal::TS_DEFINITION,
+    ReadReceipt::TS_DEFINITION,
+    Profile::TS_DEFINITION,
+    PeerProfile::TS_DEFINITION,
+    Role::TS_DEFINITION,
+    AccessGrant::TS_DEFINITION,
+    RoomAccess::TS_DEFINITION,
+    Invite::TS_DEFINITION,
+    InviteRejection::TS_DEFINITION,
+    TicketPreview::TS_DEFINITION,
+    InviteCodes::TS_DEFINITION,
+];
+
+/// Returns the complete `.d.ts` contents: payload types, command signatures and event payloads.
+pub fn definitions() -> String {
+    let mut definitions = vec![HEADER];
+    definitions.extend(PAYLOAD_TYPES);
+    definitions.push(crate::ts::COMMANDS);
+    definitions.push(crate::events::ts::EVENTS);
+    definitions.join("\n")
+}
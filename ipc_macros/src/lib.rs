@@ -3,14 +3,17 @@
 /// Also, all ipc apis ui and tauri are allowed to exist simultaneously in together without collision by placing things in respective modules.  
 /// The only exception is tauri command generation should live in a user-defined top-level module file because tauri commands cannot be
 /// placed in a non-top-level module.
+#[cfg(feature = "typescript")]
+mod typescript;
+
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::{ToTokens, TokenStreamExt, quote};
 use syn::parse::ParseStream;
 use syn::{
-    self, AngleBracketedGenericArguments, Field, FieldMutability, FnArg, GenericArgument, Ident,
-    ItemFn, ItemTrait, LitStr, Pat, PathArguments, Signature, Token, TraitItem, Type, TypePath,
-    Visibility, braced,
+    self, AngleBracketedGenericArguments, Attribute, Field, FieldMutability, FnArg,
+    GenericArgument, Ident, ItemFn, ItemTrait, LitStr, Pat, PathArguments, Signature, Token,
+    TraitItem, Type, TypePath, Visibility, braced,
     parse::Parse,
     parse_macro_input, parse_quote,
     punctuated::{Pair, Punctuated},
@@ -52,6 +55,73 @@ impl Parse for KeyValuePair {
     }
 }
 
+/// Options set through `#[ipc(...)]` on trait methods, `impl_trait!` functions and `derive_events!` entries.
+///
+/// - `since = "2"`: the version the command or event was introduced in.  Anything newer than version 1
+///   is exposed under a versioned name, e.g. `connect_via_serialized_ticket_v2`, so older frontends keep
+///   invoking the unversioned command.
+/// - `deprecated` or `deprecated = "note"`: marks the UI binding `#[deprecated]` so frontends get a warning.
+/// - `compat`: only valid inside `impl_trait!`, marks a backend-only shim serving an older version of a
+///   command under its own name.  It is registered with the command handler but not checked against the trait.
+#[derive(Default)]
+struct IpcAttrs {
+    since: Option<String>,
+    deprecated: Option<String>,
+    compat: bool,
+}
+
+impl IpcAttrs {
+    fn is_ipc_attr(attr: &Attribute) -> bool {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "ipc")
+    }
+
+    /// Parses all `#[ipc(...)]` (or `#[ipc_macros::ipc(...)]`) attributes in `attrs`.
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut ipc_attrs = Self::default();
+        for attr in attrs.iter().filter(|attr| Self::is_ipc_attr(attr)) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("since") {
+                    ipc_attrs.since = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("deprecated") {
+                    ipc_attrs.deprecated = Some(if meta.input.peek(Token![=]) {
+                        meta.value()?.parse::<LitStr>()?.value()
+                    } else {
+                        "deprecated".to_string()
+                    });
+                } else if meta.path.is_ident("compat") {
+                    ipc_attrs.compat = true;
+                } else {
+                    return Err(meta.error("expected `since`, `deprecated` or `compat`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(ipc_attrs)
+    }
+
+    /// Removes the `#[ipc(...)]` attributes so they don't reach the compiler.
+    fn strip(attrs: &mut Vec<Attribute>) {
+        attrs.retain(|attr| !Self::is_ipc_attr(attr));
+    }
+
+    /// The command or event name for the version set by `since`, version 1 is left unversioned.
+    fn versioned_name(&self, name: &str) -> String {
+        match self.since.as_deref() {
+            None | Some("1") => name.to_string(),
+            Some(since) => format!("{}_v{}", name, since.replace('.', "_")),
+        }
+    }
+
+    fn deprecation(&self) -> Option<proc_macro2::TokenStream> {
+        self.deprecated
+            .as_ref()
+            .map(|note| quote! { #[deprecated(note = #note)] })
+    }
+}
+
 fn extract_result_types(ty: &Type) -> Option<(&Type, &Type)> {
     // todo: investigate edge cases.
     match ty {
@@ -85,6 +155,12 @@ fn extract_result_types(ty: &Type) -> Option<(&Type, &Type)> {
 /// Apply this to a trait, and generate an implementation for it's fns in the
 /// same scope that call `invoke` using the fn name as the command
 ///
+/// Trait methods accept `#[ipc(since = "...", deprecated = "...")]` to version the invoked command name and
+/// mark the generated binding `#[deprecated]`, see `impl_trait!` for serving several versions from the backend.
+///
+/// With the `typescript` feature enabled, a sibling `ts` module is also emitted whose `COMMANDS` constant holds
+/// the TypeScript definitions of every command for use by non-Rust frontends.
+///
 /// # Examples
 ///
 /// ```ignore
@@ -103,9 +179,16 @@ fn extract_result_types(ty: &Type) -> Option<(&Type, &Type)> {
 pub fn invoke_bindings(attrs: TokenStream, tokens: TokenStream) -> TokenStream {
     // todo: add naming option for ui module to allow for multiple groupings of ui commands in the same module.
     let attrs = parse_macro_input!(attrs as InvokeBindingAttrs);
-    let trait_item = parse_macro_input!(tokens as ItemTrait);
+    let mut trait_item = parse_macro_input!(tokens as ItemTrait);
+    // the invoked command name and deprecation note of every binding
+    let mut commands = Vec::new();
     let fn_items = trait_item.items.iter().fold(Vec::new(), |mut m, item| {
         if let TraitItem::Fn(fn_item) = item {
+            let ipc_attrs = IpcAttrs::parse(&fn_item.attrs)
+                .unwrap_or_else(|e| panic!("invalid ipc attribute: {}", e));
+            if ipc_attrs.compat {
+                panic!("`compat` is only supported on functions in `impl_trait!`");
+            }
             let fields: Punctuated<Field, Token![,]> =
                 Punctuated::from_iter(fn_item.sig.inputs.iter().fold(Vec::new(), |mut m, arg| {
                     let pt = match arg {
@@ -132,11 +215,16 @@ pub fn invoke_bindings(attrs: TokenStream, tokens: TokenStream) -> TokenStream {
                 }));
             let field_names: Punctuated<Ident, Token![,]> =
                 Punctuated::from_iter(fields.iter().map(|field| field.ident.clone().unwrap()));
-            let fn_name = fn_item.sig.ident.to_string();
+            let fn_name = ipc_attrs.versioned_name(&fn_item.sig.ident.to_string());
             let fn_name = attrs
                 .cmd_prefix
                 .clone()
                 .map_or(fn_name.clone(), |prefix| prefix + fn_name.as_str());
+            let note = match &ipc_attrs.deprecated {
+                Some(note) => quote! { Some(#note) },
+                None => quote! { None },
+            };
+            commands.push(quote! { (#fn_name, #note) });
             let invocation = match fn_item.sig.output {
                 syn::ReturnType::Default => {
                     quote! { ::tauri_sys::core::invoke::<()> }
@@ -150,7 +238,9 @@ pub fn invoke_bindings(attrs: TokenStream, tokens: TokenStream) -> TokenStream {
                 }
             };
             m.push(ItemFn {
-                attrs: Vec::new(),
+                attrs: ipc_attrs
+                    .deprecation()
+                    .map_or(Vec::new(), |deprecation| parse_quote!(#deprecation)),
                 vis: trait_item.vis.clone(),
                 sig: fn_item.sig.clone(),
                 block: parse_quote!({
@@ -168,17 +258,52 @@ pub fn invoke_bindings(attrs: TokenStream, tokens: TokenStream) -> TokenStream {
     });
     let fn_items = ItemList { list: fn_items };
     let mod_visibility = trait_item.vis.clone();
+    let ts_definitions = typescript_commands_module(&trait_item, attrs.cmd_prefix.as_deref());
+    trait_item.items.iter_mut().for_each(|item| {
+        if let TraitItem::Fn(fn_item) = item {
+            IpcAttrs::strip(&mut fn_item.attrs);
+        }
+    });
     let ret = quote! {
         #trait_item
         #mod_visibility mod ui{
             use super::*;
+            /// Every command the bindings invoke, with the deprecation note of deprecated ones.
+            pub const COMMANDS: &[(&str, Option<&str>)] = &[#(#commands),*];
             #fn_items
         }
+        #ts_definitions
     };
 
     TokenStream::from(ret)
 }
 
+/// Emits a `ts` module holding the TypeScript definitions of the commands when the `typescript` feature is enabled.
+fn typescript_commands_module(
+    trait_item: &ItemTrait,
+    cmd_prefix: Option<&str>,
+) -> proc_macro2::TokenStream {
+    #[cfg(feature = "typescript")]
+    {
+        let mod_visibility = &trait_item.vis;
+        let definitions = match typescript::commands_definitions(trait_item, cmd_prefix) {
+            Ok(definitions) => definitions,
+            Err(e) => return e.to_compile_error(),
+        };
+        quote! {
+            #mod_visibility mod ts {
+                /// TypeScript definitions for every command, their arguments and their responses.
+                pub const COMMANDS: &str = #definitions;
+            }
+        }
+    }
+    #[cfg(not(feature = "typescript"))]
+    {
+        let _ = (trait_item, cmd_prefix);
+        proc_macro2::TokenStream::new()
+    }
+}
+
 struct ImplTrait {
     trait_ident: Ident,
     fns: ItemList<ItemFn>,
@@ -249,11 +374,36 @@ impl<I: ToTokens> ToTokens for ItemList<I> {
 ///     }
 /// });
 /// ```
+///
+/// # Versioning
+///
+/// Functions carrying `#[ipc(since = "...")]` are registered under the same versioned command name the
+/// UI bindings invoke, so the attribute must match the one on the trait method.  During a transition the
+/// previous version can keep being served by adding a `#[ipc(compat)]` shim under the old command name:
+///
+/// ```ignore
+/// ipc_macros::impl_trait!(Commands, {
+///     // served as `foo_v2`, checked against the trait
+///     #[ipc(since = "2")]
+///     #[tauri::command]
+///     async fn foo(bar: String) -> Result<Foo, String> { .. }
+///
+///     // served as `foo` for older frontends, not part of the trait
+///     #[ipc(compat)]
+///     #[tauri::command]
+///     async fn foo(bar: String) -> Result<(), String> { .. }
+/// });
+/// ```
 #[proc_macro]
 pub fn impl_trait(tokens: TokenStream) -> TokenStream {
-    let ImplTrait { trait_ident, fns } = parse_macro_input!(tokens as ImplTrait);
+    let ImplTrait {
+        trait_ident,
+        mut fns,
+    } = parse_macro_input!(tokens as ImplTrait);
 
     let mut trait_fns = Vec::new();
+    // every command registered with tauri, including versioned names and compatibility shims
+    let mut fn_listing = Vec::new();
 
     fn map_fn_input(mut item: Pair<FnArg, Comma>) -> Pair<FnArg, Comma> {
         let value = item.value_mut();
@@ -290,8 +440,24 @@ pub fn impl_trait(tokens: TokenStream) -> TokenStream {
         }))
     }
 
-    fns.list.iter().for_each(|func| {
-        let sig = &func.sig;
+    for func in fns.list.iter_mut() {
+        let ipc_attrs = match IpcAttrs::parse(&func.attrs) {
+            Ok(ipc_attrs) => ipc_attrs,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        IpcAttrs::strip(&mut func.attrs);
+        if ipc_attrs.compat {
+            // shims serve older versions of a command and are intentionally not part of the trait
+            fn_listing.push(func.sig.ident.clone());
+            continue;
+        }
+        let sig = func.sig.clone();
+        let versioned_ident = Ident::new(
+            &ipc_attrs.versioned_name(&sig.ident.to_string()),
+            sig.ident.span(),
+        );
+        func.sig.ident = versioned_ident.clone();
+        fn_listing.push(versioned_ident);
         trait_fns.push(ItemFn {
             attrs: Vec::new(),
             vis: func.vis.clone(),
@@ -310,12 +476,9 @@ pub fn impl_trait(tokens: TokenStream) -> TokenStream {
             },
             block: parse_quote!({ todo!() }),
         });
-    });
+    }
 
-    let fn_listing = trait_fns
-        .iter()
-        .map(|fn_item| fn_item.sig.ident.clone())
-        .collect::<Vec<Ident>>();
+    let command_names = fn_listing.iter().map(|ident| ident.to_string());
     let tauri_command_handler = quote! {
         pub fn command_handler<R>() -> impl Fn(::tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static
             where
@@ -325,6 +488,9 @@ pub fn impl_trait(tokens: TokenStream) -> TokenStream {
                     #(#fn_listing),*
                 ]
             }
+
+        /// Names of every command registered by `command_handler`.
+        pub const COMMANDS: &[&str] = &[#(#command_names),*];
     };
 
     let struct_name = Ident::new(format!("__Impl{}", trait_ident).as_str(), Span::call_site());
@@ -346,18 +512,29 @@ pub fn impl_trait(tokens: TokenStream) -> TokenStream {
 }
 
 struct EventDefinition {
+    ipc_attrs: IpcAttrs,
     name: LitStr,
     payload_type: Type,
 }
 
 impl Parse for EventDefinition {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ipc_attrs = IpcAttrs::parse(&input.call(Attribute::parse_outer)?)?;
+        if ipc_attrs.compat {
+            return Err(input.error(
+                "`compat` is not supported on events, keep the old event defined alongside the new one",
+            ));
+        }
         let content;
         syn::parenthesized!(content in input);
         let name: LitStr = content.parse()?;
         content.parse::<Token![,]>()?;
         let payload_type: Type = content.parse()?;
-        Ok(EventDefinition { name, payload_type })
+        Ok(EventDefinition {
+            ipc_attrs,
+            name,
+            payload_type,
+        })
     }
 }
 
@@ -443,7 +620,9 @@ impl Parse for EventMacroInput {
 ///
 /// - `ui`: Attributes applied to the UI module (typically `#[cfg(feature = "ui")]`)
 /// - `tauri`: Attributes applied to the Tauri module (typically `#[cfg(feature = "tauri")]`)
-/// - Events block: List of event definitions as `("event_name", PayloadType)` tuples
+/// - Events block: List of event definitions as `("event_name", PayloadType)` tuples, optionally preceded by
+///   `#[ipc(since = "...", deprecated = "...")]` to version the emitted event name and deprecate `listen()`.
+///   To keep older frontends working, keep emitting the old event alongside the new one during a transition.
 ///
 /// # Generated Structure
 ///
@@ -451,6 +630,7 @@ impl Parse for EventMacroInput {
 /// - `events::tauri` module with structs that can emit events to the frontend
 /// - `events::ui` module with structs that can listen for events from the backend
 /// - Each event struct includes `new()`, `event_name()`, and either `emit()` or `listen()` methods
+/// - `events::ts::EVENTS` with the TypeScript event name to payload map when the `typescript` feature is enabled
 ///
 /// # Examples
 ///
@@ -490,9 +670,10 @@ pub fn derive_events(input: TokenStream) -> TokenStream {
     let mut tauri_structs = Vec::new();
 
     for event in &input.events {
-        let event_name_str = &event.name;
+        let event_name_str = event.ipc_attrs.versioned_name(&event.name.value());
         let event_name_ident = Ident::new(&event.name.value(), event.name.span());
         let payload_type = &event.payload_type;
+        let deprecation = event.ipc_attrs.deprecation();
 
         // Generate UI struct
         tauri_structs.push(quote! {
@@ -528,6 +709,7 @@ pub fn derive_events(input: TokenStream) -> TokenStream {
                 }
                 
                 // todo: add once listen to allow for single shot events.
+                #deprecation
                 pub async fn listen() -> ::core::result::Result<impl ::futures_core::Stream<Item = ::tauri_sys::event::Event<#payload_type>>, ::tauri_sys::Error> {
                     ::tauri_sys::event::listen::<#payload_type>(Self::event_name()).await
                 }
@@ -535,11 +717,31 @@ pub fn derive_events(input: TokenStream) -> TokenStream {
         });
     }
 
+    #[cfg(feature = "typescript")]
+    let ts_definitions = {
+        let definitions = typescript::events_definitions(
+            input
+                .events
+                .iter()
+                .map(|event| (&event.ipc_attrs, event.name.value(), &event.payload_type)),
+        );
+        quote! {
+            pub mod ts {
+                /// TypeScript definitions mapping every event name to its payload.
+                pub const EVENTS: &str = #definitions;
+            }
+        }
+    };
+    #[cfg(not(feature = "typescript"))]
+    let ts_definitions = proc_macro2::TokenStream::new();
+
     let expanded = quote! {
         #[allow(non_camel_case_types)]
         pub mod events {
             use super::*;
 
+            #ts_definitions
+
             #tauri_attrs
             pub mod tauri {
                 use super::*;
@@ -557,3 +759,56 @@ pub fn derive_events(input: TokenStream) -> TokenStream {
 
     TokenStream::from(expanded)
 }
+
+/// Attach to a payload struct or enum shared over IPC to export its TypeScript definition.
+///
+/// With the `typescript` feature enabled, this adds a `TS_DEFINITION` associated constant holding the
+/// `.d.ts` declaration matching the type's serde json representation.  Without the feature it expands to the
+/// item unchanged, so it can be left on types unconditionally.  It must be placed above `#[derive(Serialize)]`.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[ipc_macros::ts_interface]
+/// #[derive(Serialize, Deserialize)]
+/// pub struct Message {
+///     timestamp: DateTime<Utc>,
+///     text: String,
+/// }
+///
+/// assert!(Message::TS_DEFINITION.starts_with("export interface Message"));
+/// ```
+#[proc_macro_attribute]
+pub fn ts_interface(_attrs: TokenStream, tokens: TokenStream) -> TokenStream {
+    #[cfg(feature = "typescript")]
+    {
+        let item = tokens.clone();
+        let input = parse_macro_input!(item as syn::DeriveInput);
+        let definition = match typescript::item_definition(&input) {
+            Ok(definition) => definition,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        let ident = &input.ident;
+        let item = proc_macro2::TokenStream::from(tokens);
+        let ret = quote! {
+            #item
+
+            impl #ident {
+                pub const TS_DEFINITION: &'static str = #definition;
+            }
+        };
+        TokenStream::from(ret)
+    }
+    #[cfg(not(feature = "typescript"))]
+    tokens
+}
+
+/// Versioning and deprecation options for IPC commands, see `invoke_bindings` and `impl_trait!`.
+///
+/// These attributes are consumed by `invoke_bindings` and `impl_trait!`.  This passthrough only exists so
+/// the attribute still resolves on the trait when `invoke_bindings` is not applied, e.g. in the backend build.
+/// Prefer the full `#[ipc_macros::ipc(...)]` path on trait methods so no import is left unused in the UI build.
+#[proc_macro_attribute]
+pub fn ipc(_attrs: TokenStream, tokens: TokenStream) -> TokenStream {
+    tokens
+}
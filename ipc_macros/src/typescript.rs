@@ -0,0 +1,450 @@
+//! TypeScript definition generation for the IPC contract.
+//!
+//! Only compiled with the `typescript` feature.  The command trait, the event list and any payload types
+//! annotated with `#[ts_interface]` are rendered to `.d.ts` snippets at macro expansion time and emitted as
+//! string constants, so the Rust definitions stay the single source of truth for non-Rust frontends.
+//! The mapping follows serde's default (externally tagged) json representation, which is what tauri uses over IPC.
+use crate::IpcAttrs;
+use syn::{
+    Attribute, Data, DeriveInput, Fields, FnArg, GenericArgument, ItemTrait, LitStr, Pat,
+    PathArguments, ReturnType, TraitItem, Type, TypePath,
+};
+
+/// Renders a rust type to the TypeScript type its serde json representation produces.
+/// Unknown paths are assumed to be other exported payload types and are referenced by name.
+pub(crate) fn ts_type(ty: &Type) -> String {
+    match ty {
+        Type::Reference(r) => ts_type(&r.elem),
+        Type::Paren(p) => ts_type(&p.elem),
+        Type::Group(g) => ts_type(&g.elem),
+        Type::Tuple(t) if t.elems.is_empty() => "null".to_string(),
+        Type::Tuple(t) => format!(
+            "[{}]",
+            t.elems.iter().map(ts_type).collect::<Vec<_>>().join(", ")
+        ),
+        Type::Array(a) => array_of(&ts_type(&a.elem)),
+        Type::Slice(s) => array_of(&ts_type(&s.elem)),
+        Type::Path(TypePath { path, .. }) => {
+            let Some(segment) = path.segments.last() else {
+                return "unknown".to_string();
+            };
+            let generics = generic_types(&segment.arguments);
+            match segment.ident.to_string().as_str() {
+                "String" | "str" | "char" | "PathBuf" | "Path" | "DateTime" | "NaiveDateTime" => {
+                    "string".to_string()
+                }
+                "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64"
+                | "i128" | "isize" | "f32" | "f64" => "number".to_string(),
+                "bool" => "boolean".to_string(),
+                "Vec" | "VecDeque" | "HashSet" | "BTreeSet" => generics
+                    .first()
+                    .map_or("unknown[]".to_string(), |t| array_of(&ts_type(t))),
+                "Option" => generics
+                    .first()
+                    .map_or("unknown".to_string(), |t| format!("{} | null", ts_type(t))),
+                "HashMap" | "BTreeMap" => match generics.as_slice() {
+                    [k, v] => format!("Record<{}, {}>", ts_type(k), ts_type(v)),
+                    _ => "Record<string, unknown>".to_string(),
+                },
+                "Box" | "Rc" | "Arc" | "Cow" => generics
+                    .last()
+                    .map_or("unknown".to_string(), |t| ts_type(t)),
+                other => other.to_string(),
+            }
+        }
+        _ => "unknown".to_string(),
+    }
+}
+
+fn array_of(inner: &str) -> String {
+    if inner.contains('|') {
+        format!("({})[]", inner)
+    } else {
+        format!("{}[]", inner)
+    }
+}
+
+fn generic_types(arguments: &PathArguments) -> Vec<&Type> {
+    match arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The resolved value of a command's return type, `Result<T, E>` resolves to `T` since `E` rejects the promise.
+fn ts_return_type(output: &ReturnType) -> String {
+    match output {
+        ReturnType::Default => "void".to_string(),
+        ReturnType::Type(_, ty) => match crate::extract_result_types(ty) {
+            Some((ok, _)) => match ok {
+                Type::Tuple(t) if t.elems.is_empty() => "void".to_string(),
+                ok => ts_type(ok),
+            },
+            None => ts_type(ty),
+        },
+    }
+}
+
+/// Builds the command section of the definitions from the `API` style trait.
+/// Argument names are camelCased to match the `Args` struct generated by `invoke_bindings`.
+/// Fails on malformed `#[ipc(...)]` attributes rather than leaving their commands out.
+pub(crate) fn commands_definitions(
+    trait_item: &ItemTrait,
+    cmd_prefix: Option<&str>,
+) -> syn::Result<String> {
+    let mut arg_interfaces = String::new();
+    let mut commands = String::new();
+    let mut args_map = String::new();
+    let mut responses_map = String::new();
+    for item in &trait_item.items {
+        let TraitItem::Fn(fn_item) = item else {
+            continue;
+        };
+        let ipc_attrs = IpcAttrs::parse(&fn_item.attrs)?;
+        let fn_name = ipc_attrs.versioned_name(&fn_item.sig.ident.to_string());
+        let cmd_name =
+            ts_key(&cmd_prefix.map_or(fn_name.clone(), |prefix| prefix.to_string() + &fn_name));
+        let args_name = format!("{}Args", to_pascal_case(&fn_name));
+        commands.push_str(&deprecation_doc(&ipc_attrs));
+        let fields = fn_item
+            .sig
+            .inputs
+            .iter()
+            .filter_map(|arg| match arg {
+                FnArg::Typed(pt) => match pt.pat.as_ref() {
+                    Pat::Ident(pi) => Some((to_camel_case(&pi.ident.to_string()), ts_type(&pt.ty))),
+                    _ => None,
+                },
+                FnArg::Receiver(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let ret = ts_return_type(&fn_item.sig.output);
+        if fields.is_empty() {
+            commands.push_str(&format!("  {}(): Promise<{}>;\n", cmd_name, ret));
+            args_map.push_str(&format!("  {}: Record<string, never>;\n", cmd_name));
+        } else {
+            arg_interfaces.push_str(&format!("export interface {} {{\n", args_name));
+            for (name, ty) in &fields {
+                arg_interfaces.push_str(&format!("  {}: {};\n", name, ty));
+            }
+            arg_interfaces.push_str("}\n\n");
+            commands.push_str(&format!(
+                "  {}(args: {}): Promise<{}>;\n",
+                cmd_name, args_name, ret
+            ));
+            args_map.push_str(&format!("  {}: {};\n", cmd_name, args_name));
+        }
+        responses_map.push_str(&format!("  {}: {};\n", cmd_name, ret));
+    }
+    Ok(format!(
+        "{arg_interfaces}export interface Commands {{\n{commands}}}\n\n\
+         export interface CommandArgs {{\n{args_map}}}\n\n\
+         export interface CommandResponses {{\n{responses_map}}}\n\n\
+         export type CommandName = keyof Commands;\n"
+    ))
+}
+
+/// Builds the event section of the definitions from the `derive_events!` list.
+pub(crate) fn events_definitions<'a>(
+    events: impl Iterator<Item = (&'a IpcAttrs, String, &'a Type)>,
+) -> String {
+    let payloads = events
+        .map(|(ipc_attrs, name, ty)| {
+            format!(
+                "{}  {}: {};\n",
+                deprecation_doc(ipc_attrs),
+                ts_key(&ipc_attrs.versioned_name(&name)),
+                ts_type(ty)
+            )
+        })
+        .collect::<String>();
+    format!(
+        "export interface EventPayloads {{\n{payloads}}}\n\n\
+         export type EventName = keyof EventPayloads;\n"
+    )
+}
+
+/// A JSDoc `@deprecated` tag so editors flag uses of deprecated commands and events.
+fn deprecation_doc(ipc_attrs: &IpcAttrs) -> String {
+    ipc_attrs.deprecated.as_ref().map_or(String::new(), |note| {
+        format!("  /** @deprecated {} */\n", note)
+    })
+}
+
+/// Quotes a property name when it is not a valid TypeScript identifier, e.g. a prefixed `plugin:` command.
+fn ts_key(name: &str) -> String {
+    let valid = name
+        .chars()
+        .enumerate()
+        .all(|(i, c)| c == '_' || c == '$' || c.is_alphabetic() || (i > 0 && c.is_numeric()));
+    if valid && !name.is_empty() {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name)
+    }
+}
+
+/// Serde container and field attributes that change the json shape of a type.
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    skip: bool,
+    optional: bool,
+    unsupported: Option<String>,
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> syn::Result<SerdeAttrs> {
+    let mut parsed = SerdeAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            let key = meta
+                .path
+                .get_ident()
+                .map(|i| i.to_string())
+                .unwrap_or_default();
+            match key.as_str() {
+                "rename" => parsed.rename = Some(meta.value()?.parse::<LitStr>()?.value()),
+                "rename_all" => parsed.rename_all = Some(meta.value()?.parse::<LitStr>()?.value()),
+                "skip" | "skip_serializing" => parsed.skip = true,
+                "skip_serializing_if" => parsed.optional = true,
+                "tag" | "content" | "untagged" | "flatten" => parsed.unsupported = Some(key),
+                _ => {}
+            }
+            // consume any remaining value so unrelated serde attributes don't fail parsing
+            if meta.input.peek(syn::Token![=]) {
+                let _: syn::Expr = meta.value()?.parse()?;
+            } else if meta.input.peek(syn::token::Paren) {
+                meta.parse_nested_meta(|_| Ok(()))?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(parsed)
+}
+
+/// Renders a struct or enum to an exported TypeScript declaration.
+pub(crate) fn item_definition(item: &DeriveInput) -> syn::Result<String> {
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item.generics,
+            "ts_interface does not support generic types",
+        ));
+    }
+    let container = serde_attrs(&item.attrs)?;
+    if let Some(key) = container.unsupported {
+        return Err(syn::Error::new_spanned(
+            &item.ident,
+            format!("ts_interface does not support `#[serde({key})]`"),
+        ));
+    }
+    let name = item.ident.to_string();
+    let rename_all = container.rename_all.as_deref();
+    match &item.data {
+        Data::Struct(data) => Ok(match &data.fields {
+            Fields::Named(_) => format!(
+                "export interface {} {}\n",
+                name,
+                fields_body(&data.fields, rename_all, "")?
+            ),
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                format!(
+                    "export type {} = {};\n",
+                    name,
+                    ts_type(&fields.unnamed[0].ty)
+                )
+            }
+            Fields::Unnamed(fields) => format!(
+                "export type {} = [{}];\n",
+                name,
+                fields
+                    .unnamed
+                    .iter()
+                    .map(|f| ts_type(&f.ty))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Fields::Unit => format!("export type {} = null;\n", name),
+        }),
+        Data::Enum(data) => {
+            let mut variants = Vec::new();
+            for variant in &data.variants {
+                let attrs = serde_attrs(&variant.attrs)?;
+                if attrs.skip {
+                    continue;
+                }
+                let tag = attrs.rename.unwrap_or_else(|| {
+                    rename_all.map_or(variant.ident.to_string(), |rule| {
+                        apply_variant_rule(&variant.ident.to_string(), rule)
+                    })
+                });
+                let field_rename = attrs.rename_all.as_deref();
+                variants.push(match &variant.fields {
+                    Fields::Unit => format!("\"{}\"", tag),
+                    Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                        format!("{{ \"{}\": {} }}", tag, ts_type(&fields.unnamed[0].ty))
+                    }
+                    Fields::Unnamed(fields) => format!(
+                        "{{ \"{}\": [{}] }}",
+                        tag,
+                        fields
+                            .unnamed
+                            .iter()
+                            .map(|f| ts_type(&f.ty))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    Fields::Named(_) => format!(
+                        "{{ \"{}\": {} }}",
+                        tag,
+                        fields_body(&variant.fields, field_rename, "  ")?
+                    ),
+                });
+            }
+            if variants.is_empty() {
+                return Ok(format!("export type {} = never;\n", name));
+            }
+            Ok(format!(
+                "export type {} =\n  | {};\n",
+                name,
+                variants.join("\n  | ")
+            ))
+        }
+        Data::Union(_) => Err(syn::Error::new_spanned(
+            &item.ident,
+            "ts_interface does not support unions",
+        )),
+    }
+}
+
+fn fields_body(fields: &Fields, rename_all: Option<&str>, indent: &str) -> syn::Result<String> {
+    let mut body = String::from("{\n");
+    for field in fields {
+        let attrs = serde_attrs(&field.attrs)?;
+        if attrs.skip {
+            continue;
+        }
+        let ident = field
+            .ident
+            .as_ref()
+            .map(|i| i.to_string())
+            .unwrap_or_default();
+        let name = attrs.rename.unwrap_or_else(|| {
+            rename_all.map_or(ident.clone(), |rule| apply_field_rule(&ident, rule))
+        });
+        let optional = if attrs.optional { "?" } else { "" };
+        body.push_str(&format!(
+            "{indent}  {}{}: {};\n",
+            name,
+            optional,
+            ts_type(&field.ty)
+        ));
+    }
+    body.push_str(indent);
+    body.push('}');
+    Ok(body)
+}
+
+/// Splits a snake_case or PascalCase identifier into lower case words.
+fn words(ident: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for c in ident.chars() {
+        if c == '_' || c == '-' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+        } else if c.is_uppercase() && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+            current.extend(c.to_lowercase());
+        } else {
+            current.extend(c.to_lowercase());
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or(String::new(), |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+pub(crate) fn to_pascal_case(ident: &str) -> String {
+    words(ident).iter().map(|w| capitalize(w)).collect()
+}
+
+pub(crate) fn to_camel_case(ident: &str) -> String {
+    let pascal = to_pascal_case(ident);
+    let mut chars = pascal.chars();
+    chars.next().map_or(String::new(), |first| {
+        first.to_lowercase().chain(chars).collect()
+    })
+}
+
+/// Mirrors serde's `rename_all` rules for enum variants, which are written in PascalCase.
+fn apply_variant_rule(variant: &str, rule: &str) -> String {
+    let snake = || {
+        let mut snake = String::new();
+        for (i, c) in variant.char_indices() {
+            if i > 0 && c.is_uppercase() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    };
+    match rule {
+        "lowercase" => variant.to_ascii_lowercase(),
+        "UPPERCASE" => variant.to_ascii_uppercase(),
+        "PascalCase" => variant.to_string(),
+        "camelCase" => variant[..1].to_ascii_lowercase() + &variant[1..],
+        "snake_case" => snake(),
+        "SCREAMING_SNAKE_CASE" => snake().to_ascii_uppercase(),
+        "kebab-case" => snake().replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => snake().to_ascii_uppercase().replace('_', "-"),
+        _ => variant.to_string(),
+    }
+}
+
+/// Mirrors serde's `rename_all` rules for fields, which are written in snake_case.
+fn apply_field_rule(field: &str, rule: &str) -> String {
+    let pascal = || {
+        let mut pascal = String::new();
+        let mut capitalize = true;
+        for c in field.chars() {
+            if c == '_' {
+                capitalize = true;
+            } else if capitalize {
+                pascal.push(c.to_ascii_uppercase());
+                capitalize = false;
+            } else {
+                pascal.push(c);
+            }
+        }
+        pascal
+    };
+    match rule {
+        "lowercase" | "snake_case" => field.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            pascal[..1].to_ascii_lowercase() + &pascal[1..]
+        }
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.to_ascii_uppercase().replace('_', "-"),
+        _ => field.to_string(),
+    }
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "typescript")]
+use ipc_macros;
+use serde::{Deserialize, Serialize};
+
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bob {
+    name: String,
+    #[serde(rename = "years")]
+    age: u32,
+    nicknames: Vec<String>,
+    partner: Option<String>,
+}
+
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Status {
+    Online,
+    AwayFor(u64),
+    LastSeen { node_id: String },
+}
+
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct Peer {
+    node_id: String,
+    last_seen: u64,
+}
+
+#[ipc_macros::ts_interface]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub enum Signal {
+    StoppedTyping,
+    #[serde(rename_all = "lowercase")]
+    ReadUpTo {
+        message_id: String,
+    },
+}
+
+#[ipc_macros::invoke_bindings(cmd_prefix = "plugin:")]
+#[allow(async_fn_in_trait)]
+pub trait Commands {
+    async fn hello(user_name: String, bob: Bob) -> Result<String, String>;
+    async fn bob() -> Result<(), String>;
+}
+
+ipc_macros::derive_events! (
+    ui=#[cfg(test_ui)],
+    tauri=#[cfg(test_tauri)],
+    {
+        ("test_event", Bob),
+        ("status", Vec<Status>),
+    }
+);
+
+#[test]
+fn struct_definition() {
+    assert_eq!(
+        Bob::TS_DEFINITION,
+        "export interface Bob {\n  name: string;\n  years: number;\n  nicknames: string[];\n  partner: string | null;\n}\n"
+    );
+}
+
+#[test]
+fn enum_definition() {
+    assert_eq!(
+        Status::TS_DEFINITION,
+        "export type Status =\n  | \"online\"\n  | { \"awayFor\": number }\n  | { \"lastSeen\": {\n    node_id: string;\n  } };\n"
+    );
+}
+
+#[test]
+fn rename_rules_keep_underscores_like_serde() {
+    assert_eq!(
+        Peer::TS_DEFINITION,
+        "export interface Peer {\n  NODE_ID: string;\n  LAST_SEEN: number;\n}\n"
+    );
+    assert_eq!(
+        Signal::TS_DEFINITION,
+        "export type Signal =\n  | \"STOPPED-TYPING\"\n  | { \"READ-UP-TO\": {\n    message_id: string;\n  } };\n"
+    );
+}
+
+#[test]
+fn command_definitions() {
+    assert!(
+        ts::COMMANDS.contains("export interface HelloArgs {\n  userName: string;\n  bob: Bob;\n}")
+    );
+    assert!(ts::COMMANDS.contains("  \"plugin:hello\"(args: HelloArgs): Promise<string>;\n"));
+    assert!(ts::COMMANDS.contains("  \"plugin:bob\"(): Promise<void>;\n"));
+}
+
+#[test]
+fn event_definitions() {
+    assert!(events::ts::EVENTS.contains("  test_event: Bob;\n  status: Status[];\n"));
+}
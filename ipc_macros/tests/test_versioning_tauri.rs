@@ -0,0 +1,38 @@
+use ipc_macros;
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+#[allow(async_fn_in_trait)]
+pub trait Commands {
+    #[ipc_macros::ipc(since = "2")]
+    async fn hello(name: String) -> Result<Vec<String>, String>;
+}
+
+ipc_macros::impl_trait!(Commands, {
+    #[ipc(since = "2")]
+    #[tauri::command]
+    async fn hello(name: String) -> Result<Vec<String>, String> {
+        Ok(vec![format!("Hello {}", name)])
+    }
+
+    #[ipc(compat)]
+    #[tauri::command]
+    async fn hello(name: String) -> Result<String, String> {
+        hello_v2(name).await.map(|greetings| greetings.join(", "))
+    }
+});
+
+#[test]
+fn versioned_command_names() {
+    assert_eq!(COMMANDS, &["hello_v2", "hello"]);
+}
+
+#[test]
+fn compat_shim_serves_through_the_versioned_command() {
+    let greeting = pin!(hello("Bob".to_string()));
+    assert_eq!(
+        greeting.poll(&mut Context::from_waker(Waker::noop())),
+        Poll::Ready(Ok("Hello Bob".to_string()))
+    );
+}
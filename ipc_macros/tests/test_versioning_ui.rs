@@ -0,0 +1,35 @@
+use ipc_macros;
+
+#[ipc_macros::invoke_bindings]
+#[allow(async_fn_in_trait)]
+pub trait Commands {
+    #[ipc_macros::ipc(since = "2")]
+    async fn hello(name: String) -> Result<String, String>;
+    #[ipc(deprecated = "use `hello` instead")]
+    async fn bob();
+}
+
+ipc_macros::derive_events! (
+    ui=#[cfg(not(test_ui))],
+    tauri=#[cfg(not(test_tauri))],
+    {
+        #[ipc(since = "2")]
+        ("test_event", String),
+        #[ipc(deprecated = "listen to `test_event` instead")]
+        ("old_event", String),
+    }
+);
+
+#[test]
+fn versioned_command_names() {
+    assert_eq!(
+        ui::COMMANDS,
+        &[("hello_v2", None), ("bob", Some("use `hello` instead"))]
+    );
+}
+
+#[test]
+fn versioned_event_names() {
+    assert_eq!(events::ui::test_event::event_name(), "test_event_v2");
+    assert_eq!(events::ui::old_event::event_name(), "old_event");
+}
@@ -1,78 +1,447 @@
-use beelay_protocol::primitives::IrohEvent;
+use beelay_protocol::primitives::{ConnectionType, IrohEvent};
 use beelay_protocol::{
-    CommitOrBundle, DocEvent, DocumentId, NoticeSubscriberClosure, start_beelay_node,
+    CommitOrBundle, DocEvent, DocumentId, NodeId, NoticeSubscriberClosure, start_beelay_node,
 };
-use ipc_layer::events;
-use ipc_layer::tauri::{AppData, MessageWithMetaData, command_handler};
+use ipc_layer::tauri::access::ContactBook;
+use ipc_layer::tauri::blobs::{BlobRequest, BlobStore};
+use ipc_layer::tauri::ephemeral::{self, Envelope, EphemeralHandler, Payload};
+use ipc_layer::tauri::history::ChatHistory;
+use ipc_layer::tauri::identity::{IdentityKeys, IdentityStore, IdentityUnlock};
+use ipc_layer::tauri::invites::InviteStore;
+use ipc_layer::tauri::outbox::Outbox;
+use ipc_layer::tauri::profiles::ProfileStore;
+use ipc_layer::tauri::receipts::ReceiptStore;
+use ipc_layer::tauri::redial::KnownPeers;
+use ipc_layer::tauri::rooms::{RoomRegistry, parse_room};
+use ipc_layer::tauri::storage::DiskStorage;
+use ipc_layer::tauri::{
+    AppData, MessageWithMetaData, command_handler, emit_delivery, emit_peer_status,
+};
+use ipc_layer::{
+    ConnectionPath, ConnectionStatus, DeliveryState, EphemeralSignal, HistoryBackfill,
+    InviteRejection, PeerPath, ReadReceipt, RoomMessage, SignalKind, events,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tauri::async_runtime::{Receiver, channel};
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Manager, RunEvent};
+
+/// Delivers the messages queued for a room in the background, so the event loops keep running meanwhile.
+fn spawn_flush<R: tauri::Runtime>(handle: &AppHandle<R>, room: DocumentId) {
+    let handle = handle.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = handle.state::<AppData>().flush_outbox(&handle, room).await {
+            eprintln!("Failed to flush the outbox of {}: {}", room, e);
+        }
+    });
+}
+
+/// Redials the peer a room was joined through in the background, until it is reachable again.
+fn spawn_redial<R: tauri::Runtime>(handle: &AppHandle<R>, room: DocumentId) {
+    let handle = handle.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = handle.state::<AppData>().redial(&handle, room).await {
+            eprintln!("Failed to redial the peer of {}: {}", room, e);
+        }
+    });
+}
 
 async fn handle_doc_events<R: tauri::Runtime>(
     mut rx: Receiver<(DocumentId, DocEvent)>,
     handle: AppHandle<R>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) {
     let this_node_id = handle.state::<AppData>().beelay_protocol.node_id();
-    let mut recent_timestamp: i64 = 0;
     while let Some((doc_id, doc_event)) = rx.recv().await {
-        match doc_event {
-            DocEvent::Data { data } => {
-                match data {
-                    CommitOrBundle::Commit(commit) => {
-                        let contents = commit.contents();
+        // a failing event is logged, ending the loop would stop message handling for every room
+        if let Err(e) = handle_doc_event(handle.clone(), this_node_id, doc_id, doc_event).await {
+            eprintln!("Failed to handle an event of {}: {}", doc_id, e);
+        }
+    }
+}
+
+async fn handle_doc_event<R: tauri::Runtime>(
+    handle: AppHandle<R>,
+    this_node_id: NodeId,
+    doc_id: DocumentId,
+    doc_event: DocEvent,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // beelay keeps syncing documents of rooms we left, their events are not ours to handle anymore
+    if handle.state::<AppData>().rooms.has_left(&doc_id)? {
+        return Ok(());
+    }
+    match doc_event {
+        DocEvent::Data { data } => {
+            match data {
+                // skip commits and bundles that don't decode (e.g. written by an older version)
+                // instead of stopping the event loop for every room
+                CommitOrBundle::Commit(commit) => {
+                    match MessageWithMetaData::decode(commit.contents()) {
+                        Ok(Some(message)) => {
+                            // persist everything, including our own messages, so history is complete on restart.
+                            // messages already recorded (sent by this node or synced before) are not emitted again
+                            let state = handle.state::<AppData>();
+                            let is_new = state.ingest(&doc_id, &message)?;
+                            let verified = message.verify(&doc_id);
+                            if is_new {
+                                // the author learns we received it
+                                state.acknowledge(&doc_id, [&message]);
+                            }
+                            if verified && message.peer_id == this_node_id {
+                                // the local commit of a message we are sending
+                                if let Some(update) = state
+                                    .deliveries
+                                    .advance(message.message.id(), DeliveryState::Committed)?
+                                {
+                                    emit_delivery(&handle, update)?;
+                                }
+                            } else if let (true, Some(profile)) =
+                                (is_new, message.message.profile())
+                            {
+                                // profile updates only count when their author signed them
+                                if verified {
+                                    state.receive_profile(&handle, message.peer_id, profile)?;
+                                }
+                            } else if let (true, Some(revision)) =
+                                (is_new, message.message.revision())
+                            {
+                                // edits and deletions change the message they revise instead of showing up
+                                state
+                                    .emit_revised(&handle, &doc_id, &revision.target)
+                                    .await?;
+                            } else if is_new {
+                                let id = message.message.id().to_string();
+                                if !verified {
+                                    // shown flagged, the author may have been impersonated
+                                    eprintln!(
+                                        "Message {} in {} is not signed by its author {}",
+                                        message.message.id(),
+                                        doc_id,
+                                        message.peer_id
+                                    );
+                                }
+                                events::tauri::conversation(RoomMessage {
+                                    room: doc_id.to_string(),
+                                    message: message.message,
+                                    author: message.peer_id.to_string(),
+                                    clock: message.clock,
+                                    verified,
+                                })
+                                .emit(&handle)?;
+                                // the author may be new to the room, and is active either way
+                                if verified {
+                                    state.emit_roster(&handle, &doc_id)?;
+                                }
+                                // revisions that arrived ahead of the message apply now
+                                state.emit_revised(&handle, &doc_id, &id).await?;
+                            }
+                        }
                         // ensure we don't capture empty messages, like the initial commits
-                        if contents.len() > 0 {
-                            let message: MessageWithMetaData =
-                                postcard::from_bytes(commit.contents())?;
-                            let new_timestamp = message.timestamp().timestamp();
-                            // prevent replay of this node's messages and prevent already seen timestamps
-                            if message.peer_id != this_node_id && new_timestamp > recent_timestamp {
-                                recent_timestamp = new_timestamp;
-                                events::tauri::conversation(message.message).emit(&handle)?;
+                        Ok(None) => {}
+                        Err(e) => eprintln!("Skipping undecodable commit in {}: {}", doc_id, e),
+                    }
+                }
+                // a peer compacted part of the document, backfill the messages we have not seen yet so
+                // late joiners get the full conversation
+                CommitOrBundle::Bundle(bundle) => {
+                    match MessageWithMetaData::decode_bundle(bundle.contents()) {
+                        Ok(messages) => {
+                            let state = handle.state::<AppData>();
+                            let mut backfill = Vec::new();
+                            let mut revised = Vec::new();
+                            let mut received = Vec::new();
+                            for message in messages {
+                                if !state.ingest(&doc_id, &message)? {
+                                    continue;
+                                }
+                                received.push(message.clone());
+                                if let Some(profile) = message.message.profile() {
+                                    if message.verify(&doc_id) {
+                                        state.receive_profile(&handle, message.peer_id, profile)?;
+                                    }
+                                    continue;
+                                }
+                                match message.message.revision() {
+                                    Some(revision) => revised.push(revision.target.clone()),
+                                    None => {
+                                        backfill.push(state.chat_message(&doc_id, message).await?)
+                                    }
+                                }
+                            }
+                            backfill.sort_by(|a, b| {
+                                (a.clock, a.message.id()).cmp(&(b.clock, b.message.id()))
+                            });
+                            if !backfill.is_empty() {
+                                events::tauri::history_backfill(HistoryBackfill {
+                                    room: doc_id.to_string(),
+                                    messages: backfill,
+                                })
+                                .emit(&handle)?;
+                                state.emit_roster(&handle, &doc_id)?;
                             }
+                            for id in revised {
+                                state.emit_revised(&handle, &doc_id, &id).await?;
+                            }
+                            state.acknowledge(&doc_id, &received);
                         }
+                        Err(e) => eprintln!("Skipping undecodable bundle in {}: {}", doc_id, e),
                     }
-                    // this is a collection of commit hashes, we don't do anything with this yet
-                    CommitOrBundle::Bundle(bundle) => {}
-                };
-            }
-            DocEvent::Discovered => {
-                let state = handle.state::<AppData>();
-                // todo: is this the best way to manage setting the document id? We need it for most actions, but investigate a cleaner ordering, OnceCells are awesome though and at least make managing this easy for the time being.
-                let _ = state.set_document_id(doc_id);
-                // todo: this is a hack, it is pretty useless sending this string, clean this up.
-                events::tauri::connection("connected".into()).emit(&handle)?;
+                }
+            };
+        }
+        DocEvent::Discovered => {
+            // rooms discovered before any of their members connected report their status once one does
+            let state = handle.state::<AppData>();
+            if let Some(peer) = state.rooms.discovered(doc_id)? {
+                state.presence.connected(peer)?;
+                emit_peer_status(&handle, &doc_id, &peer, ConnectionStatus::Connected)?;
+                state.emit_roster(&handle, &doc_id)?;
             }
-            DocEvent::AccessChanged { .. } => {}
+            // messages typed before the document was discovered can be sent now
+            spawn_flush(&handle, doc_id);
+            // the peers of the room learn our profile from its document, it may have been set before we joined
+            let publisher = handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = publisher.state::<AppData>();
+                if let Err(e) = state.publish_profile(&publisher, doc_id).await {
+                    eprintln!("Failed to publish our profile to {}: {}", doc_id, e);
+                }
+            });
+        }
+        DocEvent::AccessChanged { .. } => {
+            // the event doesn't say whose access changed, so the room's access is sent as a whole
+            let state = handle.state::<AppData>();
+            state.emit_access(&handle, &doc_id).await?;
+            // revisions by admins are authorized against the current roles, which may have changed with it
+            state.emit_all_revised(&handle, &doc_id).await?;
         }
     }
     Ok(())
 }
 
-async fn handle_connections<R: tauri::Runtime>(
-    mut rx: Receiver<IrohEvent>,
+async fn handle_connections<R: tauri::Runtime>(mut rx: Receiver<IrohEvent>, handle: AppHandle<R>) {
+    // send the status and path of the connection to frontend for every room the connection dialed or received serves
+    while let Some(iroh_event) = rx.recv().await {
+        if let Err(e) = handle_connection(handle.clone(), iroh_event) {
+            eprintln!("Failed to handle a connection event: {}", e);
+        }
+    }
+}
+
+fn handle_connection<R: tauri::Runtime>(
     handle: AppHandle<R>,
+    iroh_event: IrohEvent,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // send connection type to frontend for the most recent connection dialed or received
-    while let Some(iroh_event) = rx.recv().await {
-        let (node_ticket, connection_type) = iroh_event.unpack();
-        // convert to string to avoid import of beelay protocol types in the frontend for now
-        events::tauri::connection_type(format!("{:?}", connection_type)).emit(&handle)?;
-        let state = handle.state::<AppData>();
-        let _ = state.set_node_ticket(node_ticket);
+    let (node_ticket, connection_type) = iroh_event.unpack();
+    let peer = node_ticket.node_addr().node_id;
+    let state = handle.state::<AppData>();
+    let rooms = state.rooms.connected(node_ticket)?;
+    let path = connection_path(&connection_type);
+    match path {
+        Some(_) => state.presence.connected(peer)?,
+        None => state.presence.disconnected(peer)?,
+    }
+    for room in rooms {
+        state.emit_roster(&handle, &room)?;
+        let Some(path) = path else {
+            // the peer the room was joined through is dialed again, others redial us if they can
+            if state.known_peer(&room)? == Some(peer) {
+                spawn_redial(&handle, room);
+            } else {
+                emit_peer_status(&handle, &room, &peer, ConnectionStatus::Disconnected)?;
+            }
+            continue;
+        };
+        state.redials.stop(&room)?;
+        emit_peer_status(&handle, &room, &peer, ConnectionStatus::Connected)?;
+        events::tauri::connection_type(PeerPath {
+            room: room.to_string(),
+            peer: peer.to_string(),
+            path,
+        })
+        .emit(&handle)?;
+        spawn_flush(&handle, room);
     }
     Ok(())
 }
 
-async fn setup<R: tauri::Runtime>(handle: tauri::AppHandle<R>) -> anyhow::Result<()> {
+async fn handle_signals<R: tauri::Runtime>(
+    mut rx: Receiver<(NodeId, Envelope)>,
+    handle: AppHandle<R>,
+) {
+    while let Some((peer, envelope)) = rx.recv().await {
+        if let Err(e) = handle_signal(handle.clone(), peer, envelope).await {
+            eprintln!("Failed to handle a signal of {}: {}", peer, e);
+        }
+    }
+}
+
+async fn handle_blob_requests<R: tauri::Runtime>(
+    mut rx: Receiver<BlobRequest>,
+    handle: AppHandle<R>,
+) {
+    while let Some(request) = rx.recv().await {
+        let allowed = handle
+            .state::<AppData>()
+            .may_fetch(&request.peer, &request.hash)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to check a blob request of {}: {}", request.peer, e);
+                false
+            });
+        // the peer may have given up waiting
+        let _ = request.allowed.send(allowed);
+    }
+}
+
+async fn handle_signal<R: tauri::Runtime>(
+    handle: AppHandle<R>,
+    peer: NodeId,
+    envelope: Envelope,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let state = handle.state::<AppData>();
+    // peers joining through an invite are not members yet and don't know the room until the invite is accepted
+    match envelope.payload {
+        Payload::Redeem {
+            invite_id,
+            contact_card,
+        } => {
+            let reply = state
+                .redeem_invite(&handle, peer, &invite_id, &contact_card)
+                .await?;
+            if let Payload::InviteRejected { reason, .. } = &reply {
+                eprintln!(
+                    "Refused invite {} redeemed by {}: {}",
+                    invite_id, peer, reason
+                );
+            }
+            state.signal_peer(peer, Envelope::new(String::new(), reply));
+            return Ok(());
+        }
+        Payload::Admitted { invite_id, ticket } => {
+            state
+                .join_invited(&handle, peer, &invite_id, &ticket)
+                .await?;
+            return Ok(());
+        }
+        Payload::InviteRejected { invite_id, reason } => {
+            // only the node the invite was redeemed with may refuse it
+            if state.pending_invites.take(&invite_id, &peer)?.is_some() {
+                events::tauri::invite_rejected(InviteRejection { invite_id, reason })
+                    .emit(&handle)?;
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+    // only peers of a room may signal in it
+    let Ok(room) = parse_room(&envelope.room) else {
+        return Ok(());
+    };
+    if !state
+        .rooms
+        .members(&room)
+        .is_ok_and(|members| members.contains(&peer))
+    {
+        return Ok(());
+    }
+    // an envelope means the peer is connected and active
+    state.presence.connected(peer)?;
+    match envelope.payload {
+        Payload::Signal(kind) => {
+            if kind == SignalKind::Ping {
+                state.emit_roster(&handle, &room)?;
+                // the peer opened the room, possibly after a restart, so it learns where we are
+                let this_node_id = state.beelay_protocol.node_id();
+                if let Some(message_id) = state.receipts.marker(&room, &this_node_id)? {
+                    let payload = Payload::Read { message_id };
+                    state.signal_peer(peer, Envelope::new(envelope.room.clone(), payload));
+                }
+            }
+            events::tauri::signal(EphemeralSignal {
+                room: envelope.room,
+                peer: peer.to_string(),
+                kind,
+                expires_at: envelope.expires_at,
+            })
+            .emit(&handle)?;
+        }
+        // handled before the membership check
+        Payload::Redeem { .. } | Payload::Admitted { .. } | Payload::InviteRejected { .. } => {}
+        Payload::Offline => {
+            state.presence.disconnected(peer)?;
+            state.emit_roster(&handle, &room)?;
+        }
+        Payload::Received { message_ids } => {
+            for id in message_ids {
+                if let Some(update) = state.deliveries.acknowledge(&room, &id)? {
+                    emit_delivery(&handle, update)?;
+                }
+            }
+        }
+        Payload::Read { message_id } => {
+            // markers of messages we have not received yet are sent again on the next ping
+            let Some(clock) = state.message_clock(&room, &message_id)? else {
+                return Ok(());
+            };
+            if state.receipts.mark(&room, &peer, &message_id, clock)? {
+                events::tauri::receipt(ReadReceipt {
+                    room: envelope.room,
+                    peer: peer.to_string(),
+                    message_id,
+                })
+                .emit(&handle)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The network path of a connection, `None` when there is no path to the peer.
+fn connection_path(connection_type: &ConnectionType) -> Option<ConnectionPath> {
+    match connection_type {
+        ConnectionType::Direct(_) => Some(ConnectionPath::Direct),
+        ConnectionType::Relay(_) => Some(ConnectionPath::Relay),
+        ConnectionType::Mixed(..) => Some(ConnectionPath::Mixed),
+        ConnectionType::None => None,
+    }
+}
+
+/// Loads the node identity, waiting for the UI to unlock it through `unlock_identity` when it is passphrase protected.
+async fn load_identity(
+    identity_store: &IdentityStore,
+    mut rx_unlocked: Receiver<IdentityKeys>,
+) -> anyhow::Result<IdentityKeys> {
+    if identity_store.is_encrypted().map_err(anyhow::Error::msg)? {
+        tracing::info!("waiting for the identity to be unlocked...");
+        rx_unlocked
+            .recv()
+            .await
+            .ok_or(anyhow::anyhow!("identity unlock channel closed"))
+    } else {
+        identity_store
+            .load_or_generate(None)
+            .map_err(anyhow::Error::msg)
+    }
+}
+
+async fn setup<R: tauri::Runtime>(
+    handle: tauri::AppHandle<R>,
+    data_dir: PathBuf,
+    identity_store: IdentityStore,
+    rx_unlocked: Receiver<IdentityKeys>,
+) -> anyhow::Result<()> {
+    let identity = load_identity(&identity_store, rx_unlocked).await?;
+
     let (tx, mut rx) = channel(100);
     let (tx_iroh, rx_iroh) = channel(100);
+    let (tx_signals, rx_signals) = channel(100);
+    let (tx_blob_requests, rx_blob_requests) = channel(100);
 
     // Note: this is a messy bit of code since types cannot implement impl traits.
     let notice_closure: NoticeSubscriberClosure =
         Box::new(move |doc_id: DocumentId, event: DocEvent| {
             let tx = tx.clone();
             Box::pin(async move {
-                // todo: in the future, it may be worth processing these events to allow for document separation for multiple chats
                 let send_result = tx.send((doc_id, event)).await;
                 // throw out results for now...
                 match send_result {
@@ -84,31 +453,118 @@ async fn setup<R: tauri::Runtime>(handle: tauri::AppHandle<R>) -> anyhow::Result
             })
         });
 
-    let (router, beelay_protocol) = start_beelay_node(notice_closure, Some(tx_iroh)).await?;
-    let app_data = AppData::new(router, beelay_protocol);
+    let history = ChatHistory::open(data_dir.join("history.redb")).map_err(anyhow::Error::msg)?;
+    // rooms and their titles are known before beelay discovers their documents again
+    let rooms = RoomRegistry::open(data_dir.join("rooms.redb")).map_err(anyhow::Error::msg)?;
+    // messages waiting for their room to become reachable are kept across restarts
+    let outbox = Outbox::open(data_dir.join("outbox.redb")).map_err(anyhow::Error::msg)?;
+    let receipts =
+        ReceiptStore::open(data_dir.join("receipts.redb")).map_err(anyhow::Error::msg)?;
+    let profiles =
+        ProfileStore::open(data_dir.join("profiles.redb")).map_err(anyhow::Error::msg)?;
+    let contacts = ContactBook::open(data_dir.join("contacts.redb")).map_err(anyhow::Error::msg)?;
+    let invites = InviteStore::open(data_dir.join("invites.redb")).map_err(anyhow::Error::msg)?;
+    // the peers rooms were joined through, dialed again after a restart or when the connection drops
+    let known_peers =
+        KnownPeers::open(data_dir.join("known_peers.redb")).map_err(anyhow::Error::msg)?;
+    let resumed_rooms = known_peers.rooms().map_err(anyhow::Error::msg)?;
+    // attachments are shared between the commands and the handler serving them to peers
+    let blobs = Arc::new(BlobStore::open(data_dir.join("blobs")).map_err(anyhow::Error::msg)?);
+    // documents and keyhive state are kept on disk so we resume syncing the same documents after a restart,
+    // only exchanging the deltas with peers.
+    // shared with the app, which drops the documents of rooms it leaves
+    let storage =
+        Arc::new(DiskStorage::open(data_dir.join("beelay.redb")).map_err(anyhow::Error::msg)?);
+
+    let (router, beelay_protocol) = start_beelay_node(
+        notice_closure,
+        Some(tx_iroh),
+        storage.clone(),
+        identity.node_secret_key(),
+        identity.keyhive_signing_key(),
+        // typing indicators and other ephemeral signals travel next to beelay, outside the documents, as do
+        // attachment blobs
+        Some((
+            ephemeral::ALPN,
+            EphemeralHandler::new(tx_signals, blobs.clone(), tx_blob_requests),
+        )),
+    )
+    .await?;
+    let app_data = AppData::new(
+        router,
+        beelay_protocol,
+        history,
+        identity,
+        identity_store,
+        rooms,
+        outbox,
+        receipts,
+        profiles,
+        contacts,
+        invites,
+        known_peers,
+        blobs,
+        storage,
+    );
+    // messages left in the outbox by the previous run can be retried right away
+    app_data.restore_deliveries().map_err(anyhow::Error::msg)?;
     handle.manage(app_data);
 
     let handle1 = handle.clone();
-    tauri::async_runtime::spawn(async move {
-        if let Err(e) = handle_doc_events(rx, handle1).await {
-            eprintln!("Task error: {}", e);
-        }
-    });
+    tauri::async_runtime::spawn(handle_doc_events(rx, handle1));
 
     let handle2 = handle.clone();
+    tauri::async_runtime::spawn(handle_connections(rx_iroh, handle2));
+
+    let handle3 = handle.clone();
+    tauri::async_runtime::spawn(handle_signals(rx_signals, handle3));
+
+    let handle4 = handle.clone();
+    tauri::async_runtime::spawn(handle_blob_requests(rx_blob_requests, handle4));
+
+    // peers going idle change nothing else, their rooms are told once they count as away
+    let handle5 = handle.clone();
     tauri::async_runtime::spawn(async move {
-        if let Err(e) = handle_connections(rx_iroh, handle2).await {
-            eprintln!("Task error: {}", e);
-        }
+        handle5.state::<AppData>().watch_presence(&handle5).await;
     });
 
+    for room in resumed_rooms {
+        spawn_redial(&handle, room);
+    }
+
     Ok(())
 }
 
+/// Set once the node wound down, so the exit requested afterwards goes through.
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Holds the exit back until the node wound down, quitting would otherwise cut off in-flight syncs.
+fn on_exit_requested<R: tauri::Runtime>(
+    handle: &AppHandle<R>,
+    code: Option<i32>,
+    api: tauri::ExitRequestApi,
+) {
+    if SHUT_DOWN.load(Ordering::SeqCst) {
+        return;
+    }
+    // the node may not run yet, e.g. while the identity is locked
+    if handle.try_state::<AppData>().is_none() {
+        return;
+    }
+    api.prevent_exit();
+    let handle = handle.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = handle.state::<AppData>().shutdown(&handle).await {
+            eprintln!("Failed to shut down cleanly: {}", e);
+        }
+        SHUT_DOWN.store(true, Ordering::SeqCst);
+        handle.exit(code.unwrap_or_default());
+    });
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // todo: add more tracing integration to this app.
-    // todo: add persistence of chats, will require changes to beely protocol
     let subscriber = tracing_subscriber::fmt()
         // Use a more compact, abbreviated log format
         .compact()
@@ -130,9 +586,15 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
             let handle = app.handle().clone();
+            let data_dir = app.path().app_data_dir()?;
+            std::fs::create_dir_all(&data_dir)?;
+            // the identity is needed before the node starts, so unlocking it can't depend on `AppData`
+            let identity_store = IdentityStore::new(data_dir.join("identity.bin"));
+            let (tx_unlocked, rx_unlocked) = channel(1);
+            app.manage(IdentityUnlock::new(identity_store.clone(), tx_unlocked));
             tauri::async_runtime::spawn(async move {
                 println!("starting backend...");
-                if let Err(err) = setup(handle).await {
+                if let Err(err) = setup(handle, data_dir, identity_store, rx_unlocked).await {
                     eprintln!("failed: {:?}", err);
                 }
             });
@@ -144,6 +606,11 @@ pub fn run() {
         .invoke_handler(command_handler())
         // NOTE: This shows as an error in Rustrover, but it is not an issue!
         // It just can't reconcile the build context with the ipc_macros crate in this workspace.
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|handle, event| {
+            if let RunEvent::ExitRequested { code, api, .. } = event {
+                on_exit_requested(handle, code, api);
+            }
+        });
 }
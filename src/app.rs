@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use fast_qr::convert::{Builder, Shape, svg::SvgBuilder};
 use fast_qr::qr::QRBuilder;
 use futures::StreamExt;
@@ -7,66 +7,938 @@ use ipc_layer::events;
 use leptos::logging::log;
 use leptos::prelude::*;
 use leptos::task::spawn_local;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
 use tauri_sys::event::listen;
 
 /// Delineate incoming vs outgoing messages in the chat so they can render differently.
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LabeledMessage {
-    Incoming(api::Message),
+    /// A message, the node id of its author and whether the author's signature was verified.
+    Incoming(api::Message, String, bool),
     Outgoing(api::Message),
 }
 
 impl LabeledMessage {
-    pub fn timestamp(&self) -> &DateTime<Utc> {
+    pub fn id(&self) -> &str {
         match self {
-            LabeledMessage::Incoming(m) => m.timestamp(),
-            LabeledMessage::Outgoing(m) => m.timestamp(),
+            LabeledMessage::Incoming(m, ..) => m.id(),
+            LabeledMessage::Outgoing(m) => m.id(),
         }
     }
+
+    /// Whether the author's signature was verified, our own messages are signed by this node.
+    pub fn is_verified(&self) -> bool {
+        !matches!(self, LabeledMessage::Incoming(_, _, false))
+    }
+}
+
+impl From<api::ChatMessage> for LabeledMessage {
+    fn from(entry: api::ChatMessage) -> Self {
+        match entry.outgoing {
+            true => LabeledMessage::Outgoing(entry.message),
+            false => LabeledMessage::Incoming(entry.message, entry.author, entry.verified),
+        }
+    }
+}
+
+/// Inserts a message at its position in the room's clock order (ties broken by id), skipping messages already shown.
+/// A verified message replaces an unverified one shown with its id, which may have been forged.
+fn insert_ordered(
+    messages: &mut Vec<(api::HybridTimestamp, LabeledMessage)>,
+    clock: api::HybridTimestamp,
+    message: LabeledMessage,
+) {
+    if let Some(index) = messages
+        .iter()
+        .position(|(_, shown)| shown.id() == message.id())
+    {
+        if messages[index].1.is_verified() || !message.is_verified() {
+            return;
+        }
+        messages.remove(index);
+    }
+    let position = messages
+        .partition_point(|(shown_clock, shown)| (*shown_clock, shown.id()) < (clock, message.id()));
+    messages.insert(position, (clock, message));
+}
+
+/// The revision already folded into a message loaded from the history, if it was edited or deleted.
+fn revised_entry(room: &str, entry: &api::ChatMessage) -> Option<api::RevisedMessage> {
+    (entry.edited_at.is_some() || entry.deleted).then(|| api::RevisedMessage {
+        room: room.to_string(),
+        id: entry.message.id().to_string(),
+        text: entry.message.clone().unpack_for_html_integration().0,
+        edited_at: entry.edited_at,
+        deleted: entry.deleted,
+    })
+}
+
+/// Status line of an outgoing message, nothing for messages loaded from the history.
+fn delivery_status(delivery: Option<api::DeliveryState>, on_retry: Callback<()>) -> impl IntoView {
+    match delivery {
+        None => ().into_any(),
+        Some(api::DeliveryState::Pending) => view! { <span>"Sending"</span> }.into_any(),
+        Some(api::DeliveryState::Committed) => view! { <span>"Sent"</span> }.into_any(),
+        Some(api::DeliveryState::Synced) => view! { <span>"Delivered"</span> }.into_any(),
+        Some(api::DeliveryState::Failed { reason }) => view! {
+            <span class="text-red-500" title=reason>
+                "Failed "
+            </span>
+            <button class="underline" on:click=move |_| on_retry.run(())>
+                "Retry"
+            </button>
+        }
+        .into_any(),
+    }
 }
 
+/// Edit and delete buttons of a message, edits are typed inline in place of the buttons.
+fn revision_controls(
+    editable: bool,
+    deletable: Signal<bool>,
+    deleted: Signal<bool>,
+    text: Signal<String>,
+    on_edit: Callback<String>,
+    on_delete: Callback<()>,
+) -> impl IntoView {
+    let (editing, set_editing) = signal(false);
+    let (draft, set_draft) = signal(String::new());
+    let save = move |_| {
+        on_edit.run(draft.get());
+        set_editing.set(false);
+    };
+    view! {
+        <Show when=move || !deleted.get()>
+            <Show
+                when=move || editing.get()
+                fallback=move || {
+                    view! {
+                        <span class="space-x-2">
+                            <Show when=move || editable>
+                                <button
+                                    class="underline"
+                                    on:click=move |_| {
+                                        set_draft.set(text.get());
+                                        set_editing.set(true);
+                                    }
+                                >
+                                    "Edit"
+                                </button>
+                            </Show>
+                            <Show when=move || deletable.get()>
+                                <button class="underline" on:click=move |_| on_delete.run(())>
+                                    "Delete"
+                                </button>
+                            </Show>
+                        </span>
+                    }
+                }
+            >
+                <span class="flex items-center space-x-1">
+                    <input
+                        type="text"
+                        class="flex-1 min-w-0 px-2 py-1 border border-gray-300 dark:border-gray-600 rounded bg-white dark:bg-gray-700 text-gray-900 dark:text-white"
+                        prop:value=draft
+                        on:input=move |ev| set_draft.set(event_target_value(&ev))
+                    />
+                    <button class="underline" on:click=save>
+                        "Save"
+                    </button>
+                    <button class="underline" on:click=move |_| set_editing.set(false)>
+                        "Cancel"
+                    </button>
+                </span>
+            </Show>
+        </Show>
+    }
+}
+
+/// A round avatar of a peer: the image of its profile once it is downloaded from the peers of the room, otherwise
+/// the initial of its display name.
 #[component]
-pub fn Message(msg: LabeledMessage) -> impl IntoView {
+pub fn Avatar(
+    room: String,
+    /// Node id of the peer.
+    peer: String,
+    profile: Option<api::Profile>,
+    /// Size classes of the avatar.
+    class: &'static str,
+    /// Shown on hover before the name of the peer.
+    #[prop(optional)]
+    title: &'static str,
+) -> impl IntoView {
+    let name = profile
+        .as_ref()
+        .map(|profile| profile.display_name.clone())
+        .filter(|name| !name.is_empty());
+    let initial = name
+        .as_ref()
+        .and_then(|name| name.chars().next())
+        .map(|initial| initial.to_uppercase().collect::<String>())
+        .unwrap_or_else(|| "?".to_string());
+    let name = name.unwrap_or_else(|| peer.chars().take(8).collect());
+    // signal holding the image as a data URL once it is downloaded
+    let (data_url, set_data_url) = signal(None::<String>);
+    if let Some(image) = profile.and_then(|profile| profile.avatar).filter(|image| {
+        image.mime.starts_with("image/") && image.size <= api::MAX_INLINE_IMAGE_SIZE
+    }) {
+        spawn_local(async move {
+            let shown = async {
+                api::ui::fetch_attachment(room, image.clone()).await?;
+                api::ui::attachment_data_url(image).await
+            };
+            match shown.await {
+                Ok(url) => set_data_url.set(Some(url)),
+                Err(e) => log!("Failed to load the avatar: {}", e),
+            }
+        });
+    }
+    let alt = name.clone();
+    view! {
+        <span
+            class=format!(
+                "inline-flex {class} rounded-full overflow-hidden bg-gray-400 text-white items-center justify-center",
+            )
+            title=format!("{title}{name}")
+        >
+            {move || match data_url.get() {
+                Some(src) => {
+                    view! { <img src=src alt=alt.clone() class="w-full h-full object-cover" /> }.into_any()
+                }
+                None => initial.clone().into_any(),
+            }}
+        </span>
+    }
+}
+
+/// Small avatars of the peers (node id and profile) whose last read message this is.
+fn seen_by_avatars(room: String, peers: Vec<(String, Option<api::Profile>)>) -> impl IntoView {
+    peers
+        .into_iter()
+        .map(|(peer, profile)| {
+            view! {
+                <Avatar
+                    room=room.clone()
+                    peer=peer
+                    profile=profile
+                    class="w-4 h-4 ml-1 text-[8px]"
+                    title="Seen by "
+                />
+            }
+        })
+        .collect_view()
+}
+
+fn file_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// A file attached to a message.  Images are downloaded right away and shown inline, any attachment can be shown in
+/// its folder or saved, which downloads it from the peers of the room first.
+#[component]
+pub fn Attachment(
+    room: String,
+    attachment: api::Attachment,
+    /// Bytes received while the attachment is downloaded.
+    progress: Signal<Option<u64>>,
+) -> impl IntoView {
+    let inline =
+        attachment.mime.starts_with("image/") && attachment.size <= api::MAX_INLINE_IMAGE_SIZE;
+    let size = attachment.size;
+    let label = format!("{} ({})", attachment.name, file_size(attachment.size));
+    let alt = attachment.name.clone();
+    let room = StoredValue::new(room);
+    let attachment = StoredValue::new(attachment);
+    // signal holding the image as a data URL once it is downloaded
+    let (data_url, set_data_url) = signal(None::<String>);
+    // signal to present the outcome of revealing or saving the attachment
+    let (status, set_status) = signal(String::new());
+    // signal to handle the path the user saves the attachment to
+    let (save_path, set_save_path) = signal(String::new());
+    // downloads the attachment unless it is stored already
+    let fetch = move || async move {
+        api::ui::fetch_attachment(room.get_value(), attachment.get_value()).await
+    };
+    if inline {
+        spawn_local(async move {
+            let shown = async {
+                fetch().await?;
+                api::ui::attachment_data_url(attachment.get_value()).await
+            };
+            match shown.await {
+                Ok(url) => set_data_url.set(Some(url)),
+                Err(e) => set_status.set(format!("Failed to load the image: {}", e)),
+            }
+        });
+    }
+    let reveal = move |_| {
+        spawn_local(async move {
+            let revealed = async {
+                fetch().await?;
+                api::ui::reveal_attachment(attachment.get_value()).await
+            };
+            if let Err(e) = revealed.await {
+                set_status.set(format!("Failed to show in folder: {}", e));
+            }
+        });
+    };
+    let save = move |_| {
+        let path = save_path.get();
+        if path.is_empty() {
+            set_status.set("Enter the path to save to".to_string());
+            return;
+        }
+        spawn_local(async move {
+            let saved = async {
+                fetch().await?;
+                api::ui::save_attachment(attachment.get_value(), path).await
+            };
+            match saved.await {
+                Ok(()) => set_status.set("Saved".to_string()),
+                Err(e) => set_status.set(format!("Failed to save: {}", e)),
+            }
+        });
+    };
+    let downloading = move || {
+        progress
+            .get()
+            .filter(|received| *received < size)
+            .map(|received| format!(" {}%", received * 100 / size.max(1)))
+            .unwrap_or_default()
+    };
+    view! {
+        <div class="mt-2 space-y-1 text-xs">
+            {move || {
+                data_url
+                    .get()
+                    .map(|src| view! { <img src=src alt=alt.clone() class="max-w-full max-h-64 rounded" /> })
+            }}
+            <p class="break-all">{label} {downloading}</p>
+            <div class="flex items-center space-x-1">
+                <button
+                    on:click=reveal
+                    class="px-2 py-1 rounded bg-gray-200 dark:bg-gray-600 text-gray-900 dark:text-white hover:bg-gray-300 dark:hover:bg-gray-500"
+                >
+                    "Show in folder"
+                </button>
+                <input
+                    type="text"
+                    class="flex-1 min-w-0 px-2 py-1 border border-gray-300 dark:border-gray-600 rounded bg-white dark:bg-gray-700 text-gray-900 dark:text-white"
+                    placeholder="Save to path..."
+                    prop:value=save_path
+                    on:input=move |ev| set_save_path.set(event_target_value(&ev))
+                />
+                <button
+                    on:click=save
+                    class="px-2 py-1 rounded bg-gray-200 dark:bg-gray-600 text-gray-900 dark:text-white hover:bg-gray-300 dark:hover:bg-gray-500"
+                >
+                    Save
+                </button>
+            </div>
+            <p>{move || status.get()}</p>
+        </div>
+    }
+}
+
+/// Renders the attachments of a message, with the download progress reported per attachment hash.
+fn attachments(
+    room: String,
+    attachments: Vec<api::Attachment>,
+    progress: ReadSignal<HashMap<String, u64>>,
+) -> impl IntoView {
+    attachments
+        .into_iter()
+        .map(|attachment| {
+            let hash = attachment.hash.clone();
+            let progress =
+                Signal::derive(move || progress.with(|progress| progress.get(&hash).copied()));
+            view! { <Attachment room=room.clone() attachment=attachment progress=progress /> }
+        })
+        .collect_view()
+}
+
+#[component]
+pub fn Message(
+    msg: LabeledMessage,
+    room: String,
+    delivery: Signal<Option<api::DeliveryState>>,
+    on_retry: Callback<()>,
+    /// The peers that read up to this message, with their profiles.
+    seen_by: Signal<Vec<(String, Option<api::Profile>)>>,
+    author: Signal<String>,
+    author_profile: Signal<Option<api::Profile>>,
+    /// Bytes received of the attachments being downloaded, keyed by hash.
+    progress: ReadSignal<HashMap<String, u64>>,
+    /// The latest edit or the deletion of the message, once one arrived.
+    revised: Signal<Option<api::RevisedMessage>>,
+    /// Whether the message may be deleted, always for own messages and for others' only as an admin.
+    can_delete: Signal<bool>,
+    on_edit: Callback<String>,
+    on_delete: Callback<()>,
+) -> impl IntoView {
+    let deleted =
+        Signal::derive(move || revised.with(|revised| revised.as_ref().is_some_and(|r| r.deleted)));
+    let edited = move || {
+        revised
+            .get()
+            .filter(|revised| !revised.deleted)
+            .and_then(|revised| revised.edited_at)
+            .map(|at| {
+                view! {
+                    <span title=format!("Edited {}", at.format("%Y-%m-%d %H:%M"))>" (edited)"</span>
+                }
+            })
+    };
     match msg {
-        LabeledMessage::Incoming(m) => {
+        LabeledMessage::Incoming(m, author_id, verified) => {
+            let avatar_room = room.clone();
+            let avatar = move || {
+                view! {
+                    <Avatar
+                        room=avatar_room.clone()
+                        peer=author_id.clone()
+                        profile=author_profile.get()
+                        class="w-5 h-5 text-[10px]"
+                    />
+                }
+            };
+            let attachments = attachments(room, m.attachments().to_vec(), progress);
             let (msg, timestamp) = m.unpack_for_html_integration();
+            let text = Signal::derive(move || {
+                revised.get().map(|r| r.text).unwrap_or_else(|| msg.clone())
+            });
+            let verification = match verified {
+                true => view! {
+                    <span class="text-green-600 dark:text-green-400" title="Signed by its author">
+                        "✓"
+                    </span>
+                }
+                .into_any(),
+                false => view! {
+                    <span
+                        class="text-red-600 dark:text-red-400"
+                        title="Not signed by its author, anyone may have sent it"
+                    >
+                        "unverified"
+                    </span>
+                }
+                .into_any(),
+            };
             view! {
                 <div class="flex justify-start animate-slide-up">
                     <div class="max-w-xs lg:max-w-md">
+                        <p class="flex items-center text-xs font-semibold text-gray-600 dark:text-gray-300 mb-1 ml-2 space-x-1">
+                            {avatar}
+                            <span>{move || author.get()}</span>
+                            {verification}
+                        </p>
                         <div class="bg-white dark:bg-gray-700 rounded-lg px-4 py-2 shadow-sm border border-gray-200 dark:border-gray-600">
-                            <p class="text-gray-900 dark:text-white">{msg}</p>
+                            <Show
+                                when=move || !deleted.get()
+                                fallback=|| {
+                                    view! {
+                                        <p class="italic text-gray-500 dark:text-gray-400">
+                                            "This message was deleted"
+                                        </p>
+                                    }
+                                }
+                            >
+                                <p class="text-gray-900 dark:text-white">{move || text.get()}</p>
+                            </Show>
+                            <div
+                                class="text-gray-900 dark:text-white"
+                                class:hidden=move || deleted.get()
+                            >
+                                {attachments}
+                            </div>
                         </div>
-                        <p class="text-xs text-gray-500 dark:text-gray-400 mt-1 ml-2">
-                            {timestamp}
+                        <p class="text-xs text-gray-500 dark:text-gray-400 mt-1 ml-2 space-x-2">
+                            <span>{timestamp} {edited}</span>
+                            {revision_controls(false, can_delete, deleted, text, on_edit, on_delete)}
                         </p>
                     </div>
                 </div>
             }
+            .into_any()
         }
         LabeledMessage::Outgoing(m) => {
+            let seen_by_room = room.clone();
+            let attachments = attachments(room, m.attachments().to_vec(), progress);
             let (msg, timestamp) = m.unpack_for_html_integration();
+            let text = Signal::derive(move || {
+                revised.get().map(|r| r.text).unwrap_or_else(|| msg.clone())
+            });
             view! {
                 <div class="flex justify-end animate-slide-up">
                     <div class="max-w-xs lg:max-w-md">
                         <div class="bg-blue-500 rounded-lg px-4 py-2 shadow-sm">
-                            <p class="text-white">{msg}</p>
+                            <Show
+                                when=move || !deleted.get()
+                                fallback=|| {
+                                    view! { <p class="italic text-blue-100">"This message was deleted"</p> }
+                                }
+                            >
+                                <p class="text-white">{move || text.get()}</p>
+                            </Show>
+                            <div class="text-white" class:hidden=move || deleted.get()>
+                                {attachments}
+                            </div>
                         </div>
-                        <p class="text-xs text-gray-500 dark:text-gray-400 mt-1 mr-2 text-right">
-                            {timestamp}
+                        <p class="text-xs text-gray-500 dark:text-gray-400 mt-1 mr-2 text-right space-x-2">
+                            <span>{timestamp} {edited}</span>
+                            {revision_controls(true, can_delete, deleted, text, on_edit, on_delete)}
+                        </p>
+                        <p class="text-xs text-gray-500 dark:text-gray-400 mr-2 text-right">
+                            {move || delivery_status(delivery.get(), on_retry)}
+                        </p>
+                        <p class="mr-2 text-right">
+                            {move || seen_by_avatars(seen_by_room.clone(), seen_by.get())}
                         </p>
                     </div>
                 </div>
             }
+            .into_any()
+        }
+    }
+}
+
+/// Number of persisted messages loaded when the chat opens.
+const HISTORY_PAGE_SIZE: u32 = 50;
+
+/// Label for a room, its title when known as the full document id is too long to display.
+fn room_label(room: &api::RoomInfo) -> String {
+    room.title
+        .clone()
+        .unwrap_or_else(|| room.id.chars().take(8).collect())
+}
+
+/// What is known about the connection to a peer of a room.
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
+pub struct PeerConnection {
+    status: Option<api::ConnectionStatus>,
+    path: Option<api::ConnectionPath>,
+}
+
+/// Name of a peer: the display name of its profile, or its shortened node id until it published one.
+fn peer_name(profiles: &HashMap<String, api::Profile>, peer: &str) -> String {
+    profiles
+        .get(peer)
+        .map(|profile| profile.display_name.clone())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| peer.chars().take(8).collect())
+}
+
+/// Renders the connection to a peer as its name, status and path, colored by status.
+fn peer_connection(
+    peer: String,
+    connection: PeerConnection,
+    profiles: ReadSignal<HashMap<String, api::Profile>>,
+) -> impl IntoView {
+    let status = match connection.status {
+        Some(api::ConnectionStatus::Connecting) => "connecting",
+        Some(api::ConnectionStatus::Connected) => "connected",
+        Some(api::ConnectionStatus::Reconnecting) => "reconnecting",
+        Some(api::ConnectionStatus::Disconnected) => "disconnected",
+        None => "unknown",
+    };
+    let path = match connection.path {
+        Some(api::ConnectionPath::Direct) => " (direct)",
+        Some(api::ConnectionPath::Relay) => " (relay)",
+        Some(api::ConnectionPath::Mixed) => " (mixed)",
+        None => "",
+    };
+    let class = match connection.status {
+        Some(api::ConnectionStatus::Connected) => "text-sm text-green-500",
+        Some(api::ConnectionStatus::Disconnected) => "text-sm text-red-500",
+        _ => "text-sm text-yellow-500",
+    };
+    view! {
+        <p class=class>
+            {move || format!("{}: {status}{path}", profiles.with(|profiles| peer_name(profiles, &peer)))}
+        </p>
+    }
+}
+
+/// Renders a member of the room as its name behind a presence indicator, status text and last seen on hover.
+fn member(
+    member: api::Member,
+    profiles: ReadSignal<HashMap<String, api::Profile>>,
+) -> impl IntoView {
+    let indicator = match member.presence {
+        api::Presence::Online => "w-2 h-2 rounded-full bg-green-500",
+        api::Presence::Away => "w-2 h-2 rounded-full bg-yellow-500",
+        api::Presence::Offline => "w-2 h-2 rounded-full bg-gray-400",
+    };
+    let last_seen = member
+        .last_seen
+        .map(|last_seen| format!("last seen {}", last_seen.format("%Y-%m-%d %H:%M")))
+        .unwrap_or_else(|| "never seen".to_string());
+    let id = member.id;
+    let title = {
+        let id = id.clone();
+        move || {
+            profiles.with(|profiles| match profiles.get(&id) {
+                Some(profile) if !profile.status.is_empty() => {
+                    format!("{}, {}", profile.status, last_seen)
+                }
+                _ => last_seen.clone(),
+            })
         }
+    };
+    view! {
+        <li class="flex items-center space-x-1 text-xs text-gray-600 dark:text-gray-400" title=title>
+            <span class=indicator></span>
+            <span>{move || profiles.with(|profiles| peer_name(profiles, &id))}</span>
+        </li>
     }
 }
 
+/// How often the composer repeats that the user is still typing, well within the backend's typing TTL.
+const TYPING_REPEAT: TimeDelta = TimeDelta::seconds(3);
+
+/// The roles a member can be granted, most privileged first.
+const ROLES: [api::Role; 3] = [api::Role::Admin, api::Role::Writer, api::Role::Reader];
+
+fn role_name(role: api::Role) -> &'static str {
+    match role {
+        api::Role::Admin => "admin",
+        api::Role::Writer => "writer",
+        api::Role::Reader => "reader",
+    }
+}
+
+/// Whether this node holds the admin role among the grants of a room.
+fn admin_of(grants: &[api::AccessGrant]) -> bool {
+    grants
+        .iter()
+        .any(|grant| grant.this_node && grant.role == api::Role::Admin)
+}
+
+fn parse_role(name: &str) -> Option<api::Role> {
+    ROLES.into_iter().find(|role| role_name(*role) == name)
+}
+
+/// Renders the options of a role picker, with the given role selected.
+fn role_options(selected: api::Role) -> impl IntoView {
+    ROLES
+        .into_iter()
+        .map(|role| {
+            view! {
+                <option value={role_name(role)} selected={role == selected}>
+                    {role_name(role)}
+                </option>
+            }
+        })
+        .collect_view()
+}
+
+/// Sends an ephemeral signal to the peers of a room, these are best effort so failures are only logged.
+fn send_signal(room: String, kind: api::SignalKind) {
+    spawn_local(async move {
+        if let Err(e) = api::ui::send_signal(room, kind).await {
+            log!("Failed to send signal: {}", e);
+        }
+    });
+}
+
 #[component]
-pub fn Chat(connection_type: ReadSignal<String>) -> impl IntoView {
+pub fn Chat(
+    peers: Signal<Vec<(String, PeerConnection)>>,
+    room: ReadSignal<Option<String>>,
+    set_room: WriteSignal<Option<String>>,
+    rooms: ReadSignal<Vec<api::RoomInfo>>,
+    set_rooms: WriteSignal<Vec<api::RoomInfo>>,
+    set_is_connected: WriteSignal<bool>,
+) -> impl IntoView {
     // signal to handle a vector of all messages sent and received in this chat session
     let (messages, set_messages) = signal(vec![]);
     // signal to handle the input of messages to the text area by the user.
     let (send_message, set_send_message) = signal(String::new());
+    // signal holding the files attached to the message being composed
+    let (pending_attachments, set_pending_attachments) = signal(Vec::<api::Attachment>::new());
+    // signal to handle the path of the file the user attaches
+    let (attach_path, set_attach_path) = signal(String::new());
+    // signal to present why attaching a file failed
+    let (attach_error, set_attach_error) = signal(String::new());
+    // signal holding the bytes received of the attachments being downloaded, keyed by hash
+    let (attachment_progress, set_attachment_progress) = signal(HashMap::<String, u64>::new());
+    // signal holding the delivery state of the messages sent in this chat session, keyed by message id
+    let (deliveries, set_deliveries) = signal(HashMap::<String, api::DeliveryState>::new());
+    // signal holding the peers taking part in the room, with their presence
+    let (members, set_members) = signal(Vec::<api::Member>::new());
+    // signal holding the peers typing in the room, with the time their typing signal expires
+    let (typing, set_typing) = signal(BTreeMap::<String, DateTime<Utc>>::new());
+    // signal ticking every second so expired typing signals disappear without further events
+    let (now, set_now) = signal(Utc::now());
+    if let Ok(ticker) =
+        set_interval_with_handle(move || set_now.set(Utc::now()), Duration::from_secs(1))
+    {
+        on_cleanup(move || ticker.clear());
+    }
+    // the last time the composer signalled typing, so it is repeated at most every `TYPING_REPEAT`
+    let last_typing = StoredValue::new(None::<DateTime<Utc>>);
+    // signal holding the profiles peers published, keyed by peer node id
+    let (profiles, set_profiles) = signal(HashMap::<String, api::Profile>::new());
+    // signal holding the id of the last message every peer read in the room, keyed by peer node id
+    let (receipts, set_receipts) = signal(HashMap::<String, String>::new());
+    // the last message this node marked as read, so it is only reported once
+    let last_read = StoredValue::new(None::<String>);
+    let messages_container = NodeRef::<leptos::html::Div>::new();
+    // signal to toggle the panel managing who has access to the room
+    let (show_access, set_show_access) = signal(false);
+    // signal to present why leaving the room failed
+    let (leave_error, set_leave_error) = signal(String::new());
+    // signal holding the latest edit or the deletion of messages of the room, keyed by message id
+    let (revised, set_revised) = signal(HashMap::<String, api::RevisedMessage>::new());
+    // signal telling whether this node is an admin of the room, admins may delete anyone's messages
+    let (is_admin, set_is_admin) = signal(false);
+    // signal to present why editing or deleting a message failed
+    let (revise_error, set_revise_error) = signal(String::new());
+
+    // leaves the room shown, switching to another room or back to the connect screen when none is left
+    let leave = move |_| {
+        let Some(left) = room.get_untracked() else {
+            return;
+        };
+        spawn_local(async move {
+            if let Err(e) = api::ui::leave_room(left).await {
+                set_leave_error.set(format!("Failed to leave the room: {}", e));
+                return;
+            }
+            set_leave_error.set(String::new());
+            set_show_access.set(false);
+            let remaining = api::ui::list_rooms().await.unwrap_or_default();
+            set_room.set(remaining.first().map(|info| info.id.clone()));
+            if remaining.is_empty() {
+                set_is_connected.set(false);
+            }
+            set_rooms.set(remaining);
+        });
+    };
+
+    // populate the chat with the persisted history of the room (document) we are in, whenever it changes
+    Effect::new(move |_| {
+        set_messages.set(vec![]);
+        set_revised.set(HashMap::new());
+        set_revise_error.set(String::new());
+        if let Some(room) = room.get() {
+            spawn_local(async move {
+                match api::ui::load_history(room.clone(), None, HISTORY_PAGE_SIZE).await {
+                    Ok(history) => {
+                        // messages still being delivered show their state until a peer received them
+                        set_deliveries.update(|deliveries| {
+                            for entry in &history {
+                                if let Some(delivery) = &entry.delivery {
+                                    deliveries
+                                        .entry(entry.message.id().to_string())
+                                        .or_insert_with(|| delivery.clone());
+                                }
+                            }
+                        });
+                        set_revised.update(|revised| {
+                            for entry in &history {
+                                if let Some(revision) = revised_entry(&room, entry) {
+                                    revised.entry(revision.id.clone()).or_insert(revision);
+                                }
+                            }
+                        });
+                        set_messages.update(|messages| {
+                            // anything that already streamed in while the history was loading is skipped
+                            for entry in history {
+                                insert_ordered(messages, entry.clock, entry.into());
+                            }
+                        })
+                    }
+                    Err(e) => log!("Failed to load history: {}", e),
+                }
+            });
+        }
+    });
+
+    // load the members of the room whenever it changes, later changes stream in as roster events
+    Effect::new(move |_| {
+        set_members.set(vec![]);
+        set_typing.set(BTreeMap::new());
+        set_receipts.set(HashMap::new());
+        last_read.set_value(None);
+        if let Some(room) = room.get() {
+            // let the peers know we have the room open
+            send_signal(room.clone(), api::SignalKind::Ping);
+            let receipts_room = room.clone();
+            spawn_local(async move {
+                match api::ui::list_receipts(receipts_room).await {
+                    Ok(list) => set_receipts.update(|receipts| {
+                        for receipt in list {
+                            receipts.entry(receipt.peer).or_insert(receipt.message_id);
+                        }
+                    }),
+                    Err(e) => log!("Failed to list receipts: {}", e),
+                }
+            });
+            spawn_local(async move {
+                match api::ui::list_members(room).await {
+                    Ok(list) => set_members.set(list),
+                    Err(e) => log!("Failed to list members: {}", e),
+                }
+            });
+        }
+    });
+
+    spawn_local(async move {
+        let mut roster_updates = events::ui::roster_changed::listen()
+            .await
+            .expect("there should be a valid roster update incoming");
+        while let Some(roster) = roster_updates.next().await {
+            if Some(&roster.payload.room) == room.get_untracked().as_ref() {
+                set_members.set(roster.payload.members);
+            }
+        }
+    });
+
+    spawn_local(async move {
+        let mut signals = events::ui::signal::listen()
+            .await
+            .expect("there should be a valid signal incoming");
+        while let Some(signal) = signals.next().await {
+            let api::EphemeralSignal {
+                room: signal_room,
+                peer,
+                kind,
+                expires_at,
+            } = signal.payload;
+            if Some(&signal_room) != room.get_untracked().as_ref() {
+                continue;
+            }
+            set_typing.update(|typing| match kind {
+                api::SignalKind::Typing => {
+                    typing.insert(peer, expires_at);
+                }
+                api::SignalKind::StoppedTyping => {
+                    typing.remove(&peer);
+                }
+                api::SignalKind::Ping => {}
+            });
+        }
+    });
+
+    spawn_local(async move {
+        match api::ui::list_profiles().await {
+            Ok(list) => set_profiles.update(|profiles| {
+                for peer_profile in list {
+                    profiles
+                        .entry(peer_profile.peer)
+                        .or_insert(peer_profile.profile);
+                }
+            }),
+            Err(e) => log!("Failed to list profiles: {}", e),
+        }
+    });
+
+    spawn_local(async move {
+        let mut profile_updates = events::ui::profile_changed::listen()
+            .await
+            .expect("there should be a valid profile update incoming");
+        while let Some(update) = profile_updates.next().await {
+            let api::PeerProfile { peer, profile } = update.payload;
+            set_profiles.update(|profiles| {
+                profiles.insert(peer, profile);
+            });
+        }
+    });
+
+    spawn_local(async move {
+        let mut receipt_updates = events::ui::receipt::listen()
+            .await
+            .expect("there should be a valid receipt incoming");
+        while let Some(receipt) = receipt_updates.next().await {
+            let api::ReadReceipt {
+                room: receipt_room,
+                peer,
+                message_id,
+            } = receipt.payload;
+            if Some(&receipt_room) == room.get_untracked().as_ref() {
+                set_receipts.update(|receipts| {
+                    receipts.insert(peer, message_id);
+                });
+            }
+        }
+    });
+
+    // the peers shown under each outgoing message: every peer appears under the last of our messages at or
+    // before the message it last read
+    let seen_by = Memo::new(move |_| {
+        let messages = messages.get();
+        let mut seen_by = HashMap::<String, Vec<(String, Option<api::Profile>)>>::new();
+        for (peer, message_id) in receipts.get() {
+            let Some(read) = messages
+                .iter()
+                .position(|(_, message)| message.id() == message_id)
+            else {
+                continue;
+            };
+            if let Some((_, message)) = messages[..=read]
+                .iter()
+                .rev()
+                .find(|(_, message)| matches!(message, LabeledMessage::Outgoing(_)))
+            {
+                seen_by.entry(message.id().to_string()).or_default().push((
+                    peer.clone(),
+                    profiles.with(|profiles| profiles.get(&peer).cloned()),
+                ));
+            }
+        }
+        seen_by
+    });
+
+    // marks the newest incoming message as read once the chat is scrolled down to it
+    let mark_read = move || {
+        let Some(container) = messages_container.get_untracked() else {
+            return;
+        };
+        if container.scroll_top() + container.client_height() < container.scroll_height() - 16 {
+            return;
+        }
+        let newest = messages.with_untracked(|messages| {
+            messages
+                .iter()
+                .rev()
+                .find_map(|(_, message)| match message {
+                    LabeledMessage::Incoming(m, ..) => Some(m.id().to_string()),
+                    LabeledMessage::Outgoing(_) => None,
+                })
+        });
+        if let (Some(room), Some(newest)) = (room.get_untracked(), newest)
+            && last_read.get_value().as_ref() != Some(&newest)
+        {
+            last_read.set_value(Some(newest.clone()));
+            spawn_local(async move {
+                if let Err(e) = api::ui::mark_read(room, newest).await {
+                    log!("Failed to mark read: {}", e);
+                }
+            });
+        }
+    };
+    // messages arriving while the chat is scrolled down are read right away
+    Effect::new(move |_| {
+        messages.track();
+        mark_read();
+    });
+
+    // the peers whose typing signal has not expired yet
+    let typing_peers = move || {
+        let now = now.get();
+        typing
+            .get()
+            .into_iter()
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(peer, _)| profiles.with(|profiles| peer_name(profiles, &peer)))
+            .collect::<Vec<_>>()
+    };
 
     // listen for incoming messages and add them to the messages vector
     spawn_local(async move {
@@ -75,27 +947,215 @@ pub fn Chat(connection_type: ReadSignal<String>) -> impl IntoView {
             .expect("there should be a valid message incoming");
         while let Some(msg) = incoming_messages.next().await {
             log!("Received message: {:?}", msg);
-            let labeled_msg = LabeledMessage::Incoming(msg.payload);
+            // messages of other rooms are picked up from the history when switching to them
+            if Some(&msg.payload.room) != room.get_untracked().as_ref() {
+                continue;
+            }
+            let labeled_msg = LabeledMessage::Incoming(
+                msg.payload.message,
+                msg.payload.author,
+                msg.payload.verified,
+            );
+            set_messages.update(|messages| {
+                insert_ordered(messages, msg.payload.clock, labeled_msg);
+            });
+        }
+    });
+
+    // merge messages recovered from bundles peers compacted, so late joiners see the full conversation
+    spawn_local(async move {
+        let mut backfills = events::ui::history_backfill::listen()
+            .await
+            .expect("there should be a valid history backfill incoming");
+        while let Some(backfill) = backfills.next().await {
+            if Some(&backfill.payload.room) != room.get_untracked().as_ref() {
+                continue;
+            }
+            set_revised.update(|revised| {
+                for entry in &backfill.payload.messages {
+                    if let Some(revision) = revised_entry(&backfill.payload.room, entry) {
+                        revised.insert(revision.id.clone(), revision);
+                    }
+                }
+            });
+            set_messages.update(|messages| {
+                for entry in backfill.payload.messages {
+                    insert_ordered(messages, entry.clock, entry.into());
+                }
+            });
+        }
+    });
+
+    // show edits and deletions of messages of the room as they arrive
+    spawn_local(async move {
+        let mut revisions = events::ui::message_revised::listen()
+            .await
+            .expect("there should be a valid message revision incoming");
+        while let Some(revision) = revisions.next().await {
+            if Some(&revision.payload.room) != room.get_untracked().as_ref() {
+                continue;
+            }
+            set_revised.update(|revised| {
+                revised.insert(revision.payload.id.clone(), revision.payload);
+            });
+        }
+    });
+
+    // whether this node is an admin of the room, updated as roles change
+    Effect::new(move |_| {
+        set_is_admin.set(false);
+        let Some(room) = room.get() else {
+            return;
+        };
+        spawn_local(async move {
+            match api::ui::list_access(room).await {
+                Ok(grants) => set_is_admin.set(admin_of(&grants)),
+                Err(e) => log!("Failed to list access: {}", e),
+            }
+        });
+    });
+
+    spawn_local(async move {
+        let mut access_updates = events::ui::membership_changed::listen()
+            .await
+            .expect("there should be a valid membership update incoming");
+        while let Some(update) = access_updates.next().await {
+            if room.get_untracked().as_ref() == Some(&update.payload.room) {
+                set_is_admin.set(admin_of(&update.payload.grants));
+            }
+        }
+    });
+
+    // track the delivery state of our messages, moving them to the clock the backend assigned
+    spawn_local(async move {
+        let mut delivery_updates = events::ui::delivery::listen()
+            .await
+            .expect("there should be a valid delivery update incoming");
+        while let Some(update) = delivery_updates.next().await {
+            let api::MessageDelivery {
+                room: update_room,
+                id,
+                state,
+                clock,
+            } = update.payload;
+            if Some(&update_room) != room.get_untracked().as_ref() {
+                continue;
+            }
             set_messages.update(|messages| {
-                messages.push(labeled_msg);
+                if let Some(position) = messages
+                    .iter()
+                    .position(|(shown_clock, shown)| shown.id() == id && *shown_clock != clock)
+                {
+                    let (_, message) = messages.remove(position);
+                    insert_ordered(messages, clock, message);
+                }
+            });
+            set_deliveries.update(|deliveries| {
+                deliveries.insert(id, state);
+            });
+        }
+    });
+
+    // follow the downloads of attachments of the room
+    spawn_local(async move {
+        let mut progress_updates = events::ui::attachment_progress::listen()
+            .await
+            .expect("there should be a valid attachment progress incoming");
+        while let Some(update) = progress_updates.next().await {
+            if Some(&update.payload.room) != room.get_untracked().as_ref() {
+                continue;
+            }
+            set_attachment_progress.update(|progress| {
+                progress.insert(update.payload.hash, update.payload.received);
             });
         }
     });
 
+    let attach = move |_| {
+        let path = attach_path.get();
+        if path.is_empty() {
+            return;
+        }
+        spawn_local(async move {
+            match api::ui::add_attachment(path).await {
+                Ok(attachment) => {
+                    set_pending_attachments.update(|attachments| attachments.push(attachment));
+                    set_attach_path.set(String::new());
+                    set_attach_error.set(String::new());
+                }
+                Err(e) => set_attach_error.set(format!("Failed to attach the file: {}", e)),
+            }
+        });
+    };
+
+    let retry = move |id: String| {
+        spawn_local(async move {
+            if let Err(reason) = api::ui::retry_message(id.clone()).await {
+                set_deliveries.update(|deliveries| {
+                    deliveries.insert(id, api::DeliveryState::Failed { reason });
+                });
+            }
+        });
+    };
+
+    // edits and deletions show once the backend emitted them, only failures are reported here
+    let edit = move |id: String, text: String| {
+        let Some(room) = room.get_untracked() else {
+            return;
+        };
+        spawn_local(async move {
+            match api::ui::edit_message(room, id, text).await {
+                Ok(()) => set_revise_error.set(String::new()),
+                Err(e) => set_revise_error.set(format!("Failed to edit the message: {}", e)),
+            }
+        });
+    };
+
+    let delete = move |id: String| {
+        let Some(room) = room.get_untracked() else {
+            return;
+        };
+        spawn_local(async move {
+            match api::ui::delete_message(room, id).await {
+                Ok(()) => set_revise_error.set(String::new()),
+                Err(e) => set_revise_error.set(format!("Failed to delete the message: {}", e)),
+            }
+        });
+    };
+
     // adds new messages created by the user and sends them out
-    // todo: handle message send failures
     // todo: allow sending on keyboard "enter" key press
     let send_out = move |_ev| {
         let msg = send_message.get();
-        if !msg.is_empty() {
-            let msg = api::Message::new(msg);
+        if let Some(room) = room.get()
+            && (!msg.is_empty() || !pending_attachments.with(|attachments| attachments.is_empty()))
+        {
+            let msg = api::Message::with_attachments(msg, pending_attachments.get());
+            set_pending_attachments.set(Vec::new());
+            let id = msg.id().to_string();
             let labeled_msg = LabeledMessage::Outgoing(msg.clone());
-            set_messages.update(|messages| messages.push(labeled_msg));
-            // todo: we have no check to make sure the message broadcasts here, we should add this
+            set_deliveries.update(|deliveries| {
+                deliveries.insert(id.clone(), api::DeliveryState::Pending);
+            });
+            set_messages.update(|messages| {
+                // provisional position after everything shown, the backend assigns the final clock
+                let clock = messages
+                    .last()
+                    .map(|(clock, _)| *clock)
+                    .unwrap_or_default()
+                    .successor(msg.timestamp().timestamp_millis());
+                insert_ordered(messages, clock, labeled_msg);
+            });
+            last_typing.set_value(None);
+            send_signal(room.clone(), api::SignalKind::StoppedTyping);
+            // progress is reported through delivery events, this also covers failures before the backend tracked it
             spawn_local(async move {
-                api::ui::broadcast_message(msg)
-                    .await
-                    .expect("should send a valid message");
+                if let Err(reason) = api::ui::broadcast_message(room, msg).await {
+                    log!("Failed to send message: {}", reason);
+                    set_deliveries.update(|deliveries| {
+                        deliveries.insert(id, api::DeliveryState::Failed { reason });
+                    });
+                }
             });
         }
     };
@@ -106,7 +1166,10 @@ pub fn Chat(connection_type: ReadSignal<String>) -> impl IntoView {
             <header class="flex-shrink-0 bg-white dark:bg-gray-800 border-b border-gray-200 dark:border-gray-700 px-4 py-3 shadow-sm">
                 <div class="flex items-center justify-between">
                     <div class="flex items-center space-x-3">
-                        <button class="p-2 rounded-lg hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors duration-200 md:hidden">
+                        <button
+                            on:click=move |_| set_is_connected.set(false)
+                            class="p-2 rounded-lg hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors duration-200"
+                        >
                             <svg
                                 class="w-5 h-5 text-gray-600 dark:text-gray-400"
                                 fill="none"
@@ -122,20 +1185,59 @@ pub fn Chat(connection_type: ReadSignal<String>) -> impl IntoView {
                             </svg>
                         </button>
                         <div class="flex items-center space-x-3">
+                            // number of members online
                             <div class="w-8 h-8 bg-blue-500 rounded-full flex items-center justify-center">
-                                <span class="text-white font-semibold text-sm">U</span>
+                                <span class="text-white font-semibold text-sm">
+                                    {move || {
+                                        members
+                                            .get()
+                                            .iter()
+                                            .filter(|member| member.presence == api::Presence::Online)
+                                            .count()
+                                    }}
+                                </span>
                             </div>
                             <div>
                                 <h2 class="text-lg font-semibold text-gray-900 dark:text-white">
-                                    Connection Type
+                                    Peers
                                 </h2>
-                                <p class="text-sm text-green-500">
-                                    {move || connection_type.get()}
-                                </p>
+                                <For
+                                    each=move || peers.get()
+                                    key=|peer| peer.clone()
+                                    children=move |(peer, connection)| {
+                                        peer_connection(peer, connection, profiles)
+                                    }
+                                />
                             </div>
+                            <select
+                                class="px-2 py-1 border border-gray-300 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-sm text-gray-900 dark:text-white"
+                                on:change=move |ev| set_room.set(Some(event_target_value(&ev)))
+                            >
+                                <For
+                                    each=move || rooms.get()
+                                    key=|room| room.id.clone()
+                                    children=move |option| {
+                                        let label = room_label(&option);
+                                        let option = option.id;
+                                        let selected = {
+                                            let option = option.clone();
+                                            move || room.get().as_ref() == Some(&option)
+                                        };
+                                        view! {
+                                            <option value=option prop:selected=selected>
+                                                {label}
+                                            </option>
+                                        }
+                                    }
+                                />
+                            </select>
                         </div>
                     </div>
-                    <button class="p-2 rounded-lg hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors duration-200">
+                    <button
+                        on:click=move |_| set_show_access.update(|show| *show = !*show)
+                        class="p-2 rounded-lg hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors duration-200"
+                        title="Room access"
+                    >
                         <svg
                             class="w-5 h-5 text-gray-600 dark:text-gray-400"
                             fill="none"
@@ -151,17 +1253,94 @@ pub fn Chat(connection_type: ReadSignal<String>) -> impl IntoView {
                         </svg>
                     </button>
                 </div>
+                <ul class="flex flex-wrap gap-3 mt-2">
+                    <For
+                        each=move || members.get()
+                        key=|member| member.clone()
+                        children=move |entry| member(entry, profiles)
+                    />
+                </ul>
+                <Show when=move || show_access.get()>
+                    <AccessSettings room=room />
+                    <div class="flex items-center justify-between mt-2">
+                        <p class="text-xs text-red-500">{move || leave_error.get()}</p>
+                        <button
+                            on:click=leave
+                            class="px-3 py-1 text-sm font-medium rounded-lg text-white bg-red-600 hover:bg-red-700 transition-colors duration-200"
+                            title="Stop syncing the room and delete its local history"
+                        >
+                            Leave room
+                        </button>
+                    </div>
+                </Show>
             </header>
 
             <div class="flex-1 min-h-0 overflow-hidden">
-                <div id="messages-container" class="h-full overflow-y-auto px-4 py-4">
+                <div
+                    id="messages-container"
+                    class="h-full overflow-y-auto px-4 py-4"
+                    node_ref=messages_container
+                    on:scroll=move |_| mark_read()
+                >
                     <div class="space-y-4">
                         // todo: is this the most efficient way to render messages?  this will likely result in poor performance for large chats.
                         <For
                             each=move || messages.get()
-                            key=|message| message.timestamp().to_string()
-                            children=move |message| {
-                                view! { <Message msg=message /> }
+                            key=|(_, message)| message.id().to_string()
+                            children=move |(_, message)| {
+                                let id = message.id().to_string();
+                                let delivery = {
+                                    let id = id.clone();
+                                    Signal::derive(move || deliveries.get().get(&id).cloned())
+                                };
+                                let seen_by = {
+                                    let id = id.clone();
+                                    Signal::derive(move || {
+                                        seen_by.with(|seen_by| seen_by.get(&id).cloned().unwrap_or_default())
+                                    })
+                                };
+                                let author = match &message {
+                                    LabeledMessage::Incoming(_, author, _) => author.clone(),
+                                    LabeledMessage::Outgoing(_) => String::new(),
+                                };
+                                let revision = {
+                                    let id = id.clone();
+                                    Signal::derive(move || revised.with(|revised| revised.get(&id).cloned()))
+                                };
+                                let outgoing = matches!(message, LabeledMessage::Outgoing(_));
+                                let can_delete = Signal::derive(move || outgoing || is_admin.get());
+                                let on_edit = {
+                                    let id = id.clone();
+                                    Callback::new(move |text: String| edit(id.clone(), text))
+                                };
+                                let on_delete = {
+                                    let id = id.clone();
+                                    Callback::new(move |_: ()| delete(id.clone()))
+                                };
+                                let author_profile = {
+                                    let author = author.clone();
+                                    Signal::derive(move || profiles.with(|profiles| profiles.get(&author).cloned()))
+                                };
+                                let author = Signal::derive(move || {
+                                    profiles.with(|profiles| peer_name(profiles, &author))
+                                });
+                                let on_retry = Callback::new(move |_: ()| retry(id.clone()));
+                                view! {
+                                    <Message
+                                        msg=message
+                                        room=room.get_untracked().unwrap_or_default()
+                                        delivery=delivery
+                                        on_retry=on_retry
+                                        seen_by=seen_by
+                                        author=author
+                                        author_profile=author_profile
+                                        progress=attachment_progress
+                                        revised=revision
+                                        can_delete=can_delete
+                                        on_edit=on_edit
+                                        on_delete=on_delete
+                                    />
+                                }
                             }
                         />
 
@@ -170,6 +1349,56 @@ pub fn Chat(connection_type: ReadSignal<String>) -> impl IntoView {
             </div>
 
             <div class="flex-shrink-0 bg-white dark:bg-gray-800 border-t border-gray-200 dark:border-gray-700 px-4 py-3">
+                <p class="text-xs text-gray-500 dark:text-gray-400 h-4 mb-1">
+                    {move || {
+                        let peers = typing_peers();
+                        match peers.len() {
+                            0 => String::new(),
+                            1 => format!("{} is typing...", peers[0]),
+                            _ => format!("{} are typing...", peers.join(", ")),
+                        }
+                    }}
+                </p>
+                <ul class="flex flex-wrap gap-2 mb-1">
+                    <For
+                        each=move || pending_attachments.get()
+                        key=|attachment| attachment.hash.clone()
+                        children=move |attachment| {
+                            let hash = attachment.hash.clone();
+                            view! {
+                                <li class="flex items-center space-x-1 px-2 py-1 rounded bg-gray-100 dark:bg-gray-700 text-xs text-gray-900 dark:text-white">
+                                    <span>{format!("{} ({})", attachment.name, file_size(attachment.size))}</span>
+                                    <button
+                                        on:click=move |_| {
+                                            set_pending_attachments
+                                                .update(|attachments| attachments.retain(|attachment| attachment.hash != hash))
+                                        }
+                                        title="Remove the attachment"
+                                    >
+                                        "×"
+                                    </button>
+                                </li>
+                            }
+                        }
+                    />
+                </ul>
+                <div class="flex items-center space-x-2 mb-2">
+                    <input
+                        type="text"
+                        class="flex-1 px-3 py-1 border border-gray-300 dark:border-gray-600 rounded-lg text-sm bg-white dark:bg-gray-700 text-gray-900 dark:text-white placeholder-gray-400 dark:placeholder-gray-500"
+                        placeholder="Path of a file to attach..."
+                        prop:value=attach_path
+                        on:input=move |ev| set_attach_path.set(event_target_value(&ev))
+                    />
+                    <button
+                        on:click=attach
+                        class="px-3 py-1 text-sm font-medium rounded-lg bg-gray-200 dark:bg-gray-600 text-gray-900 dark:text-white hover:bg-gray-300 dark:hover:bg-gray-500 transition-colors duration-200"
+                    >
+                        Attach
+                    </button>
+                </div>
+                <p class="text-xs text-red-500">{move || attach_error.get()}</p>
+                <p class="text-xs text-red-500">{move || revise_error.get()}</p>
                 <div class="flex items-center space-x-3">
                     <div class="flex-1">
                         <textarea
@@ -179,6 +1408,14 @@ pub fn Chat(connection_type: ReadSignal<String>) -> impl IntoView {
                             prop:value=send_message
                             on:input=move |ev| {
                                 set_send_message.set(event_target_value(&ev));
+                                let now = Utc::now();
+                                let repeat = last_typing
+                                    .get_value()
+                                    .is_none_or(|last| now - last >= TYPING_REPEAT);
+                                if repeat && let Some(room) = room.get_untracked() {
+                                    last_typing.set_value(Some(now));
+                                    send_signal(room, api::SignalKind::Typing);
+                                }
                             }
                         ></textarea>
                     </div>
@@ -201,6 +1438,541 @@ pub fn Chat(connection_type: ReadSignal<String>) -> impl IntoView {
     }
 }
 
+/// Shown on launch while the stored identity is passphrase protected, the node only starts once it is unlocked.
+#[component]
+pub fn UnlockIdentity(set_locked: WriteSignal<bool>) -> impl IntoView {
+    // signal to manage the input of the passphrase
+    let (passphrase, set_passphrase) = signal(String::new());
+    // signal to present unlock failures such as a wrong passphrase
+    let (error, set_error) = signal(String::new());
+
+    let unlock = move |_ev| {
+        let passphrase = passphrase.get();
+        spawn_local(async move {
+            match api::ui::unlock_identity(passphrase).await {
+                Ok(()) => set_locked.set(false),
+                Err(e) => set_error.set(e),
+            }
+        });
+    };
+
+    view! {
+        <div class="h-full flex items-center justify-center p-6">
+            <div class="w-full max-w-md space-y-4 animate-fade-in">
+                <div class="text-center">
+                    <h1 class="text-3xl font-bold text-gray-900 dark:text-white mb-2">
+                        Unlock Identity
+                    </h1>
+                    <p class="text-gray-600 dark:text-gray-400">
+                        Enter the passphrase protecting this node
+                    </p>
+                </div>
+                <input
+                    type="password"
+                    class="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                    placeholder="Passphrase"
+                    prop:value=passphrase
+                    on:input=move |ev| {
+                        set_passphrase.set(event_target_value(&ev));
+                    }
+                />
+                <button
+                    on:click=unlock
+                    class="w-full flex justify-center items-center px-4 py-2 border border-transparent text-base font-medium rounded-lg text-white bg-blue-600 hover:bg-blue-700 focus:outline-none focus:ring-2 focus:ring-offset-2 focus:ring-blue-500 dark:focus:ring-offset-gray-900 transition-all duration-200 shadow-lg"
+                >
+                    Unlock
+                </button>
+                <p class="text-sm text-red-500">{move || error.get()}</p>
+            </div>
+        </div>
+    }
+}
+
+/// Lists the keyhive identities with access to the room and lets the user add members, change their role or
+/// revoke them.  Members are added by the contact card they share, this node's own card is shown to share it.
+#[component]
+pub fn AccessSettings(room: ReadSignal<Option<String>>) -> impl IntoView {
+    // signal holding the identities with access to the room
+    let (grants, set_grants) = signal(Vec::<api::AccessGrant>::new());
+    // signal to present this node's contact card
+    let (contact_card, set_contact_card) = signal(String::new());
+    // signals to manage the inputs of a new member
+    let (new_member, set_new_member) = signal(String::new());
+    let (new_role, set_new_role) = signal(api::Role::Writer);
+    // signal to present the outcome of access changes
+    let (status, set_status) = signal(String::new());
+
+    spawn_local(async move {
+        match api::ui::get_contact_card().await {
+            Ok(card) => set_contact_card.set(card),
+            Err(e) => log!("Failed to get contact card: {}", e),
+        }
+    });
+
+    Effect::new(move |_| {
+        let Some(room) = room.get() else {
+            return;
+        };
+        spawn_local(async move {
+            match api::ui::list_access(room).await {
+                Ok(list) => set_grants.set(list),
+                Err(e) => set_status.set(e),
+            }
+        });
+    });
+
+    spawn_local(async move {
+        let mut access_updates = events::ui::membership_changed::listen()
+            .await
+            .expect("there should be a valid membership update incoming");
+        while let Some(update) = access_updates.next().await {
+            if room.get_untracked().as_ref() == Some(&update.payload.room) {
+                set_grants.set(update.payload.grants);
+            }
+        }
+    });
+
+    // changes report errors on the status line, the list updates through "membership_changed" events
+    let report = move |result: Result<(), String>, done: &str| match result {
+        Ok(()) => set_status.set(done.to_string()),
+        Err(e) => set_status.set(e),
+    };
+
+    let add = move |_ev| {
+        let Some(room) = room.get_untracked() else {
+            return;
+        };
+        let contact_card = new_member.get_untracked();
+        let role = new_role.get_untracked();
+        spawn_local(async move {
+            let result = api::ui::add_member(room, contact_card, role).await;
+            if result.is_ok() {
+                set_new_member.set(String::new());
+            }
+            report(result, "Member added");
+        });
+    };
+
+    let grant = move |entry: api::AccessGrant| {
+        let short: String = entry.member.chars().take(8).collect();
+        let label = match entry.this_node {
+            true => format!("{short} (you)"),
+            false => short,
+        };
+        // this node's own access is not managed from here
+        let controls = (!entry.this_node).then(|| {
+            let member = entry.member.clone();
+            let on_role = move |ev| {
+                let (Some(room), Some(role)) =
+                    (room.get_untracked(), parse_role(&event_target_value(&ev)))
+                else {
+                    return;
+                };
+                let member = member.clone();
+                spawn_local(async move {
+                    report(
+                        api::ui::set_member_role(room, member, role).await,
+                        "Role changed",
+                    );
+                });
+            };
+            let member = entry.member.clone();
+            let on_remove = move |_| {
+                let Some(room) = room.get_untracked() else {
+                    return;
+                };
+                let member = member.clone();
+                spawn_local(async move {
+                    report(
+                        api::ui::remove_member(room, member).await,
+                        "Member removed",
+                    );
+                });
+            };
+            view! {
+                <select
+                    class="px-2 py-1 border border-gray-300 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-xs text-gray-900 dark:text-white"
+                    on:change=on_role
+                >
+                    {role_options(entry.role)}
+                </select>
+                <button
+                    on:click=on_remove
+                    class="px-2 py-1 text-xs font-medium rounded-lg text-white bg-red-600 hover:bg-red-700 transition-colors duration-200"
+                >
+                    Remove
+                </button>
+            }
+        });
+        view! {
+            <li class="flex items-center justify-between space-x-2 text-sm text-gray-700 dark:text-gray-300">
+                <span class="font-mono" title=entry.member.clone()>
+                    {label}
+                </span>
+                <div class="flex items-center space-x-2">
+                    <span class="text-xs text-gray-500 dark:text-gray-400">
+                        {role_name(entry.role)}
+                    </span>
+                    {controls}
+                </div>
+            </li>
+        }
+    };
+
+    view! {
+        <div class="space-y-2 pt-2 mt-2 border-t border-gray-200 dark:border-gray-700">
+            <ul class="space-y-1">
+                <For each=move || grants.get() key=|entry| entry.clone() children=grant />
+            </ul>
+            <div class="flex space-x-2">
+                <input
+                    type="text"
+                    class="flex-1 px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white text-sm"
+                    placeholder="Contact card of the new member"
+                    prop:value=new_member
+                    on:input=move |ev| {
+                        set_new_member.set(event_target_value(&ev));
+                    }
+                />
+                <select
+                    class="px-2 py-1 border border-gray-300 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-sm text-gray-900 dark:text-white"
+                    on:change=move |ev| {
+                        if let Some(role) = parse_role(&event_target_value(&ev)) {
+                            set_new_role.set(role);
+                        }
+                    }
+                >
+                    {role_options(api::Role::Writer)}
+                </select>
+                <button
+                    on:click=add
+                    class="px-4 py-2 text-sm font-medium rounded-lg text-white bg-blue-600 hover:bg-blue-700 transition-colors duration-200"
+                >
+                    Add
+                </button>
+            </div>
+            <p class="text-xs text-gray-600 dark:text-gray-400 break-all">
+                "Your contact card: " <span class="font-mono select-all">{move || contact_card.get()}</span>
+            </p>
+            <p class="text-sm text-gray-600 dark:text-gray-400">{move || status.get()}</p>
+        </div>
+    }
+}
+
+/// Parses the limits of an invite from the inputs of the connect screen, empty inputs lift the respective limit.
+fn invite_limits(
+    hours: &str,
+    uses: &str,
+    invitee: &str,
+) -> Result<(Option<DateTime<Utc>>, Option<u32>, Option<String>), String> {
+    let expires_at = match hours.trim() {
+        "" => None,
+        hours => {
+            let hours = hours
+                .parse()
+                .map_err(|_| "Validity must be a number of hours".to_string())?;
+            let validity = TimeDelta::try_hours(hours).ok_or("Validity is too long".to_string())?;
+            Some(Utc::now() + validity)
+        }
+    };
+    let max_uses = match uses.trim() {
+        "" => None,
+        uses => Some(
+            uses.parse()
+                .map_err(|_| "Uses must be a number".to_string())?,
+        ),
+    };
+    let invitee = Some(invitee.trim().to_string()).filter(|invitee| !invitee.is_empty());
+    Ok((expires_at, max_uses, invitee))
+}
+
+/// Lists the invites issued for a room with how often they were used, and lets the user revoke them.  Reloaded
+/// whenever the room changes or a new ticket is shown.
+#[component]
+pub fn Invites(room: ReadSignal<Option<String>>, ticket: ReadSignal<String>) -> impl IntoView {
+    // signal holding the invites issued for the room
+    let (invites, set_invites) = signal(Vec::<api::Invite>::new());
+
+    let load = move |room: String| {
+        spawn_local(async move {
+            match api::ui::list_invites(room).await {
+                Ok(list) => set_invites.set(list),
+                Err(e) => log!("Failed to list invites: {}", e),
+            }
+        });
+    };
+
+    Effect::new(move |_| {
+        ticket.track();
+        match room.get() {
+            Some(room) => load(room),
+            None => set_invites.set(vec![]),
+        }
+    });
+
+    let invite = move |invite: api::Invite| {
+        let uses = match invite.max_uses {
+            Some(max_uses) => format!("{}/{} used", invite.redeemed_by.len(), max_uses),
+            None => format!("{} used", invite.redeemed_by.len()),
+        };
+        let validity = match (invite.revoked, invite.expires_at) {
+            (true, _) => "revoked".to_string(),
+            (false, Some(expires_at)) if expires_at <= Utc::now() => "expired".to_string(),
+            (false, Some(expires_at)) => format!("until {}", expires_at.format("%Y-%m-%d %H:%M")),
+            (false, None) => "no expiry".to_string(),
+        };
+        let invitee = invite
+            .invitee
+            .map(|invitee| format!(", for {}", invitee.chars().take(8).collect::<String>()))
+            .unwrap_or_default();
+        let revoke = (!invite.revoked).then(|| {
+            let id = invite.id.clone();
+            let on_revoke = move |_| {
+                let id = id.clone();
+                spawn_local(async move {
+                    match api::ui::revoke_invite(id).await {
+                        Ok(()) => {
+                            if let Some(room) = room.get_untracked() {
+                                load(room);
+                            }
+                        }
+                        Err(e) => log!("Failed to revoke invite: {}", e),
+                    }
+                });
+            };
+            view! {
+                <button
+                    on:click=on_revoke
+                    class="px-2 py-1 text-xs font-medium rounded-lg text-white bg-red-600 hover:bg-red-700 transition-colors duration-200"
+                >
+                    Revoke
+                </button>
+            }
+        });
+        view! {
+            <li class="flex items-center justify-between space-x-2 text-xs text-gray-600 dark:text-gray-400">
+                <span>{format!("{uses}, {validity}{invitee}")}</span>
+                {revoke}
+            </li>
+        }
+    };
+
+    view! {
+        <ul class="space-y-1">
+            <For each=move || invites.get() key=|invite| invite.clone() children=invite />
+        </ul>
+    }
+}
+
+/// Lets the user set the display name, avatar and status text peers see instead of this node's id.
+#[component]
+pub fn ProfileSettings() -> impl IntoView {
+    // signals to manage the inputs of the profile
+    let (display_name, set_display_name) = signal(String::new());
+    let (profile_status, set_profile_status) = signal(String::new());
+    let (avatar, set_avatar) = signal(None::<api::Attachment>);
+    // signal to handle the path of the image picked as avatar
+    let (avatar_path, set_avatar_path) = signal(String::new());
+    // signal to present the outcome of profile changes
+    let (status, set_status) = signal(String::new());
+
+    spawn_local(async move {
+        match api::ui::get_profile().await {
+            Ok(profile) => {
+                set_display_name.set(profile.display_name);
+                set_profile_status.set(profile.status);
+                set_avatar.set(profile.avatar);
+            }
+            // the node may still be starting up
+            Err(e) => log!("Failed to get profile: {}", e),
+        }
+    });
+
+    // the image is added to the blob store, peers fetch it from there once the profile is published
+    let pick_avatar = move |_ev| {
+        let path = avatar_path.get().trim().to_string();
+        if path.is_empty() {
+            set_status.set("Enter the path of the avatar image".into());
+            return;
+        }
+        spawn_local(async move {
+            match api::ui::add_attachment(path).await {
+                Ok(image)
+                    if image.mime.starts_with("image/")
+                        && image.size <= api::MAX_INLINE_IMAGE_SIZE =>
+                {
+                    set_avatar.set(Some(image));
+                    set_avatar_path.set(String::new());
+                    set_status.set("Save the profile to publish the avatar".into());
+                }
+                Ok(_) => set_status.set(format!(
+                    "The avatar must be an image of at most {}",
+                    file_size(api::MAX_INLINE_IMAGE_SIZE)
+                )),
+                Err(e) => set_status.set(format!("Failed to add the avatar: {}", e)),
+            }
+        });
+    };
+
+    let save_profile = move |_ev| {
+        let display_name = display_name.get().trim().to_string();
+        let profile_status = profile_status.get().trim().to_string();
+        let avatar = avatar.get();
+        spawn_local(async move {
+            match api::ui::set_profile(display_name, avatar, profile_status).await {
+                Ok(()) => set_status.set("Profile published".into()),
+                Err(e) => set_status.set(e),
+            }
+        });
+    };
+
+    view! {
+        <div class="space-y-2 pt-4 border-t border-gray-300 dark:border-gray-600">
+            <input
+                type="text"
+                class="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                placeholder="Display name"
+                prop:value=display_name
+                on:input=move |ev| {
+                    set_display_name.set(event_target_value(&ev));
+                }
+            />
+            <input
+                type="text"
+                class="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                placeholder="Status (optional)"
+                prop:value=profile_status
+                on:input=move |ev| {
+                    set_profile_status.set(event_target_value(&ev));
+                }
+            />
+            <div class="flex items-center space-x-2">
+                // the avatar is stored locally, so no room is needed to show it
+                {move || {
+                    let profile = api::Profile {
+                        display_name: display_name.get(),
+                        avatar: avatar.get(),
+                        ..Default::default()
+                    };
+                    view! {
+                        <Avatar room=String::new() peer=String::new() profile=Some(profile) class="w-8 h-8 text-sm" />
+                    }
+                }}
+                <input
+                    type="text"
+                    class="flex-1 min-w-0 px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                    placeholder="Avatar image path..."
+                    prop:value=avatar_path
+                    on:input=move |ev| set_avatar_path.set(event_target_value(&ev))
+                />
+                <button
+                    on:click=pick_avatar
+                    class="px-3 py-2 text-sm rounded-lg bg-gray-200 dark:bg-gray-600 text-gray-900 dark:text-white hover:bg-gray-300 dark:hover:bg-gray-500"
+                >
+                    Set
+                </button>
+                <Show when=move || avatar.with(Option::is_some)>
+                    <button
+                        on:click=move |_| set_avatar.set(None)
+                        class="px-3 py-2 text-sm rounded-lg bg-gray-200 dark:bg-gray-600 text-gray-900 dark:text-white hover:bg-gray-300 dark:hover:bg-gray-500"
+                    >
+                        Remove
+                    </button>
+                </Show>
+            </div>
+            <button
+                on:click=save_profile
+                class="w-full px-4 py-2 text-sm font-medium rounded-lg text-white bg-blue-600 hover:bg-blue-700 transition-colors duration-200"
+            >
+                Save Profile
+            </button>
+            <p class="text-sm text-gray-600 dark:text-gray-400">{move || status.get()}</p>
+        </div>
+    }
+}
+
+/// Shows this node's fingerprint and lets the user protect or rotate the persisted identity.
+#[component]
+pub fn IdentitySettings() -> impl IntoView {
+    // signal to present the fingerprint of this node's id
+    let (fingerprint, set_fingerprint) = signal(String::new());
+    // signal to manage the input of a new passphrase, an empty passphrase stores the identity unencrypted
+    let (passphrase, set_passphrase) = signal(String::new());
+    // signal to present the outcome of identity changes
+    let (status, set_status) = signal(String::new());
+
+    let load_fingerprint = move || {
+        spawn_local(async move {
+            match api::ui::get_node_fingerprint().await {
+                Ok(fingerprint) => set_fingerprint.set(fingerprint),
+                // the node may still be starting up
+                Err(e) => log!("Failed to get node fingerprint: {}", e),
+            }
+        });
+    };
+    load_fingerprint();
+
+    let passphrase_option = move || Some(passphrase.get()).filter(|p| !p.is_empty());
+
+    let save_passphrase = move |_ev| {
+        let passphrase = passphrase_option();
+        spawn_local(async move {
+            match api::ui::set_identity_passphrase(passphrase.clone()).await {
+                Ok(()) if passphrase.is_some() => {
+                    set_status.set("Identity is passphrase protected".into())
+                }
+                Ok(()) => set_status.set("Identity is stored unencrypted".into()),
+                Err(e) => set_status.set(e),
+            }
+        });
+    };
+
+    let rotate = move |_ev| {
+        let passphrase = passphrase_option();
+        spawn_local(async move {
+            // the app restarts with the new identity on success
+            if let Err(e) = api::ui::rotate_identity(passphrase).await {
+                set_status.set(e);
+            }
+        });
+    };
+
+    view! {
+        <div class="space-y-2 pt-4 border-t border-gray-300 dark:border-gray-600">
+            <p class="text-sm text-gray-600 dark:text-gray-400">
+                "Node fingerprint: "
+                <span class="font-mono" on:click=move |_| load_fingerprint()>
+                    {move || fingerprint.get()}
+                </span>
+            </p>
+            <input
+                type="password"
+                class="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                placeholder="Identity passphrase (optional)"
+                prop:value=passphrase
+                on:input=move |ev| {
+                    set_passphrase.set(event_target_value(&ev));
+                }
+            />
+            <div class="flex space-x-2">
+                <button
+                    on:click=save_passphrase
+                    class="flex-1 px-4 py-2 text-sm font-medium rounded-lg text-white bg-gray-600 hover:bg-gray-700 transition-colors duration-200"
+                >
+                    Set Passphrase
+                </button>
+                <button
+                    on:click=rotate
+                    class="flex-1 px-4 py-2 text-sm font-medium rounded-lg text-white bg-red-600 hover:bg-red-700 transition-colors duration-200"
+                >
+                    Rotate Identity
+                </button>
+            </div>
+            <p class="text-sm text-gray-600 dark:text-gray-400">{move || status.get()}</p>
+        </div>
+    }
+}
+
 #[component]
 pub fn App() -> impl IntoView {
     // todo: implement proper error handling across the app.
@@ -209,74 +1981,244 @@ pub fn App() -> impl IntoView {
     let (this_nodes_ticket, set_this_nodes_ticket) = signal(String::new());
     // signal to build and present the qr code for the node ticket
     let (this_nodes_ticket_qr, set_this_nodes_ticket_qr) = signal(String::new());
+    // signal holding the compact forms of the node ticket to share it outside the app
+    let (invite_codes, set_invite_codes) = signal(None::<api::InviteCodes>);
     // signal to set a connection message indicated what Document we just connected to (used mostly for debugging at this time)
     let (connection_msg, set_connection_msg) = signal(String::new());
     // signal to manage the input of a connection event into a text area
     let (connection_ticket, set_connection_ticket) = signal(String::new());
+    // signal holding what the entered ticket invites into, the node only dials once the user confirms it
+    let (preview, set_preview) = signal(None::<api::TicketPreview>);
     // signal to indicate we have connected to a chat session and will cause a switch to the chat screen
     let (is_connected, set_is_connected) = signal(false);
-    // signal to set the connection type on the chat screen (direct, mixed, etc.)
-    let (connection_type, set_connection_type) = signal(String::new());
+    // signal holding the connection to every peer of every room, keyed by room id and then by peer node id
+    let (connections, set_connections) =
+        signal(HashMap::<String, BTreeMap<String, PeerConnection>>::new());
+    // signal holding the documents (rooms) this node takes part in
+    let (rooms, set_rooms) = signal(Vec::<api::RoomInfo>::new());
+    // signal holding the room the next ticket invites into, a new room is created when `None`
+    let (invite_room, set_invite_room) = signal(None::<String>);
+    // signal to manage the input of the title of a new room
+    let (room_title, set_room_title) = signal(String::new());
+    // signals to manage the limits of the next invite into an existing room, empty inputs lift the limit
+    let (invite_hours, set_invite_hours) = signal("24".to_string());
+    let (invite_uses, set_invite_uses) = signal("1".to_string());
+    let (invitee, set_invitee) = signal(String::new());
+    // signal holding the id of the room shown in the chat, once one is discovered
+    let (room, set_room) = signal(None::<String>);
+    // the peers of the room shown in the chat
+    let peers = Signal::derive(move || {
+        room.get()
+            .and_then(|room| connections.get().get(&room).cloned())
+            .map(|peers| peers.into_iter().collect())
+            .unwrap_or_default()
+    });
+
+    let refresh_rooms = move || async move {
+        match api::ui::list_rooms().await {
+            Ok(list) => set_rooms.set(list),
+            Err(e) => log!("Failed to list rooms: {}", e),
+        }
+    };
+
+    // signal to indicate the stored identity is passphrase protected and must be unlocked before the node starts
+    let (locked, set_locked) = signal(false);
+
+    spawn_local(async move {
+        match api::ui::is_identity_locked().await {
+            Ok(is_locked) => set_locked.set(is_locked),
+            Err(e) => log!("Failed to check the identity lock: {}", e),
+        }
+    });
 
     spawn_local(async move {
-        // todo: migrate to once listen once implemented in the macro for ipc layer
-        // once we receive the connection event, we can set the connected state to true and stop listening further
-        let connection_events = events::ui::connection::listen()
+        // every room a peer connects in is a room we take part in, switch the chat to rooms as they are joined
+        let mut connection_events = events::ui::connection::listen()
             .await
             .expect("there should be a valid connection event");
-        let (mut events, abort_handle) = futures::stream::abortable(connection_events);
-        while let Some(msg) = events.next().await {
-            if msg.payload == "connected" {
-                set_is_connected.set(true);
-                break;
+        while let Some(msg) = connection_events.next().await {
+            let api::PeerStatus { room, peer, status } = msg.payload;
+            set_connections.update(|connections| {
+                connections
+                    .entry(room.clone())
+                    .or_default()
+                    .entry(peer)
+                    .or_default()
+                    .status = Some(status);
+            });
+            if status != api::ConnectionStatus::Connected {
+                continue;
+            }
+            let is_new = !rooms.get_untracked().iter().any(|info| info.id == room);
+            refresh_rooms().await;
+            if is_new {
+                set_room.set(Some(room));
             }
+            set_is_connected.set(true);
         }
-        abort_handle.abort();
     });
 
-    // spawned task to listen for and set the connection type for all send and receives of messages as they stream in and out.
+    // spawned task to listen for and set the path (direct, relay, mixed) of the connection to every peer as it changes.
     spawn_local(async move {
         let mut connection_updates = events::ui::connection_type::listen()
             .await
             .expect("there should be a valid connection update incoming");
         while let Some(msg) = connection_updates.next().await {
             log!("Received message: {:?}", msg);
-            let connection_type = msg.payload;
-            set_connection_type.set(connection_type);
+            let api::PeerPath { room, peer, path } = msg.payload;
+            set_connections.update(|connections| {
+                connections
+                    .entry(room)
+                    .or_default()
+                    .entry(peer)
+                    .or_default()
+                    .path = Some(path);
+            });
+        }
+    });
+
+    spawn_local(async move {
+        // the node we joined a room through refused the invite, back to the connect screen with the reason
+        let mut rejections = events::ui::invite_rejected::listen()
+            .await
+            .expect("there should be a valid invite rejection incoming");
+        while let Some(rejection) = rejections.next().await {
+            set_connection_msg.set(format!("Invite refused: {}", rejection.payload.reason));
+            set_is_connected.set(false);
         }
     });
 
     let display_ticket = move |_ev| {
         spawn_local(async move {
-            let ticket = api::ui::get_serialized_ticket()
-                .await
-                .expect("should produce a valid ticket");
-            set_this_nodes_ticket.set(ticket.clone());
-
-            // QRBuilder::new can fail if content is too big for version,
-            // please check before unwrapping.
-            // todo: implement safety checks.
-            // todo: there was an odd wasm out of bound memory access error form fast_qr that only happened once during testing.  Will need to investigate further.
-            let qrcode = QRBuilder::new(ticket).build().unwrap();
+            let ticket = match invite_room.get_untracked() {
+                Some(room) => match invite_limits(
+                    &invite_hours.get_untracked(),
+                    &invite_uses.get_untracked(),
+                    &invitee.get_untracked(),
+                ) {
+                    Ok((expires_at, max_uses, invitee)) => {
+                        api::ui::create_invite(room, expires_at, max_uses, invitee).await
+                    }
+                    Err(e) => Err(e),
+                },
+                None => api::ui::create_room(room_title.get_untracked()).await,
+            };
+            let ticket = match ticket {
+                Ok(ticket) => ticket,
+                Err(e) => {
+                    set_this_nodes_ticket.set(e);
+                    set_invite_codes.set(None);
+                    set_this_nodes_ticket_qr.set(String::new());
+                    return;
+                }
+            };
+            // a newly created room can be invited into again
+            refresh_rooms().await;
+            let codes = match api::ui::invite_codes(ticket.clone()).await {
+                Ok(codes) => codes,
+                Err(e) => {
+                    set_this_nodes_ticket.set(e);
+                    set_invite_codes.set(None);
+                    set_this_nodes_ticket_qr.set(String::new());
+                    return;
+                }
+            };
+            set_this_nodes_ticket.set(ticket);
 
-            let svg = SvgBuilder::default()
-                .shape(Shape::RoundedSquare)
-                .to_str(&qrcode);
+            // the compact code keeps the QR code small, it still fails for codes beyond the largest QR version
+            // todo: there was an odd wasm out of bound memory access error form fast_qr that only happened once during testing.  Will need to investigate further.
+            let svg = match QRBuilder::new(codes.code.clone()).build() {
+                Ok(qrcode) => SvgBuilder::default()
+                    .shape(Shape::RoundedSquare)
+                    .to_str(&qrcode),
+                Err(e) => {
+                    log!("Failed to build the QR code: {:?}", e);
+                    String::new()
+                }
+            };
             set_this_nodes_ticket_qr.set(svg);
+            set_invite_codes.set(Some(codes));
+        });
+    };
+
+    let inspect = move |_ev| {
+        let ticket_value = connection_ticket.get();
+        spawn_local(async move {
+            match api::ui::inspect_ticket(ticket_value).await {
+                Ok(ticket_preview) => {
+                    set_connection_msg.set(String::new());
+                    set_preview.set(Some(ticket_preview));
+                }
+                Err(e) => {
+                    set_preview.set(None);
+                    set_connection_msg.set(format!("Invalid ticket: {}", e));
+                }
+            }
         });
     };
 
     let connect = move |_ev| {
         let ticket_value = connection_ticket.get();
+        set_preview.set(None);
         spawn_local(async move {
-            let new_msg = api::ui::connect_via_serialized_ticket(ticket_value)
-                .await
-                .expect("should consume a valid ticket");
-            set_connection_msg.set(new_msg);
-            set_is_connected.set(true); // Set connected state
+            match api::ui::connect_via_serialized_ticket(ticket_value).await {
+                Ok(new_msg) => {
+                    set_connection_msg.set(new_msg);
+                    set_is_connected.set(true); // Set connected state
+                }
+                Err(e) => set_connection_msg.set(format!("Failed to connect: {}", e)),
+            }
         });
     };
 
+    let ticket_preview = move || {
+        preview.get().map(|ticket_preview| {
+            let title = ticket_preview
+                .title
+                .unwrap_or_else(|| "Untitled room".to_string());
+            let relay = match ticket_preview.relay {
+                Some(relay) => format!("via relay {}", relay),
+                None => format!(
+                    "direct only ({} addresses)",
+                    ticket_preview.direct_addresses.len()
+                ),
+            };
+            let validity = match ticket_preview.expires_at {
+                Some(expires_at) => {
+                    format!("invite valid until {}", expires_at.format("%Y-%m-%d %H:%M"))
+                }
+                None => "invite without expiry".to_string(),
+            };
+            view! {
+                <div class="p-3 space-y-2 border border-gray-300 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-800">
+                    <p class="text-sm font-medium text-gray-900 dark:text-white break-all">
+                        {title}
+                    </p>
+                    <p class="text-xs text-gray-600 dark:text-gray-400">
+                        "Invited by "
+                        <span class="font-mono">{ticket_preview.inviter_fingerprint}</span>
+                    </p>
+                    <p class="text-xs text-gray-600 dark:text-gray-400 break-all">{relay}</p>
+                    <p class="text-xs text-gray-600 dark:text-gray-400">{validity}</p>
+                    <div class="flex space-x-2">
+                        <button
+                            on:click=connect
+                            class="flex-1 px-3 py-2 text-sm font-medium rounded-lg text-white bg-green-600 hover:bg-green-700 transition-colors duration-200"
+                        >
+                            Join
+                        </button>
+                        <button
+                            on:click=move |_| set_preview.set(None)
+                            class="flex-1 px-3 py-2 text-sm font-medium rounded-lg text-gray-700 dark:text-gray-300 bg-gray-200 dark:bg-gray-700 hover:bg-gray-300 dark:hover:bg-gray-600 transition-colors duration-200"
+                        >
+                            Cancel
+                        </button>
+                    </div>
+                </div>
+            }
+        })
+    };
+
     let scan_qr_code = view! {
         {move || {
             #[cfg(feature = "mobile")]
@@ -357,8 +2299,20 @@ pub fn App() -> impl IntoView {
         </button>
 
         {move || {
-            if is_connected.get() {
-                view! { <Chat connection_type=connection_type /> }.into_any()
+            if locked.get() {
+                view! { <UnlockIdentity set_locked=set_locked /> }.into_any()
+            } else if is_connected.get() {
+                view! {
+                    <Chat
+                        peers=peers
+                        room=room
+                        set_room=set_room
+                        rooms=rooms
+                        set_rooms=set_rooms
+                        set_is_connected=set_is_connected
+                    />
+                }
+                    .into_any()
             } else {
                 view! {
                     <div class="h-full flex items-center justify-center p-6">
@@ -373,7 +2327,65 @@ pub fn App() -> impl IntoView {
                                 </p>
                             </div>
 
-                            <div class="text-center">
+                            <div class="text-center space-y-4">
+                                <select
+                                    class="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                                    on:change=move |ev| {
+                                        let value = event_target_value(&ev);
+                                        set_invite_room.set(Some(value).filter(|room| !room.is_empty()));
+                                    }
+                                >
+                                    <option value="">"New room"</option>
+                                    <For
+                                        each=move || rooms.get()
+                                        key=|room| room.id.clone()
+                                        children=move |room| {
+                                            view! { <option value=room.id.clone()>{room_label(&room)}</option> }
+                                        }
+                                    />
+                                </select>
+                                <Show when=move || invite_room.get().is_some()>
+                                    <div class="flex space-x-2">
+                                        <input
+                                            type="number"
+                                            min="1"
+                                            class="w-1/2 px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                                            placeholder="Valid for hours (no expiry)"
+                                            prop:value=invite_hours
+                                            on:input=move |ev| {
+                                                set_invite_hours.set(event_target_value(&ev));
+                                            }
+                                        />
+                                        <input
+                                            type="number"
+                                            min="1"
+                                            class="w-1/2 px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                                            placeholder="Uses (unlimited)"
+                                            prop:value=invite_uses
+                                            on:input=move |ev| {
+                                                set_invite_uses.set(event_target_value(&ev));
+                                            }
+                                        />
+                                    </div>
+                                    <input
+                                        class="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                                        placeholder="Invitee node id (anyone)"
+                                        prop:value=invitee
+                                        on:input=move |ev| {
+                                            set_invitee.set(event_target_value(&ev));
+                                        }
+                                    />
+                                </Show>
+                                <Show when=move || invite_room.get().is_none()>
+                                    <input
+                                        class="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white"
+                                        placeholder="Room title"
+                                        prop:value=room_title
+                                        on:input=move |ev| {
+                                            set_room_title.set(event_target_value(&ev));
+                                        }
+                                    />
+                                </Show>
                                 <button
                                     on:click=display_ticket
                                     class="inline-flex items-center px-6 py-3 border border-transparent text-base font-medium rounded-lg text-white bg-blue-600 hover:bg-blue-700 focus:outline-none focus:ring-2 focus:ring-offset-2 focus:ring-blue-500 dark:focus:ring-offset-gray-900 transition-all duration-200 transform hover:scale-105 shadow-lg"
@@ -398,8 +2410,28 @@ pub fn App() -> impl IntoView {
                                     class="text-gray-600 dark:text-gray-400 text-sm font-mono"
                                     style="word-break: break-word; overflow-wrap: break-word; hyphens: auto; max-width: 100%; white-space: normal;"
                                 >
-                                    {move || this_nodes_ticket.get()}
+                                    {move || {
+                                        invite_codes
+                                            .get()
+                                            .map(|codes| codes.code)
+                                            .unwrap_or_else(|| this_nodes_ticket.get())
+                                    }}
                                 </p>
+                                {move || {
+                                    invite_codes
+                                        .get()
+                                        .map(|codes| {
+                                            view! {
+                                                <p class="text-gray-600 dark:text-gray-400 text-xs break-all">
+                                                    "Word code: "{codes.words}
+                                                </p>
+                                                <p class="text-gray-600 dark:text-gray-400 text-xs font-mono break-all">
+                                                    {codes.link}
+                                                </p>
+                                            }
+                                        })
+                                }}
+                                <Invites room=invite_room ticket=this_nodes_ticket />
                             </div>
 
                             <div class="relative">
@@ -425,15 +2457,16 @@ pub fn App() -> impl IntoView {
                                         id="connection-ticket"
                                         rows="3"
                                         class="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg shadow-sm placeholder-gray-400 dark:placeholder-gray-500 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent bg-white dark:bg-gray-800 text-gray-900 dark:text-white resize-none transition-all duration-200"
-                                        placeholder="Paste your connection ticket here..."
+                                        placeholder="Paste a ticket, invite code, word code or beelay-chat:// link..."
                                         prop:value=connection_ticket
                                         on:input=move |ev| {
                                             set_connection_ticket.set(event_target_value(&ev));
+                                            set_preview.set(None);
                                         }
                                     ></textarea>
                                 </div>
                                 <button
-                                    on:click=connect
+                                    on:click=inspect
                                     class="w-full flex justify-center items-center px-4 py-2 border border-transparent text-base font-medium rounded-lg text-white bg-green-600 hover:bg-green-700 focus:outline-none focus:ring-2 focus:ring-offset-2 focus:ring-green-500 dark:focus:ring-offset-gray-900 transition-all duration-200 disabled:opacity-50 disabled:cursor-not-allowed shadow-lg"
                                 >
                                     <svg
@@ -449,8 +2482,9 @@ pub fn App() -> impl IntoView {
                                             d="M13 10V3L4 14h7v7l9-11h-7z"
                                         ></path>
                                     </svg>
-                                    Connect & Start Chat
+                                    Check Ticket
                                 </button>
+                                {ticket_preview}
                                 <p
                                     class="text-gray-600 dark:text-gray-400 text-sm font-mono"
                                     style="word-break: break-word; overflow-wrap: break-word; hyphens: auto; max-width: 100%; white-space: normal;"
@@ -458,6 +2492,9 @@ pub fn App() -> impl IntoView {
                                     {move || connection_msg.get()}
                                 </p>
                             </div>
+
+                            <ProfileSettings />
+                            <IdentitySettings />
                         </div>
                     </div>
                 }